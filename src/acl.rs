@@ -1,6 +1,8 @@
 use crate::client::Client;
 use crate::error::Result;
 use crate::models::*;
+use crate::paging::paged;
+use futures::stream::{Stream, TryStreamExt};
 use std::collections::HashMap;
 
 impl Client {
@@ -28,6 +30,49 @@ impl Client {
         self.get_with_query("/commercial/record/export", &params).await
     }
 
+    /// Get a page of ACL logs (GET /open/records/acl)
+    ///
+    /// Retrieves one page of ACL logs, i.e. the IPs banned or challenged by
+    /// ACL rules, newest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `page` - Page number, starting at 1
+    /// * `page_size` - Number of logs per page
+    ///
+    /// # Returns
+    ///
+    /// Returns the page of ACL logs and the total count
+    pub async fn get_acl_log_page(&self, page: i32, page_size: i32) -> Result<ACLLogsResponse> {
+        let mut params = HashMap::new();
+        params.insert("page".to_string(), page.to_string());
+        params.insert("page_size".to_string(), page_size.to_string());
+        self.get_with_query("/open/records/acl", &params).await
+    }
+
+    /// Stream all ACL logs
+    ///
+    /// Fetches pages of 100 lazily until a short page or `total` is reached.
+    /// A failed page is yielded as an `Err` item and ends the stream.
+    pub fn acl_logs(&self) -> impl Stream<Item = Result<ACLLog>> + '_ {
+        const PAGE_SIZE: i32 = 100;
+        paged(1, PAGE_SIZE, move |page| async move {
+            let response = self.get_acl_log_page(page, PAGE_SIZE).await?;
+            Ok((response.data, response.total))
+        })
+    }
+
+    /// Get all ACL logs
+    ///
+    /// Pages through `get_acl_log_page` until every log is fetched.
+    ///
+    /// # Returns
+    ///
+    /// Returns all ACL logs
+    pub async fn all_acl_logs(&self) -> Result<Vec<ACLLog>> {
+        self.acl_logs().try_collect().await
+    }
+
     /// ACL relieve (PUT /open/acl/relieve)
     ///
     /// Relieves ACL blocking.
@@ -0,0 +1,371 @@
+//! ACL ban analytics and allowlist-driven auto-relief
+//!
+//! Summarizes the bans returned by `all_acl_logs` by IP, site, reason and
+//! country,
+//! finds IPs that keep getting banned, and relieves bans that hit trusted
+//! networks such as an office NAT or monitoring probes.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use crate::policy::eval::Cidr;
+use crate::time::{parse_timestamp, unix_now};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Whether a ban is still in force; bans without a readable expiry are
+/// treated as active
+pub fn is_active(log: &ACLLog, now: i64) -> bool {
+    match log.valid_before.as_deref().and_then(parse_timestamp) {
+        Some(valid_before) => valid_before > now,
+        None => true,
+    }
+}
+
+fn site_name(log: &ACLLog) -> String {
+    log.site_title
+        .clone()
+        .filter(|t| !t.is_empty())
+        .or_else(|| log.site_server_names.as_ref().and_then(|n| n.first()).cloned())
+        .unwrap_or_else(|| format!("site {}", log.site_id))
+}
+
+/// Active bans grouped by IP, site, reason and country
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BanSummary {
+    /// All bans in the logs
+    pub total: usize,
+    pub active: usize,
+    pub by_ip: BTreeMap<String, usize>,
+    pub by_site: BTreeMap<String, usize>,
+    pub by_reason: BTreeMap<String, usize>,
+    /// Bans without a country are counted as `unknown`
+    pub by_country: BTreeMap<String, usize>,
+}
+
+impl BanSummary {
+    pub fn new(logs: &[ACLLog], now: i64) -> Self {
+        let mut summary = Self {
+            total: logs.len(),
+            ..Default::default()
+        };
+        for log in logs.iter().filter(|log| is_active(log, now)) {
+            summary.active += 1;
+            *summary.by_ip.entry(log.ip.clone()).or_insert(0) += 1;
+            *summary.by_site.entry(site_name(log)).or_insert(0) += 1;
+            *summary.by_reason.entry(log.reason.clone()).or_insert(0) += 1;
+            let country = log.country.clone().filter(|c| !c.is_empty());
+            *summary.by_country.entry(country.unwrap_or_else(|| "unknown".to_string())).or_insert(0) += 1;
+        }
+        summary
+    }
+
+    /// IPs with the most active bans, most first
+    pub fn top_ips(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut ips: Vec<_> = self.by_ip.iter().map(|(ip, count)| (ip.as_str(), *count)).collect();
+        ips.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        ips.truncate(n);
+        ips
+    }
+}
+
+impl fmt::Display for BanSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (title, groups) in [("site", &self.by_site), ("reason", &self.by_reason), ("country", &self.by_country)] {
+            for (key, count) in groups {
+                writeln!(f, "{} {}: {}", title, key, count)?;
+            }
+        }
+        for (ip, count) in self.top_ips(10) {
+            writeln!(f, "ip {}: {}", ip, count)?;
+        }
+        write!(f, "{} active of {} bans", self.active, self.total)
+    }
+}
+
+/// An IP banned several times
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepeatOffender {
+    pub ip: String,
+    pub bans: usize,
+    pub sites: BTreeSet<i32>,
+    pub reasons: BTreeSet<String>,
+    /// Requests denied over all bans
+    pub denied: i64,
+    pub first_banned: Option<String>,
+    pub last_banned: Option<String>,
+}
+
+/// IPs with at least `min_bans` bans, most bans first
+pub fn repeat_offenders(logs: &[ACLLog], min_bans: usize) -> Vec<RepeatOffender> {
+    let mut by_ip: BTreeMap<&str, RepeatOffender> = BTreeMap::new();
+    for log in logs {
+        let offender = by_ip.entry(&log.ip).or_insert_with(|| RepeatOffender {
+            ip: log.ip.clone(),
+            bans: 0,
+            sites: BTreeSet::new(),
+            reasons: BTreeSet::new(),
+            denied: 0,
+            first_banned: None,
+            last_banned: None,
+        });
+        offender.bans += 1;
+        offender.sites.insert(log.site_id);
+        offender.reasons.insert(log.reason.clone());
+        offender.denied += log.denied_count as i64;
+        if let Some(at) = &log.created_at {
+            let key = |v: &Option<String>| v.as_deref().and_then(parse_timestamp);
+            let time = parse_timestamp(at);
+            if offender.first_banned.is_none() || time < key(&offender.first_banned) {
+                offender.first_banned = Some(at.clone());
+            }
+            if offender.last_banned.is_none() || time > key(&offender.last_banned) {
+                offender.last_banned = Some(at.clone());
+            }
+        }
+    }
+
+    let mut offenders: Vec<_> = by_ip.into_values().filter(|o| o.bans >= min_bans).collect();
+    offenders.sort_by(|a, b| b.bans.cmp(&a.bans).then(b.denied.cmp(&a.denied)).then(a.ip.cmp(&b.ip)));
+    offenders
+}
+
+/// Trusted networks whose bans are relieved automatically
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Allowlist {
+    pub entries: Vec<(Cidr, String)>,
+}
+
+impl Allowlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust a network; the label shows up in relief logs
+    pub fn allow(mut self, cidr: &str, label: &str) -> Result<Self> {
+        self.entries.push((cidr.parse()?, label.to_string()));
+        Ok(self)
+    }
+
+    /// Parse one network per line with an optional `# label`; blank lines
+    /// are ignored
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut allowlist = Self::new();
+        for line in text.lines() {
+            let (cidr, label) = line.split_once('#').unwrap_or((line, ""));
+            if cidr.trim().is_empty() {
+                continue;
+            }
+            allowlist = allowlist.allow(cidr.trim(), label.trim())?;
+        }
+        Ok(allowlist)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Label of the first network containing `ip`
+    pub fn matches(&self, ip: &IpAddr) -> Option<&str> {
+        self.entries.iter().find(|(cidr, _)| cidr.contains(ip)).map(|(_, label)| label.as_str())
+    }
+}
+
+/// A ban to relieve
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relief {
+    pub log_id: i32,
+    pub ip: String,
+    pub site_id: i32,
+    pub reason: String,
+    /// Label of the allowlist entry the IP matched
+    pub label: String,
+}
+
+impl fmt::Display for Relief {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "relieve ban #{} of {} on site {} ({}): allowlisted as {:?}",
+            self.log_id, self.ip, self.site_id, self.reason, self.label
+        )
+    }
+}
+
+/// Active bans of allowlisted IPs
+pub fn plan_relief(logs: &[ACLLog], allowlist: &Allowlist, now: i64) -> Vec<Relief> {
+    logs.iter()
+        .filter(|log| is_active(log, now))
+        .filter_map(|log| {
+            let ip = log.ip.parse::<IpAddr>().ok()?;
+            let label = allowlist.matches(&ip)?;
+            Some(Relief {
+                log_id: log.id,
+                ip: log.ip.clone(),
+                site_id: log.site_id,
+                reason: log.reason.clone(),
+                label: label.to_string(),
+            })
+        })
+        .collect()
+}
+
+impl Client {
+    /// Summarize ACL bans
+    ///
+    /// # Returns
+    ///
+    /// Returns active bans grouped by IP, site, reason and country
+    pub async fn acl_ban_summary(&self) -> Result<BanSummary> {
+        let logs = self.all_acl_logs().await?;
+        Ok(BanSummary::new(&logs, unix_now()))
+    }
+
+    /// Find repeatedly banned IPs
+    ///
+    /// # Arguments
+    ///
+    /// * `min_bans` - Minimum number of bans
+    ///
+    /// # Returns
+    ///
+    /// Returns the offenders, most bans first
+    pub async fn acl_repeat_offenders(&self, min_bans: usize) -> Result<Vec<RepeatOffender>> {
+        let logs = self.all_acl_logs().await?;
+        Ok(repeat_offenders(&logs, min_bans))
+    }
+
+    /// Relieve bans of allowlisted IPs
+    ///
+    /// Fetches all ACL logs, and calls `acl_relieve` for every active ban
+    /// whose IP is in the allowlist. Each relief is logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `allowlist` - Trusted networks
+    /// * `dry_run` - Only log what would be relieved
+    ///
+    /// # Returns
+    ///
+    /// Returns the relieved (or, on a dry run, relievable) bans
+    pub async fn relieve_allowlisted_bans(&self, allowlist: &Allowlist, dry_run: bool) -> Result<Vec<Relief>> {
+        let logs = self.all_acl_logs().await?;
+        let reliefs = plan_relief(&logs, allowlist, unix_now());
+
+        for relief in &reliefs {
+            if dry_run {
+                info!("dry run: {}", relief);
+                continue;
+            }
+            self.acl_relieve(&ACLRelieveRequest {
+                id: vec![relief.log_id],
+                search: None,
+            })
+            .await
+            .map_err(|e| Error::StepFailed {
+                step: relief.to_string(),
+                source: Box::new(e),
+            })?;
+            info!("{}", relief);
+        }
+        Ok(reliefs)
+    }
+
+    /// Relieve allowlisted bans every `interval`
+    ///
+    /// Runs until the task is dropped; failed rounds are logged and retried
+    /// on the next tick.
+    pub async fn run_acl_auto_relief(&self, allowlist: &Allowlist, interval: Duration) -> Result<()> {
+        loop {
+            if let Err(e) = self.relieve_allowlisted_bans(allowlist, false).await {
+                warn!("ACL auto relief failed: {}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1704067200; // 2024-01-01T00:00:00Z
+
+    fn ban(id: i32, ip: &str, site_id: i32, reason: &str, valid_before: &str) -> ACLLog {
+        ACLLog {
+            id,
+            ip: ip.to_string(),
+            site_id,
+            action: "ban".to_string(),
+            result: String::new(),
+            status: 0,
+            period: 60,
+            count: 100,
+            denied_count: 10,
+            reason: reason.to_string(),
+            block_min: Some(10),
+            city: None,
+            country: Some("CN".to_string()),
+            province: None,
+            created_at: Some(format!("2023-12-{:02}T00:00:00Z", id)),
+            updated_at: None,
+            valid_before: Some(valid_before.to_string()),
+            site_comment: None,
+            site_icon: None,
+            site_server_names: Some(vec![format!("s{}.com", site_id)]),
+            site_title: None,
+        }
+    }
+
+    #[test]
+    fn test_summary_and_offenders() {
+        let mut logs = vec![
+            ban(1, "1.1.1.1", 1, "req", "2024-01-01T00:10:00Z"),
+            ban(2, "1.1.1.1", 2, "attack", "2024-01-01T00:10:00Z"),
+            ban(3, "2.2.2.2", 1, "req", "2023-12-31T23:00:00Z"),
+            ban(4, "1.1.1.1", 1, "req", "2023-12-31T23:00:00Z"),
+        ];
+        logs[1].country = None;
+
+        let summary = BanSummary::new(&logs, NOW);
+        assert_eq!((summary.total, summary.active), (4, 2));
+        assert_eq!(summary.by_ip["1.1.1.1"], 2);
+        assert_eq!(summary.by_site["s1.com"], 1);
+        assert_eq!(summary.by_reason["attack"], 1);
+        assert_eq!(summary.by_country["CN"], 1);
+        assert_eq!(summary.by_country["unknown"], 1);
+        assert!(summary.to_string().contains("country CN: 1\n"));
+        assert!(summary.to_string().ends_with("2 active of 4 bans"));
+
+        let offenders = repeat_offenders(&logs, 2);
+        assert_eq!(offenders.len(), 1);
+        assert_eq!(offenders[0].bans, 3);
+        assert_eq!(offenders[0].sites, BTreeSet::from([1, 2]));
+        assert_eq!(offenders[0].first_banned.as_deref(), Some("2023-12-01T00:00:00Z"));
+        assert_eq!(offenders[0].last_banned.as_deref(), Some("2023-12-04T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_plan_relief() {
+        let allowlist = Allowlist::parse("10.0.0.0/8 # office NAT\n\n192.0.2.7\n").unwrap();
+        assert!(Allowlist::parse("not an ip").is_err());
+
+        let logs = vec![
+            ban(1, "10.1.2.3", 1, "req", "2024-01-01T00:10:00Z"),
+            ban(2, "10.1.2.3", 1, "req", "2023-12-31T23:00:00Z"),
+            ban(3, "192.0.2.7", 2, "error", "2024-01-01T00:10:00Z"),
+            ban(4, "8.8.8.8", 1, "req", "2024-01-01T00:10:00Z"),
+        ];
+        let reliefs = plan_relief(&logs, &allowlist, NOW);
+        assert_eq!(reliefs.iter().map(|r| r.log_id).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(reliefs[0].label, "office NAT");
+        assert_eq!(
+            reliefs[0].to_string(),
+            r#"relieve ban #1 of 10.1.2.3 on site 1 (req): allowlisted as "office NAT""#
+        );
+    }
+}
@@ -0,0 +1,559 @@
+//! Fluent builder for site ACL rate-limiting rules
+//!
+//! `ACLConfig` leaves every field optional. `AclRule` builds a validated
+//! rule instead:
+//!
+//! ```no_run
+//! # use safeline_rs::acl_rule::{AclCondition, AclRule};
+//! # use std::time::Duration;
+//! let rule = AclRule::requests(100)
+//!     .per(Duration::from_secs(60))
+//!     .ban_for(Duration::from_secs(600))
+//!     .when(AclCondition::path().prefix("/login"));
+//! ```
+//!
+//! and the `add_acl_rule`, `update_acl_rule` and `remove_acl_rule` helpers
+//! check it against the website's rules before writing it through the
+//! per-rule endpoints (`create_website_acl_rule`, `update_website_acl_rule`
+//! and `delete_website_acl_rule`), so all other rules are kept.
+//! `apply_acl_rules` writes a list of rules the same way, matching them by
+//! name.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use crate::policy::dsl::{Expr, Op};
+use regex::Regex;
+use std::time::Duration;
+use tracing::info;
+
+/// What an ACL rule counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclKind {
+    Requests,
+    Attacks,
+    Errors,
+}
+
+impl AclKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AclKind::Requests => "req",
+            AclKind::Attacks => "attack",
+            AclKind::Errors => "error",
+        }
+    }
+}
+
+/// What happens when the limit is exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclAction {
+    /// Block the client for a while
+    Ban(Duration),
+    /// Make the client solve a challenge
+    Challenge,
+}
+
+/// Maximum counting period
+pub const MAX_PERIOD: Duration = Duration::from_secs(24 * 3600);
+/// Maximum ban duration
+pub const MAX_BAN: Duration = Duration::from_secs(30 * 24 * 3600);
+/// Maximum request count per period
+pub const MAX_COUNT: i32 = 1_000_000;
+
+/// A condition restricting which requests a rule counts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclCondition {
+    pub field: String,
+    pub op: Op,
+    pub values: Vec<String>,
+}
+
+/// Start of a condition on a request field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclField(&'static str);
+
+impl AclField {
+    fn op(self, op: Op, value: &str) -> AclCondition {
+        AclCondition {
+            field: self.0.to_string(),
+            op,
+            values: vec![value.to_string()],
+        }
+    }
+
+    pub fn eq(self, value: &str) -> AclCondition {
+        self.op(Op::Eq, value)
+    }
+
+    pub fn not_eq(self, value: &str) -> AclCondition {
+        self.op(Op::NotEq, value)
+    }
+
+    pub fn has(self, value: &str) -> AclCondition {
+        self.op(Op::Has, value)
+    }
+
+    pub fn not_has(self, value: &str) -> AclCondition {
+        self.op(Op::NotHas, value)
+    }
+
+    pub fn prefix(self, value: &str) -> AclCondition {
+        self.op(Op::Prefix, value)
+    }
+
+    pub fn re(self, value: &str) -> AclCondition {
+        self.op(Op::Re, value)
+    }
+}
+
+impl AclCondition {
+    pub fn path() -> AclField {
+        AclField("url_path")
+    }
+
+    pub fn uri() -> AclField {
+        AclField("uri")
+    }
+
+    pub fn query() -> AclField {
+        AclField("query_string")
+    }
+
+    pub fn host() -> AclField {
+        AclField("host")
+    }
+
+    pub fn method() -> AclField {
+        AclField("method")
+    }
+
+    pub fn ip() -> AclField {
+        AclField("src_ip")
+    }
+
+    /// Add an alternative value; the condition holds if any value matches
+    pub fn or(mut self, value: &str) -> Self {
+        self.values.push(value.to_string());
+        self
+    }
+
+    /// Parse a single condition in policy DSL syntax, e.g.
+    /// `url_path prefix "/login"`
+    pub fn parse(source: &str) -> Result<Self> {
+        match Expr::parse(source)? {
+            Expr::Cond(c) if c.key.is_none() => Ok(Self {
+                field: c.field,
+                op: c.op,
+                values: c.values,
+            }),
+            _ => Err(Error::InvalidInput(format!(
+                "ACL conditions must be a single field comparison: {:?}",
+                source
+            ))),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if matches!(self.op, Op::In | Op::NotIn) {
+            return Err(Error::InvalidInput(format!("ACL conditions do not support '{}'", self.op.as_str())));
+        }
+        if self.values.is_empty() {
+            return Err(Error::InvalidInput(format!("ACL condition on {} has no values", self.field)));
+        }
+        if self.op == Op::Re {
+            for value in &self.values {
+                Regex::new(value).map_err(|e| Error::InvalidInput(format!("invalid regex {:?}: {}", value, e)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn to_rule_condition(&self) -> RuleCondition {
+        RuleCondition {
+            condition: MatchCondition {
+                field: self.field.clone(),
+                operator: self.op.as_str().to_string(),
+                value: self.values.clone(),
+            },
+            strategy: String::new(),
+        }
+    }
+}
+
+/// A rate-limiting rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclRule {
+    pub name: Option<String>,
+    pub kind: AclKind,
+    pub count: i32,
+    pub period: Duration,
+    pub action: AclAction,
+    pub priority: Option<i32>,
+    pub enabled: bool,
+    pub conditions: Vec<AclCondition>,
+}
+
+impl AclRule {
+    fn new(kind: AclKind, count: i32) -> Self {
+        Self {
+            name: None,
+            kind,
+            count,
+            period: Duration::from_secs(60),
+            action: AclAction::Ban(Duration::from_secs(600)),
+            priority: None,
+            enabled: true,
+            conditions: Vec::new(),
+        }
+    }
+
+    /// Limit the number of requests per period
+    pub fn requests(count: i32) -> Self {
+        Self::new(AclKind::Requests, count)
+    }
+
+    /// Limit the number of detected attacks per period
+    pub fn attacks(count: i32) -> Self {
+        Self::new(AclKind::Attacks, count)
+    }
+
+    /// Limit the number of error responses per period
+    pub fn errors(count: i32) -> Self {
+        Self::new(AclKind::Errors, count)
+    }
+
+    /// Counting period, 60 seconds by default
+    pub fn per(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Ban offending clients for `duration`; the default is 10 minutes
+    pub fn ban_for(mut self, duration: Duration) -> Self {
+        self.action = AclAction::Ban(duration);
+        self
+    }
+
+    /// Challenge offending clients instead of banning them
+    pub fn challenge(mut self) -> Self {
+        self.action = AclAction::Challenge;
+        self
+    }
+
+    /// Only count requests matching the condition; several conditions
+    /// must all hold
+    pub fn when(mut self, condition: AclCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn disabled(mut self) -> Self {
+        self.enabled = false;
+        self
+    }
+
+    /// Name of the rule, generated from its limit if not set
+    pub fn rule_name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("{} {} per {}s", self.count, self.kind.as_str(), self.period.as_secs()))
+    }
+
+    /// Check that all values are in range
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(Error::InvalidInput(format!("ACL rule {:?}: {}", self.rule_name(), message)));
+
+        if !(1..=MAX_COUNT).contains(&self.count) {
+            return invalid(format!("count must be between 1 and {}", MAX_COUNT));
+        }
+        if self.period.as_secs() == 0 || self.period > MAX_PERIOD || self.period.subsec_nanos() != 0 {
+            return invalid(format!("period must be whole seconds between 1s and {}s", MAX_PERIOD.as_secs()));
+        }
+        if let AclAction::Ban(duration) = self.action {
+            if duration.as_secs() < 60 || duration > MAX_BAN || duration.as_secs() % 60 != 0 || duration.subsec_nanos() != 0 {
+                return invalid(format!("ban must be whole minutes between 1 and {}", MAX_BAN.as_secs() / 60));
+            }
+        }
+        if self.priority.is_some_and(|p| p < 0) {
+            return invalid("priority must not be negative".to_string());
+        }
+        if self.name.as_deref().is_some_and(|n| n.trim().is_empty()) {
+            return invalid("name must not be empty".to_string());
+        }
+        for condition in &self.conditions {
+            condition.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Validate the rule and convert it to an `ACLConfig`
+    pub fn build(&self) -> Result<ACLConfig> {
+        self.validate()?;
+        let (action, block_min) = match self.action {
+            AclAction::Ban(duration) => ("ban", Some((duration.as_secs() / 60) as i32)),
+            AclAction::Challenge => ("challenge", None),
+        };
+
+        Ok(ACLConfig {
+            id: 0,
+            site_id: 0,
+            name: self.rule_name(),
+            r#type: Some(self.kind.as_str().to_string()),
+            action: Some(action.to_string()),
+            enabled: Some(self.enabled),
+            use_global: None,
+            built_in: None,
+            priority: self.priority,
+            period: Some(self.period.as_secs() as i32),
+            block_min,
+            count: Some(self.count),
+            conditions: Some(self.conditions.iter().map(AclCondition::to_rule_condition).collect()),
+            created_at: None,
+            updated_at: None,
+        })
+    }
+}
+
+/// Build a rule to add, checking its name is not taken
+fn new_rule(rules: &[ACLConfig], rule: &AclRule) -> Result<ACLConfig> {
+    let config = rule.build()?;
+    if rules.iter().any(|r| r.name == config.name) {
+        return Err(Error::InvalidInput(format!("ACL rule {:?} already exists", config.name)));
+    }
+    Ok(config)
+}
+
+fn find_rule(rules: &[ACLConfig], rule_id: i32) -> Result<&ACLConfig> {
+    let rule = rules
+        .iter()
+        .find(|r| r.id == rule_id)
+        .ok_or_else(|| Error::InvalidInput(format!("ACL rule {} does not exist", rule_id)))?;
+    if rule.built_in == Some(true) {
+        return Err(Error::InvalidInput(format!("ACL rule {} is built in", rule_id)));
+    }
+    Ok(rule)
+}
+
+/// Replace the settings of a rule, keeping its identity
+fn updated_rule(rules: &[ACLConfig], rule_id: i32, rule: &AclRule) -> Result<ACLConfig> {
+    let current = find_rule(rules, rule_id)?;
+    Ok(ACLConfig {
+        id: current.id,
+        site_id: current.site_id,
+        use_global: current.use_global,
+        built_in: current.built_in,
+        created_at: current.created_at,
+        updated_at: current.updated_at,
+        ..rule.build()?
+    })
+}
+
+/// Pair each rule with the ID of the website's rule of the same name, if
+/// any, and take over that rule's identity
+fn rules_by_name(current: &[ACLConfig], site_id: i32, rules: &[ACLConfig]) -> Vec<(Option<i32>, ACLConfig)> {
+    rules
+        .iter()
+        .map(|rule| match current.iter().find(|r| r.name == rule.name) {
+            Some(existing) => (
+                Some(existing.id),
+                ACLConfig {
+                    id: existing.id,
+                    site_id,
+                    built_in: existing.built_in,
+                    created_at: existing.created_at,
+                    updated_at: existing.updated_at,
+                    ..rule.clone()
+                },
+            ),
+            None => (
+                None,
+                ACLConfig {
+                    id: 0,
+                    site_id,
+                    created_at: None,
+                    updated_at: None,
+                    ..rule.clone()
+                },
+            ),
+        })
+        .collect()
+}
+
+impl Client {
+    /// Add ACL rule
+    ///
+    /// Checks the rule's name is not taken on the website, then creates
+    /// the rule. Other rules are left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `site_id` - Website ID
+    /// * `rule` - Rule to add; its name must not be taken
+    ///
+    /// # Returns
+    ///
+    /// Returns the ID of the new rule
+    pub async fn add_acl_rule(&self, site_id: i32, rule: &AclRule) -> Result<i32> {
+        let config = new_rule(&self.get_website_acl(site_id).await?, rule)?;
+        info!("adding ACL rule {:?} to website {}", rule.rule_name(), site_id);
+        self.create_website_acl_rule(site_id, &config).await
+    }
+
+    /// Update ACL rule
+    ///
+    /// Replaces the settings of one rule. Built-in rules cannot be updated.
+    ///
+    /// # Arguments
+    ///
+    /// * `site_id` - Website ID
+    /// * `rule_id` - ACL rule ID
+    /// * `rule` - New settings
+    ///
+    /// # Returns
+    ///
+    /// Returns the rule as written
+    pub async fn update_acl_rule(&self, site_id: i32, rule_id: i32, rule: &AclRule) -> Result<ACLConfig> {
+        let config = updated_rule(&self.get_website_acl(site_id).await?, rule_id, rule)?;
+        info!("updating ACL rule {} of website {}", rule_id, site_id);
+        self.update_website_acl_rule(site_id, rule_id, &config).await?;
+        Ok(config)
+    }
+
+    /// Remove ACL rule
+    ///
+    /// Deletes one rule. Built-in rules cannot be removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `site_id` - Website ID
+    /// * `rule_id` - ACL rule ID
+    pub async fn remove_acl_rule(&self, site_id: i32, rule_id: i32) -> Result<()> {
+        find_rule(&self.get_website_acl(site_id).await?, rule_id)?;
+        info!("removing ACL rule {} from website {}", rule_id, site_id);
+        self.delete_website_acl_rule(site_id, rule_id).await
+    }
+
+    /// Apply ACL rules by name
+    ///
+    /// Updates the website's rule of the same name for each given rule and
+    /// creates the rules that have no match, one rule at a time. Rules not
+    /// in the list are left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `site_id` - Website ID
+    /// * `rules` - Rules to write; their IDs and timestamps are ignored
+    ///
+    /// # Returns
+    ///
+    /// Returns the IDs of the written rules, in order
+    pub async fn apply_acl_rules(&self, site_id: i32, rules: &[ACLConfig]) -> Result<Vec<i32>> {
+        let current = self.get_website_acl(site_id).await?;
+        let mut ids = Vec::with_capacity(rules.len());
+        for (existing, config) in rules_by_name(&current, site_id, rules) {
+            let id = match existing {
+                Some(rule_id) => {
+                    info!("updating ACL rule {:?} of website {}", config.name, site_id);
+                    self.update_website_acl_rule(site_id, rule_id, &config).await?
+                }
+                None => {
+                    info!("adding ACL rule {:?} to website {}", config.name, site_id);
+                    self.create_website_acl_rule(site_id, &config).await?
+                }
+            };
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_build() {
+        let config = AclRule::requests(100)
+            .per(MINUTE)
+            .ban_for(10 * MINUTE)
+            .when(AclCondition::path().prefix("/login"))
+            .when(AclCondition::method().eq("POST").or("PUT"))
+            .build()
+            .unwrap();
+        assert_eq!(config.name, "100 req per 60s");
+        assert_eq!(config.r#type.as_deref(), Some("req"));
+        assert_eq!(config.action.as_deref(), Some("ban"));
+        assert_eq!((config.period, config.block_min, config.count), (Some(60), Some(10), Some(100)));
+        let conditions = config.conditions.unwrap();
+        assert_eq!(conditions[0].condition.operator, "prefix");
+        assert_eq!(conditions[1].condition.value, vec!["POST", "PUT"]);
+
+        let challenge = AclRule::attacks(5).challenge().named("scanners").build().unwrap();
+        assert_eq!((challenge.action.as_deref(), challenge.block_min), (Some("challenge"), None));
+
+        assert_eq!(
+            AclCondition::parse(r#"url_path prefix "/login""#).unwrap(),
+            AclCondition::path().prefix("/login")
+        );
+        assert!(AclCondition::parse(r#"header["x"] eq "1""#).is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(AclRule::requests(0).build().is_err());
+        assert!(AclRule::requests(10).per(Duration::from_secs(0)).build().is_err());
+        assert!(AclRule::requests(10).per(Duration::from_millis(1500)).build().is_err());
+        assert!(AclRule::requests(10).per(MAX_PERIOD + MINUTE).build().is_err());
+        assert!(AclRule::requests(10).ban_for(Duration::from_secs(90)).build().is_err());
+        assert!(AclRule::requests(10).ban_for(MAX_BAN + MINUTE).build().is_err());
+        assert!(AclRule::requests(10).priority(-1).build().is_err());
+        assert!(AclRule::requests(10).when(AclCondition::host().re("(")).build().is_err());
+        assert!(AclRule::requests(10).ban_for(MAX_BAN).per(MAX_PERIOD).build().is_ok());
+    }
+
+    #[test]
+    fn test_rule_checks() {
+        let mut builtin = AclRule::errors(50).named("builtin").build().unwrap();
+        builtin.id = 1;
+        builtin.built_in = Some(true);
+        let mut login = AclRule::requests(100).named("login").build().unwrap();
+        login.id = 2;
+        login.created_at = Some(1704067200);
+        let rules = vec![builtin, login];
+
+        assert_eq!(new_rule(&rules, &AclRule::requests(10).named("api")).unwrap().name, "api");
+        assert!(new_rule(&rules, &AclRule::requests(10).named("login")).is_err());
+
+        let updated = updated_rule(&rules, 2, &AclRule::requests(200).named("login")).unwrap();
+        assert_eq!((updated.id, updated.count), (2, Some(200)));
+        assert_eq!(updated.created_at, Some(1704067200));
+
+        assert!(find_rule(&rules, 2).is_ok());
+        assert!(find_rule(&rules, 1).is_err());
+        assert!(updated_rule(&rules, 3, &AclRule::requests(1)).is_err());
+
+        let mut imported = AclRule::requests(300).named("login").build().unwrap();
+        imported.id = 9;
+        imported.site_id = 4;
+        let api = AclRule::requests(10).named("api").build().unwrap();
+        let writes = rules_by_name(&rules, 5, &[imported, api]);
+        assert_eq!(writes[0].0, Some(2));
+        assert_eq!((writes[0].1.id, writes[0].1.site_id, writes[0].1.count), (2, 5, Some(300)));
+        assert_eq!(writes[0].1.created_at, Some(1704067200));
+        assert_eq!(writes[1].0, None);
+        assert_eq!((writes[1].1.id, writes[1].1.site_id), (0, 5));
+    }
+}
@@ -0,0 +1,533 @@
+//! Readable view of a detect log
+//!
+//! `DetectLog` keeps the attacked request as opaque strings. `AttackDetail`
+//! parses the raw headers, decodes the query string and body (form, JSON or
+//! multipart), locates the matched payload and joins the Skynet rule that
+//! fired, for rendering as text or JSON.
+
+use crate::client::Client;
+use crate::correlate::log_time;
+use crate::error::Result;
+use crate::models::*;
+use crate::policy::replay::parse_raw_headers;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Decode `%XX` escapes, and `+` as a space if `plus` is set; invalid
+/// escapes are kept as they are
+pub fn percent_decode(value: &str, plus: bool) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(h), Some(l)) => {
+                    out.push((h * 16 + l) as u8);
+                    i += 3;
+                    continue;
+                }
+                _ => out.push(b'%'),
+            },
+            b'+' if plus => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Decode a query string or form body into name/value pairs
+pub fn parse_form(value: &str) -> Vec<(String, String)> {
+    value
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name, true), percent_decode(value, true))
+        })
+        .collect()
+}
+
+/// One part of a multipart body
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MultipartField {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    pub value: String,
+}
+
+/// A decoded request body
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Body {
+    Empty,
+    Form(Vec<(String, String)>),
+    Json(serde_json::Value),
+    Multipart(Vec<MultipartField>),
+    Raw(String),
+}
+
+/// Value of a `; name=value` parameter of a header such as Content-Type
+fn header_param<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim().trim_matches('"'))
+    })
+}
+
+fn parse_multipart(body: &str, boundary: &str) -> Vec<MultipartField> {
+    let delimiter = format!("--{}", boundary);
+    body.split(delimiter.as_str())
+        .filter_map(|part| {
+            let part = part.strip_prefix("\r\n").or_else(|| part.strip_prefix('\n'))?;
+            let (head, value) = part.split_once("\r\n\r\n").or_else(|| part.split_once("\n\n"))?;
+            let headers = parse_raw_headers(head);
+            let header = |name: &str| {
+                headers
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map(|(_, v)| v.as_str())
+            };
+            let disposition = header("Content-Disposition")?;
+            Some(MultipartField {
+                name: header_param(disposition, "name").unwrap_or_default().to_string(),
+                filename: header_param(disposition, "filename").map(str::to_string),
+                content_type: header("Content-Type").map(str::to_string),
+                value: value.trim_end_matches(['\r', '\n']).to_string(),
+            })
+        })
+        .collect()
+}
+
+impl Body {
+    /// Decode a body according to its Content-Type, guessing JSON and form
+    /// bodies when the type is missing
+    pub fn parse(body: &str, content_type: Option<&str>) -> Self {
+        if body.is_empty() {
+            return Body::Empty;
+        }
+        let mime = content_type
+            .and_then(|c| c.split(';').next())
+            .map(|m| m.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        if mime.starts_with("multipart/") {
+            if let Some(boundary) = content_type.and_then(|c| header_param(c, "boundary")) {
+                return Body::Multipart(parse_multipart(body, boundary));
+            }
+        }
+        let looks_like_json = body.trim_start().starts_with(['{', '[']);
+        if mime.ends_with("json") || (mime.is_empty() && looks_like_json) {
+            if let Ok(value) = serde_json::from_str(body) {
+                return Body::Json(value);
+            }
+        }
+        let looks_like_form = !body.contains(char::is_whitespace) && body.contains('=');
+        if mime == "application/x-www-form-urlencoded" || (mime.is_empty() && looks_like_form) {
+            return Body::Form(parse_form(body));
+        }
+        Body::Raw(body.to_string())
+    }
+}
+
+/// Where the matched payload was found
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Highlight {
+    /// `url_path`, `query_string`, `req_body` or `header:<name>`
+    pub field: String,
+    /// Whether the offset is into the URL-decoded value
+    pub decoded: bool,
+    /// Byte offset and length of the payload within the value
+    pub offset: usize,
+    pub len: usize,
+    /// The value with the payload marked as `[[...]]`
+    pub marked: String,
+}
+
+fn highlight(payload: &str, fields: &[(String, &str)]) -> Option<Highlight> {
+    if payload.is_empty() {
+        return None;
+    }
+    let mut candidates = Vec::new();
+    for (name, value) in fields {
+        candidates.push((name, false, value.to_string()));
+    }
+    for (name, value) in fields {
+        candidates.push((name, true, percent_decode(value, true)));
+    }
+    candidates.into_iter().find_map(|(name, decoded, value)| {
+        let offset = value.find(payload)?;
+        let end = offset + payload.len();
+        Some(Highlight {
+            field: name.clone(),
+            decoded,
+            offset,
+            len: payload.len(),
+            marked: format!("{}[[{}]]{}", &value[..offset], payload, &value[end..]),
+        })
+    })
+}
+
+/// The Skynet rule that matched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatchedRule {
+    pub id: String,
+    pub name: String,
+    pub attack_type: i32,
+    pub risk_level: i32,
+}
+
+/// Parsed view of a detect log
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttackDetail {
+    pub id: i32,
+    pub event_id: String,
+    pub timestamp: Option<i64>,
+    pub src_ip: String,
+    pub host: String,
+    pub method: String,
+    pub path: String,
+    /// `None` for views of attack records, which carry no classification
+    pub attack_type: Option<i32>,
+    pub risk_level: Option<i32>,
+    /// 0: passed, 1: blocked
+    pub action: i32,
+    pub reason: String,
+    /// Header values by lowercase name, in request order
+    pub headers: BTreeMap<String, Vec<String>>,
+    pub query: Vec<(String, String)>,
+    pub body: Body,
+    pub payload: Option<String>,
+    pub decode_path: Option<String>,
+    pub highlight: Option<Highlight>,
+    pub rule: Option<MatchedRule>,
+}
+
+impl AttackDetail {
+    /// Build the view, joining the rule from `rules` (keyed by rule ID)
+    pub fn new(log: &DetectLog, rules: &HashMap<String, SkynetInfo>) -> Self {
+        let raw_headers = parse_raw_headers(log.req_header.as_deref().unwrap_or_default());
+        let mut headers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, value) in &raw_headers {
+            headers.entry(name.to_ascii_lowercase()).or_default().push(value.clone());
+        }
+        let content_type = headers.get("content-type").and_then(|v| v.first()).map(String::as_str);
+
+        let query_string = log.query_string.as_deref().unwrap_or_default();
+        let body = log.req_body.as_deref().unwrap_or_default();
+
+        let mut fields = vec![
+            ("url_path".to_string(), log.url_path.as_str()),
+            ("query_string".to_string(), query_string),
+            ("req_body".to_string(), body),
+        ];
+        fields.extend(raw_headers.iter().map(|(name, value)| (format!("header:{}", name), value.as_str())));
+
+        let rule = rules
+            .get(&log.rule_id)
+            .or_else(|| rules.values().find(|r| r.id == log.rule_id))
+            .map(|r| MatchedRule {
+                id: r.id.clone(),
+                name: r.name.clone(),
+                attack_type: r.attack_type,
+                risk_level: r.risk_level,
+            });
+
+        Self {
+            id: log.id,
+            event_id: log.event_id.clone(),
+            timestamp: log_time(log),
+            src_ip: log.src_ip.clone(),
+            host: log.host.clone(),
+            method: log.method.clone(),
+            path: log.url_path.clone(),
+            attack_type: Some(log.attack_type),
+            risk_level: Some(log.risk_level),
+            action: log.action,
+            reason: log.reason.clone(),
+            query: parse_form(query_string),
+            body: Body::parse(body, content_type),
+            headers,
+            payload: log.payload.clone().filter(|p| !p.is_empty()),
+            decode_path: log.decode_path.clone().filter(|p| !p.is_empty()),
+            highlight: log.payload.as_deref().and_then(|p| highlight(p, &fields)),
+            rule,
+        }
+    }
+
+    /// Build the view of an attack record
+    ///
+    /// A `RecordEvent` only carries the source, target and counts, so the
+    /// request fields, classification, payload and rule stay empty.
+    pub fn from_record_event(event: &RecordEvent) -> Self {
+        Self {
+            id: event.id,
+            event_id: String::new(),
+            timestamp: Some(event.start_at),
+            src_ip: event.ip.clone(),
+            host: event.host.clone(),
+            method: String::new(),
+            path: String::new(),
+            attack_type: None,
+            risk_level: None,
+            action: (event.deny_count > 0) as i32,
+            reason: format!("{} passed, {} denied", event.pass_count, event.deny_count),
+            headers: BTreeMap::new(),
+            query: Vec::new(),
+            body: Body::Empty,
+            payload: None,
+            decode_path: None,
+            highlight: None,
+            rule: None,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for AttackDetail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = if self.action == 1 { "blocked" } else { "passed" };
+        write!(f, "Attack #{}", self.id)?;
+        if !self.event_id.is_empty() {
+            write!(f, " ({})", self.event_id)?;
+        }
+        writeln!(f, " from {}: {}", self.src_ip, action)?;
+        writeln!(f, "  {} {}{}", self.method, self.host, self.path)?;
+        match (self.attack_type, self.risk_level) {
+            (Some(attack_type), Some(risk_level)) => writeln!(f, "  attack type {}, risk {}", attack_type, risk_level)?,
+            (Some(attack_type), None) => writeln!(f, "  attack type {}", attack_type)?,
+            (None, Some(risk_level)) => writeln!(f, "  risk {}", risk_level)?,
+            (None, None) => {}
+        }
+        if let Some(rule) = &self.rule {
+            writeln!(f, "  rule {}: {}", rule.id, rule.name)?;
+        }
+        if !self.reason.is_empty() {
+            writeln!(f, "  reason: {}", self.reason)?;
+        }
+
+        writeln!(f, "Headers:")?;
+        for (name, values) in &self.headers {
+            for value in values {
+                writeln!(f, "  {}: {}", name, value)?;
+            }
+        }
+        if !self.query.is_empty() {
+            writeln!(f, "Query:")?;
+            for (name, value) in &self.query {
+                writeln!(f, "  {} = {}", name, value)?;
+            }
+        }
+        match &self.body {
+            Body::Empty => {}
+            Body::Form(pairs) => {
+                writeln!(f, "Body (form):")?;
+                for (name, value) in pairs {
+                    writeln!(f, "  {} = {}", name, value)?;
+                }
+            }
+            Body::Json(value) => {
+                writeln!(f, "Body (json):")?;
+                let pretty = serde_json::to_string_pretty(value).map_err(|_| fmt::Error)?;
+                for line in pretty.lines() {
+                    writeln!(f, "  {}", line)?;
+                }
+            }
+            Body::Multipart(fields) => {
+                writeln!(f, "Body (multipart):")?;
+                for field in fields {
+                    match &field.filename {
+                        Some(filename) => writeln!(f, "  {} (file {:?}, {} bytes)", field.name, filename, field.value.len())?,
+                        None => writeln!(f, "  {} = {}", field.name, field.value)?,
+                    }
+                }
+            }
+            Body::Raw(body) => writeln!(f, "Body:\n  {}", body)?,
+        }
+
+        if let Some(payload) = &self.payload {
+            write!(f, "Payload: {}", payload)?;
+            if let Some(decode_path) = &self.decode_path {
+                write!(f, " (decoded via {})", decode_path)?;
+            }
+            writeln!(f)?;
+        }
+        match &self.highlight {
+            Some(h) => write!(
+                f,
+                "Matched in {}{} at {}: {}",
+                h.field,
+                if h.decoded { " (decoded)" } else { "" },
+                h.offset,
+                h.marked
+            ),
+            None => write!(f, "Matched location unknown"),
+        }
+    }
+}
+
+impl Client {
+    /// Get attack detail
+    ///
+    /// Fetches a rule detect log and the Skynet rules, and builds the
+    /// parsed view.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Record ID
+    ///
+    /// # Returns
+    ///
+    /// Returns the parsed attack
+    pub async fn attack_detail(&self, id: i32) -> Result<AttackDetail> {
+        let log = self.get_rule_detect_log(id).await?;
+        let rules = self.get_skynet_rule().await?.rules;
+        Ok(AttackDetail::new(&log, &rules))
+    }
+
+    /// Get attack record detail
+    ///
+    /// Fetches an attack record with `get_record_detail` and builds its
+    /// view.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Record ID
+    ///
+    /// # Returns
+    ///
+    /// Returns the view of the record
+    pub async fn record_attack_detail(&self, id: i32) -> Result<AttackDetail> {
+        Ok(AttackDetail::from_record_event(&self.get_record_detail(id).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(percent_decode("a%20b+c%2", true), "a b c%2");
+        assert_eq!(percent_decode("a+b%zz", false), "a+b%zz");
+        assert_eq!(
+            parse_form("id=1%27+or+1%3D1&x&y="),
+            vec![
+                ("id".to_string(), "1' or 1=1".to_string()),
+                ("x".to_string(), String::new()),
+                ("y".to_string(), String::new()),
+            ]
+        );
+
+        assert_eq!(Body::parse("", None), Body::Empty);
+        assert_eq!(Body::parse(r#"{"a":1}"#, None), Body::Json(serde_json::json!({"a": 1})));
+        assert_eq!(
+            Body::parse("a=1", Some("application/x-www-form-urlencoded; charset=utf-8")),
+            Body::Form(vec![("a".to_string(), "1".to_string())])
+        );
+        assert_eq!(Body::parse("hello world", None), Body::Raw("hello world".to_string()));
+
+        let multipart = "--XY\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhi\r\n--XY\r\nContent-Disposition: form-data; name=\"f\"; filename=\"a.php\"\r\nContent-Type: text/plain\r\n\r\n<?php x ?>\r\n--XY--\r\n";
+        let Body::Multipart(fields) = Body::parse(multipart, Some("multipart/form-data; boundary=XY")) else {
+            panic!("not multipart");
+        };
+        assert_eq!(fields.len(), 2);
+        assert_eq!((fields[0].name.as_str(), fields[0].value.as_str()), ("note", "hi"));
+        assert_eq!(fields[1].filename.as_deref(), Some("a.php"));
+        assert_eq!(fields[1].value, "<?php x ?>");
+    }
+
+    #[test]
+    fn test_attack_detail() {
+        let log: DetectLog = serde_json::from_value(serde_json::json!({
+            "id": 7,
+            "event_id": "e7",
+            "site_uuid": "1",
+            "website": "a",
+            "src_ip": "1.2.3.4",
+            "src_port": 40000,
+            "dst_ip": "10.0.0.1",
+            "dst_port": 443,
+            "host": "a.com",
+            "method": "POST",
+            "url_path": "/login",
+            "protocol": 1,
+            "module": "m",
+            "attack_type": 0,
+            "risk_level": 2,
+            "action": 1,
+            "reason": "",
+            "rule_id": "m_sqli",
+            "query_string": "next=%2Fhome",
+            "req_header": "POST /login HTTP/1.1\r\nHost: a.com\r\nContent-Type: application/x-www-form-urlencoded\r\nCookie: a=1\r\nCookie: b=2\r\n",
+            "req_body": "user=admin%27+or+1%3D1--&pass=x",
+            "payload": "admin' or 1=1--",
+            "timestamp": 1700000000,
+        }))
+        .unwrap();
+        let rules = HashMap::from([(
+            "m_sqli".to_string(),
+            SkynetInfo {
+                id: "m_sqli".to_string(),
+                name: "SQL injection".to_string(),
+                attack_type: 0,
+                mode: "default".to_string(),
+                risk_level: 2,
+            },
+        )]);
+
+        let detail = AttackDetail::new(&log, &rules);
+        assert_eq!(detail.headers["cookie"], vec!["a=1", "b=2"]);
+        assert_eq!(detail.query, vec![("next".to_string(), "/home".to_string())]);
+        assert!(matches!(&detail.body, Body::Form(pairs) if pairs[0].1 == "admin' or 1=1--"));
+        assert_eq!(detail.rule.as_ref().unwrap().name, "SQL injection");
+
+        let highlight = detail.highlight.clone().unwrap();
+        assert_eq!((highlight.field.as_str(), highlight.decoded, highlight.offset), ("req_body", true, 5));
+        assert_eq!(highlight.marked, "user=[[admin' or 1=1--]]&pass=x");
+
+        let text = detail.to_string();
+        assert!(text.starts_with("Attack #7 (e7) from 1.2.3.4: blocked\n  POST a.com/login\n"));
+        assert!(text.contains("  attack type 0, risk 2\n"));
+        assert!(text.contains("  rule m_sqli: SQL injection\n"));
+        assert!(text.ends_with("Matched in req_body (decoded) at 5: user=[[admin' or 1=1--]]&pass=x"));
+
+        let json: serde_json::Value = serde_json::from_str(&detail.to_json().unwrap()).unwrap();
+        assert_eq!(json["body"]["type"], "form");
+        assert_eq!(json["highlight"]["offset"], 5);
+    }
+
+    #[test]
+    fn test_from_record_event() {
+        let event: RecordEvent = serde_json::from_value(serde_json::json!({
+            "id": 3,
+            "ip": "1.2.3.4",
+            "host": "a.com",
+            "protocol": "http",
+            "dst_port": 80,
+            "start_at": 1700000000,
+            "end_at": 1700000060,
+            "pass_count": 2,
+            "deny_count": 5,
+            "finished": true,
+        }))
+        .unwrap();
+
+        let detail = AttackDetail::from_record_event(&event);
+        assert_eq!((detail.timestamp, detail.action), (Some(1700000000), 1));
+        assert_eq!(detail.body, Body::Empty);
+        assert_eq!((detail.attack_type, detail.risk_level), (None, None));
+        let text = detail.to_string();
+        assert!(text.starts_with("Attack #3 from 1.2.3.4: blocked\n"));
+        assert!(!text.contains("attack type"));
+    }
+}
@@ -0,0 +1,506 @@
+//! Challenge and waiting-room analytics
+//!
+//! Aggregates challenge records into pass/fail rates per site and per
+//! client, a time-to-solve distribution and a list of likely bots, and
+//! waiting-room records into queue length and wait time percentiles per
+//! time bucket. Challenge records carry no fingerprint, so clients are
+//! keyed by the JA4 fingerprint seen for their IP in detect logs when one
+//! is known, and by IP otherwise.
+
+use crate::client::Client;
+use crate::error::Result;
+use crate::models::*;
+use futures::stream::{StreamExt, TryStreamExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Summary of a set of values
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub count: usize,
+    pub min: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    pub fn new(mut values: Vec<f64>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(f64::total_cmp);
+        Self {
+            count: values.len(),
+            min: values[0],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            p50: percentile(&values, 50.0),
+            p90: percentile(&values, 90.0),
+            p99: percentile(&values, 99.0),
+            max: values[values.len() - 1],
+        }
+    }
+}
+
+impl fmt::Display for Percentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "n={} min={} p50={} p90={} p99={} max={} mean={:.1}",
+            self.count, self.min, self.p50, self.p90, self.p99, self.max, self.mean
+        )
+    }
+}
+
+/// Upper bounds in seconds of the time-to-solve histogram buckets
+pub const SOLVE_BUCKETS: [i64; 7] = [1, 2, 5, 10, 30, 60, 300];
+
+/// Time-to-solve distribution
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SolveTimes {
+    pub percentiles: Percentiles,
+    /// Count per bucket, keyed by upper bound; `None` holds slower solves
+    pub histogram: Vec<(Option<i64>, usize)>,
+}
+
+impl SolveTimes {
+    pub fn new(seconds: Vec<i64>) -> Self {
+        let mut histogram: Vec<(Option<i64>, usize)> = SOLVE_BUCKETS.iter().map(|b| (Some(*b), 0)).collect();
+        histogram.push((None, 0));
+        for s in &seconds {
+            let bucket = SOLVE_BUCKETS.iter().position(|b| s <= b).unwrap_or(SOLVE_BUCKETS.len());
+            histogram[bucket].1 += 1;
+        }
+        Self {
+            percentiles: Percentiles::new(seconds.into_iter().map(|s| s as f64).collect()),
+            histogram,
+        }
+    }
+}
+
+/// Challenge outcomes of a site or client
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PassRate {
+    pub records: usize,
+    pub triggers: i64,
+    pub passes: i64,
+    pub failures: i64,
+    /// Passes per trigger, 0 to 1
+    pub pass_rate: f64,
+}
+
+impl PassRate {
+    fn add(&mut self, log: &ChallengeLog) {
+        self.records += 1;
+        self.triggers += log.trigger_count as i64;
+        self.passes += log.pass_count as i64;
+        self.failures = (self.triggers - self.passes).max(0);
+        self.pass_rate = if self.triggers > 0 { self.passes as f64 / self.triggers as f64 } else { 0.0 };
+    }
+}
+
+/// A client that keeps failing challenges
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BotSuspect {
+    /// JA4 fingerprint, or IP if none is known
+    pub client: String,
+    pub ips: BTreeSet<String>,
+    pub rate: PassRate,
+}
+
+/// When a client counts as a likely bot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BotCriteria {
+    pub min_failures: i64,
+    /// Highest pass rate still considered suspicious
+    pub max_pass_rate: f64,
+}
+
+impl Default for BotCriteria {
+    fn default() -> Self {
+        Self {
+            min_failures: 5,
+            max_pass_rate: 0.2,
+        }
+    }
+}
+
+/// Challenge analytics
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChallengeReport {
+    pub overall: PassRate,
+    pub by_site: BTreeMap<String, PassRate>,
+    pub by_client: BTreeMap<String, PassRate>,
+    /// `dur_sec` of records that passed, from first challenge to pass
+    pub solve_times: SolveTimes,
+    /// Most failures first
+    pub bots: Vec<BotSuspect>,
+}
+
+fn challenge_site(log: &ChallengeLog) -> String {
+    Some(log.site_title.clone())
+        .filter(|t| !t.is_empty())
+        .or_else(|| log.site_server_names.first().cloned())
+        .unwrap_or_else(|| format!("site {}", log.site_id))
+}
+
+impl ChallengeReport {
+    /// Aggregate challenge records; `fingerprints` maps IPs to JA4
+    /// fingerprints
+    pub fn new(logs: &[ChallengeLog], fingerprints: &HashMap<String, String>, criteria: BotCriteria) -> Self {
+        let mut report = Self::default();
+        let mut client_ips: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut solve_times = Vec::new();
+
+        for log in logs {
+            let client = fingerprints.get(&log.ip).cloned().unwrap_or_else(|| log.ip.clone());
+            report.overall.add(log);
+            report.by_site.entry(challenge_site(log)).or_default().add(log);
+            report.by_client.entry(client.clone()).or_default().add(log);
+            client_ips.entry(client).or_default().insert(log.ip.clone());
+            if log.pass_count > 0 && log.dur_sec >= 0 {
+                solve_times.push(log.dur_sec as i64);
+            }
+        }
+
+        report.solve_times = SolveTimes::new(solve_times);
+        report.bots = report
+            .by_client
+            .iter()
+            .filter(|(_, rate)| rate.failures >= criteria.min_failures && rate.pass_rate <= criteria.max_pass_rate)
+            .map(|(client, rate)| BotSuspect {
+                client: client.clone(),
+                ips: client_ips.remove(client).unwrap_or_default(),
+                rate: rate.clone(),
+            })
+            .collect();
+        report.bots.sort_by(|a, b| b.rate.failures.cmp(&a.rate.failures).then(a.client.cmp(&b.client)));
+        report
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Export as JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+fn write_rate(f: &mut fmt::Formatter<'_>, name: &str, rate: &PassRate) -> fmt::Result {
+    writeln!(
+        f,
+        "  {}: {} passed / {} triggered ({:.1}%), {} failed",
+        name,
+        rate.passes,
+        rate.triggers,
+        rate.pass_rate * 100.0,
+        rate.failures
+    )
+}
+
+impl fmt::Display for ChallengeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Sites:")?;
+        for (site, rate) in &self.by_site {
+            write_rate(f, site, rate)?;
+        }
+        writeln!(f, "Time to solve: {}", self.solve_times.percentiles)?;
+        for (bound, count) in &self.solve_times.histogram {
+            match bound {
+                Some(bound) => writeln!(f, "  <= {}s: {}", bound, count)?,
+                None => writeln!(f, "  slower: {}", count)?,
+            }
+        }
+        if !self.bots.is_empty() {
+            writeln!(f, "Likely bots:")?;
+            for bot in &self.bots {
+                write_rate(f, &bot.client, &bot.rate)?;
+            }
+        }
+        write!(
+            f,
+            "{} records from {} clients, {:.1}% passed",
+            self.overall.records,
+            self.by_client.len(),
+            self.overall.pass_rate * 100.0
+        )
+    }
+}
+
+/// Waiting room activity in one time bucket
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WaitingRoomBucket {
+    /// Unix time the bucket starts at
+    pub start: i64,
+    pub records: usize,
+    /// Peak queue length of each record
+    pub queue_length: Percentiles,
+    /// Average wait of each record, in seconds
+    pub wait_seconds: Percentiles,
+    pub total_waiting: i64,
+}
+
+/// Waiting room analytics
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WaitingRoomReport {
+    /// Bucket width in seconds
+    pub bucket: i64,
+    pub buckets: Vec<WaitingRoomBucket>,
+    pub queue_length: Percentiles,
+    pub wait_seconds: Percentiles,
+}
+
+impl WaitingRoomReport {
+    /// Aggregate records into buckets by start time
+    pub fn new(logs: &[WaitingRoomLog], bucket: Duration) -> Self {
+        let width = (bucket.as_secs() as i64).max(1);
+        let mut grouped: BTreeMap<i64, Vec<&WaitingRoomLog>> = BTreeMap::new();
+        for log in logs {
+            grouped.entry(log.started_at.div_euclid(width) * width).or_default().push(log);
+        }
+
+        let queue = |logs: &[&WaitingRoomLog]| Percentiles::new(logs.iter().map(|l| l.top_waiting as f64).collect());
+        let wait = |logs: &[&WaitingRoomLog]| Percentiles::new(logs.iter().map(|l| l.avg_wait_sec as f64).collect());
+        let all: Vec<&WaitingRoomLog> = logs.iter().collect();
+
+        Self {
+            bucket: width,
+            buckets: grouped
+                .into_iter()
+                .map(|(start, logs)| WaitingRoomBucket {
+                    start,
+                    records: logs.len(),
+                    queue_length: queue(&logs),
+                    wait_seconds: wait(&logs),
+                    total_waiting: logs.iter().map(|l| l.total_waiting as i64).sum(),
+                })
+                .collect(),
+            queue_length: queue(&all),
+            wait_seconds: wait(&all),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Export as JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for WaitingRoomReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for bucket in &self.buckets {
+            writeln!(
+                f,
+                "{}: {} records, {} waited, queue p50={} p90={} max={}, wait p50={}s p90={}s max={}s",
+                bucket.start,
+                bucket.records,
+                bucket.total_waiting,
+                bucket.queue_length.p50,
+                bucket.queue_length.p90,
+                bucket.queue_length.max,
+                bucket.wait_seconds.p50,
+                bucket.wait_seconds.p90,
+                bucket.wait_seconds.max
+            )?;
+        }
+        write!(f, "Queue length: {}\nWait seconds: {}", self.queue_length, self.wait_seconds)
+    }
+}
+
+impl Client {
+    /// Challenge analytics
+    ///
+    /// Fetches all challenge records started in the time range, and detect
+    /// logs of the same range to key clients by JA4 fingerprint.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Unix time range start
+    /// * `end` - Unix time range end
+    /// * `criteria` - When a client counts as a likely bot
+    ///
+    /// # Returns
+    ///
+    /// Returns pass rates, time-to-solve distribution and likely bots
+    pub async fn challenge_report(&self, start: i64, end: i64, criteria: BotCriteria) -> Result<ChallengeReport> {
+        let logs: Vec<ChallengeLog> = self
+            .challenge_records(ChallengeRecordSearchRequest {
+                started_at_begin: Some(start),
+                started_at_end: Some(end),
+                ..Default::default()
+            })
+            .try_collect()
+            .await?;
+
+        let mut fingerprints = HashMap::new();
+        let mut detect = std::pin::pin!(self.detect_logs(DetectLogSearchRequest {
+            start: Some(start),
+            end: Some(end),
+            ..Default::default()
+        }));
+        while let Some(log) = detect.next().await {
+            let log = log?;
+            if let Some(fingerprint) = log.ja4_fingerprint.filter(|f| !f.is_empty()) {
+                fingerprints.insert(log.src_ip, fingerprint);
+            }
+        }
+
+        Ok(ChallengeReport::new(&logs, &fingerprints, criteria))
+    }
+
+    /// Waiting room analytics
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Unix time range start
+    /// * `end` - Unix time range end
+    /// * `site` - Optional site filter
+    /// * `bucket` - Width of the time buckets
+    ///
+    /// # Returns
+    ///
+    /// Returns queue length and wait time percentiles per time bucket
+    pub async fn waiting_room_report(
+        &self,
+        start: i64,
+        end: i64,
+        site: Option<&str>,
+        bucket: Duration,
+    ) -> Result<WaitingRoomReport> {
+        let logs: Vec<WaitingRoomLog> = self
+            .waiting_room_logs(WaitingRoomSearchRequest {
+                started_at_begin: Some(start),
+                started_at_end: Some(end),
+                site: site.map(str::to_string),
+                ..Default::default()
+            })
+            .try_collect()
+            .await?;
+        Ok(WaitingRoomReport::new(&logs, bucket))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn challenge(ip: &str, site: &str, triggers: i32, passes: i32, dur_sec: i32) -> ChallengeLog {
+        ChallengeLog {
+            id: 0,
+            site_id: 1,
+            site_title: site.to_string(),
+            site_icon: String::new(),
+            site_server_names: Vec::new(),
+            site_comment: String::new(),
+            ip: ip.to_string(),
+            rule_id: None,
+            pass_count: passes,
+            trigger_count: triggers,
+            dur_sec,
+            started_at: 0,
+            ended_at: 0,
+            city: None,
+            country: None,
+            province: None,
+            created_at: String::new(),
+            updated_at: String::new(),
+            event_id: String::new(),
+        }
+    }
+
+    fn waiting(started_at: i64, top_waiting: i32, avg_wait_sec: i32) -> WaitingRoomLog {
+        WaitingRoomLog {
+            site_id: 1,
+            site_title: "a".to_string(),
+            site_icon: String::new(),
+            site_server_names: Vec::new(),
+            site_comment: String::new(),
+            rule_id: 1,
+            started_at,
+            ended_at: started_at + 60,
+            dur_sec: 60,
+            cur_waiting: 0,
+            max_concurrent: 100,
+            top_waiting,
+            total_waiting: top_waiting * 2,
+            avg_wait_sec,
+            bounce_rate: 0.1,
+        }
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let p = Percentiles::new((1..=100).map(f64::from).collect());
+        assert_eq!((p.count, p.min, p.p50, p.p90, p.p99, p.max), (100, 1.0, 50.0, 90.0, 99.0, 100.0));
+        assert_eq!(p.mean, 50.5);
+        assert_eq!(Percentiles::new(Vec::new()).count, 0);
+
+        let times = SolveTimes::new(vec![1, 3, 3, 400]);
+        assert_eq!(times.histogram[0], (Some(1), 1));
+        assert_eq!(times.histogram[2], (Some(5), 2));
+        assert_eq!(times.histogram[7], (None, 1));
+    }
+
+    #[test]
+    fn test_challenge_report() {
+        let logs = vec![
+            challenge("1.1.1.1", "shop", 1, 1, 3),
+            challenge("2.2.2.2", "shop", 4, 0, 60),
+            challenge("3.3.3.3", "blog", 3, 0, 30),
+            challenge("4.4.4.4", "blog", 2, 1, 8),
+        ];
+        let fingerprints = HashMap::from([
+            ("2.2.2.2".to_string(), "t13d_bot".to_string()),
+            ("3.3.3.3".to_string(), "t13d_bot".to_string()),
+        ]);
+
+        let report = ChallengeReport::new(&logs, &fingerprints, BotCriteria::default());
+        assert_eq!((report.overall.triggers, report.overall.passes, report.overall.failures), (10, 2, 8));
+        assert_eq!(report.by_site["shop"].pass_rate, 0.2);
+        assert_eq!(report.by_client.len(), 3);
+        assert_eq!(report.solve_times.percentiles.count, 2);
+
+        assert_eq!(report.bots.len(), 1);
+        assert_eq!(report.bots[0].client, "t13d_bot");
+        assert_eq!(report.bots[0].ips.len(), 2);
+        assert_eq!(report.bots[0].rate.failures, 7);
+        assert!(report.to_string().ends_with("4 records from 3 clients, 20.0% passed"));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["by_client"]["t13d_bot"]["records"], 2);
+    }
+
+    #[test]
+    fn test_waiting_room_report() {
+        let logs = vec![waiting(3600, 10, 5), waiting(3700, 30, 20), waiting(7300, 5, 1)];
+        let report = WaitingRoomReport::new(&logs, Duration::from_secs(3600));
+        assert_eq!(report.buckets.len(), 2);
+        assert_eq!((report.buckets[0].start, report.buckets[0].records), (3600, 2));
+        assert_eq!(report.buckets[0].queue_length.max, 30.0);
+        assert_eq!(report.buckets[0].total_waiting, 80);
+        assert_eq!(report.buckets[1].start, 7200);
+        assert_eq!(report.wait_seconds.p50, 5.0);
+    }
+}
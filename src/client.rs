@@ -94,8 +94,8 @@ impl Client {
                     // This is a workaround since we can't directly check if R is ()
                     let type_name = std::any::type_name::<R>();
                     if type_name == "()" {
-                        // Safety: () is a zero-sized type, so this is safe
-                        Ok(unsafe { std::mem::MaybeUninit::uninit().assume_init() })
+                        // () deserializes from a JSON null
+                        serde_json::from_str::<R>("null").map_err(Error::from)
                     } else {
                         // For empty responses, return a default value if possible
                         serde_json::from_str::<R>("{}").map_err(|_| {
@@ -144,6 +144,18 @@ impl Client {
             .await
     }
 
+    /// Execute a POST request with query parameters and an empty body
+    pub async fn post_with_query<R: for<'de> Deserialize<'de>>(
+        &self,
+        endpoint: &str,
+        params: &HashMap<String, String>,
+    ) -> Result<R> {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(params)
+            .finish();
+        self.post(&format!("{}?{}", endpoint, query), &()).await
+    }
+
     /// Execute a PUT request
     pub async fn put<T: Serialize, R: for<'de> Deserialize<'de>>(
         &self,
@@ -210,6 +222,40 @@ impl Client {
             serde_json::from_str(&response_text).map_err(Error::from)
         }
     }
+
+    /// Execute a GET request with query parameters and return the raw response
+    ///
+    /// Used for endpoints that return files instead of JSON. Non-success
+    /// status codes are turned into an `ApiError` carrying the response body.
+    pub async fn get_raw(
+        &self,
+        endpoint: &str,
+        params: &HashMap<String, String>,
+    ) -> Result<reqwest::Response> {
+        let url = format!("{}{}", self.base_url, endpoint);
+        let response = self
+            .http_client
+            .request(reqwest::Method::GET, &url)
+            .header("X-SLCE-API-TOKEN", &self.api_token)
+            .query(params)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<ApiResponse<serde_json::Value>>(&text)
+                .ok()
+                .and_then(|r| r.msg)
+                .unwrap_or(text);
+            return Err(Error::ApiError {
+                code: Some(status.as_u16().to_string()),
+                message,
+            });
+        }
+
+        Ok(response)
+    }
 }
 
 /// Standard API response wrapper
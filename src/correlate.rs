@@ -0,0 +1,347 @@
+//! Correlate detect logs into incidents
+//!
+//! Detect logs sharing a key (source IP, JA4 fingerprint or event ID) are
+//! merged into one incident as long as they are no further apart than the
+//! window. A `Correlator` can be fed one log at a time, e.g. from
+//! `Client::detect_logs`, and closes incidents once they go quiet.
+
+use crate::client::Client;
+use crate::error::Result;
+use crate::models::*;
+use crate::time::parse_timestamp;
+use futures::stream::{Stream, StreamExt};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::time::Duration;
+
+/// What detect logs are grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CorrelationKey {
+    SrcIp,
+    Fingerprint,
+    EventId,
+}
+
+impl CorrelationKey {
+    /// Key value of a log; `None` if the log does not carry it
+    pub fn of(&self, log: &DetectLog) -> Option<String> {
+        let value = match self {
+            CorrelationKey::SrcIp => Some(&log.src_ip),
+            CorrelationKey::Fingerprint => log.ja4_fingerprint.as_ref(),
+            CorrelationKey::EventId => Some(&log.event_id),
+        };
+        value.filter(|v| !v.is_empty()).cloned()
+    }
+}
+
+impl fmt::Display for CorrelationKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CorrelationKey::SrcIp => "ip",
+            CorrelationKey::Fingerprint => "ja4",
+            CorrelationKey::EventId => "event",
+        })
+    }
+}
+
+/// Unix time of a log, from `timestamp` (seconds or milliseconds) or
+/// `created_at`
+pub fn log_time(log: &DetectLog) -> Option<i64> {
+    match log.timestamp {
+        Some(ts) if ts > 100_000_000_000 => Some(ts / 1000),
+        Some(ts) if ts > 0 => Some(ts),
+        _ => log.created_at.as_deref().and_then(parse_timestamp),
+    }
+}
+
+/// A group of related detect logs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Incident {
+    pub key: CorrelationKey,
+    pub value: String,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub events: usize,
+    pub src_ips: BTreeSet<String>,
+    pub hosts: BTreeSet<String>,
+    /// Event count per attack type
+    pub attack_types: BTreeMap<i32, usize>,
+    pub rule_ids: BTreeSet<String>,
+    /// Event count per action, 0 passed and 1 blocked
+    pub actions: BTreeMap<i32, usize>,
+    pub max_risk: i32,
+    pub log_ids: Vec<i32>,
+}
+
+impl Incident {
+    fn new(key: CorrelationKey, value: String, time: i64) -> Self {
+        Self {
+            key,
+            value,
+            first_seen: time,
+            last_seen: time,
+            events: 0,
+            src_ips: BTreeSet::new(),
+            hosts: BTreeSet::new(),
+            attack_types: BTreeMap::new(),
+            rule_ids: BTreeSet::new(),
+            actions: BTreeMap::new(),
+            max_risk: 0,
+            log_ids: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, log: &DetectLog, time: i64) {
+        self.first_seen = self.first_seen.min(time);
+        self.last_seen = self.last_seen.max(time);
+        self.events += 1;
+        self.src_ips.insert(log.src_ip.clone());
+        self.hosts.insert(log.host.clone());
+        *self.attack_types.entry(log.attack_type).or_insert(0) += 1;
+        let rule_ids = std::iter::once(&log.rule_id).chain(log.rule_id_list.iter().flatten());
+        self.rule_ids.extend(rule_ids.filter(|id| !id.is_empty()).cloned());
+        *self.actions.entry(log.action).or_insert(0) += 1;
+        self.max_risk = self.max_risk.max(log.risk_level);
+        self.log_ids.push(log.id);
+    }
+
+    /// Events that were let through
+    pub fn passed(&self) -> usize {
+        self.actions.get(&0).copied().unwrap_or(0)
+    }
+
+    pub fn duration(&self) -> i64 {
+        self.last_seen - self.first_seen
+    }
+
+    /// Ranking key: highest risk first, then attacks that got through, then
+    /// breadth (attack types and hosts), then volume
+    pub fn severity(&self) -> (i32, usize, usize, usize, usize) {
+        (self.max_risk, self.passed(), self.attack_types.len(), self.hosts.len(), self.events)
+    }
+}
+
+impl fmt::Display for Incident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |items: Vec<String>| items.join(",");
+        write!(
+            f,
+            "{} {}: {} events over {}s, risk {}, hosts [{}], attack types [{}], rules [{}], {} passed",
+            self.key,
+            self.value,
+            self.events,
+            self.duration(),
+            self.max_risk,
+            join(self.hosts.iter().cloned().collect()),
+            join(self.attack_types.keys().map(i32::to_string).collect()),
+            join(self.rule_ids.iter().cloned().collect()),
+            self.passed()
+        )
+    }
+}
+
+/// Sort incidents by severity, most severe first
+pub fn rank(incidents: &mut [Incident]) {
+    incidents.sort_by_key(|i| (Reverse(i.severity()), i.first_seen));
+}
+
+/// Groups detect logs into incidents
+#[derive(Debug, Clone)]
+pub struct Correlator {
+    pub key: CorrelationKey,
+    /// Maximum gap between two logs of the same incident, in seconds
+    pub window: i64,
+    open: HashMap<String, Incident>,
+    closed: Vec<Incident>,
+    /// Logs without the key or a usable time
+    pub skipped: usize,
+}
+
+impl Correlator {
+    pub fn new(key: CorrelationKey, window: Duration) -> Self {
+        Self {
+            key,
+            window: window.as_secs() as i64,
+            open: HashMap::new(),
+            closed: Vec::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Add a log; logs may arrive in either time order
+    pub fn push(&mut self, log: &DetectLog) {
+        let (Some(value), Some(time)) = (self.key.of(log), log_time(log)) else {
+            self.skipped += 1;
+            return;
+        };
+
+        if let Some(incident) = self.open.get(&value) {
+            if time > incident.last_seen + self.window || time < incident.first_seen - self.window {
+                let incident = self.open.remove(&value).unwrap();
+                self.closed.push(incident);
+            }
+        }
+        self.open
+            .entry(value.clone())
+            .or_insert_with(|| Incident::new(self.key, value, time))
+            .add(log, time);
+    }
+
+    /// Close and return incidents quiet for longer than the window at `now`,
+    /// together with those already split off, ranked
+    pub fn flush(&mut self, now: i64) -> Vec<Incident> {
+        let window = self.window;
+        let quiet: Vec<String> = self
+            .open
+            .iter()
+            .filter(|(_, incident)| incident.last_seen + window < now)
+            .map(|(value, _)| value.clone())
+            .collect();
+        for value in quiet {
+            if let Some(incident) = self.open.remove(&value) {
+                self.closed.push(incident);
+            }
+        }
+        let mut incidents = std::mem::take(&mut self.closed);
+        rank(&mut incidents);
+        incidents
+    }
+
+    /// Incidents still open
+    pub fn open(&self) -> impl Iterator<Item = &Incident> {
+        self.open.values()
+    }
+
+    /// Close everything and return all incidents, ranked
+    pub fn finish(mut self) -> Vec<Incident> {
+        self.closed.extend(self.open.into_values());
+        rank(&mut self.closed);
+        self.closed
+    }
+
+    /// Feed a whole stream of logs and return the ranked incidents
+    pub async fn correlate_stream<S>(mut self, logs: S) -> Result<Vec<Incident>>
+    where
+        S: Stream<Item = Result<DetectLog>>,
+    {
+        let mut logs = std::pin::pin!(logs);
+        while let Some(log) = logs.next().await {
+            self.push(&log?);
+        }
+        Ok(self.finish())
+    }
+}
+
+/// Correlate a batch of logs
+pub fn correlate(logs: &[DetectLog], key: CorrelationKey, window: Duration) -> Vec<Incident> {
+    let mut correlator = Correlator::new(key, window);
+    for log in logs {
+        correlator.push(log);
+    }
+    correlator.finish()
+}
+
+impl Client {
+    /// Correlate detect logs into incidents
+    ///
+    /// Streams all detect logs matching the filters and groups them.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Time range and filters
+    /// * `key` - What to group by
+    /// * `window` - Maximum gap between logs of one incident
+    ///
+    /// # Returns
+    ///
+    /// Returns incidents, most severe first
+    pub async fn correlate_detect_logs(
+        &self,
+        request: DetectLogSearchRequest,
+        key: CorrelationKey,
+        window: Duration,
+    ) -> Result<Vec<Incident>> {
+        Correlator::new(key, window).correlate_stream(self.detect_logs(request)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_log(id: i32, ip: &str, timestamp: i64) -> DetectLog {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "event_id": format!("e{}", id),
+            "site_uuid": "1",
+            "website": "a",
+            "src_ip": ip,
+            "src_port": 40000,
+            "dst_ip": "10.0.0.1",
+            "dst_port": 443,
+            "host": "a.com",
+            "method": "GET",
+            "url_path": "/",
+            "protocol": 1,
+            "module": "m",
+            "attack_type": 0,
+            "risk_level": 1,
+            "action": 1,
+            "reason": "",
+            "rule_id": "r1",
+            "timestamp": timestamp,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_correlate() {
+        let mut logs = vec![
+            detect_log(1, "1.1.1.1", 1000),
+            detect_log(2, "1.1.1.1", 1100),
+            detect_log(3, "1.1.1.1", 5000),
+            detect_log(4, "2.2.2.2", 1_050_000),
+        ];
+        logs[1].host = "b.com".to_string();
+        logs[1].attack_type = 7;
+        logs[1].rule_id = "r2".to_string();
+        logs[1].rule_id_list = Some(vec!["r2".to_string(), "r3".to_string()]);
+        logs[2].risk_level = 3;
+        logs[2].action = 0;
+        logs[3].ja4_fingerprint = Some("t13d".to_string());
+        logs[3].created_at = Some("1970-01-01T00:17:30Z".to_string());
+        logs[3].timestamp = None;
+
+        let incidents = correlate(&logs, CorrelationKey::SrcIp, Duration::from_secs(300));
+        assert_eq!(incidents.len(), 3);
+        assert_eq!((incidents[0].log_ids.clone(), incidents[0].max_risk, incidents[0].passed()), (vec![3], 3, 1));
+
+        let burst = &incidents[1];
+        assert_eq!(burst.log_ids, vec![1, 2]);
+        assert_eq!((burst.first_seen, burst.last_seen), (1000, 1100));
+        assert_eq!(burst.hosts.len(), 2);
+        assert_eq!(burst.attack_types, BTreeMap::from([(0, 1), (7, 1)]));
+        assert_eq!(burst.rule_ids, BTreeSet::from(["r1", "r2", "r3"].map(String::from)));
+        assert_eq!(incidents[2].first_seen, 1050);
+
+        let by_fingerprint = correlate(&logs, CorrelationKey::Fingerprint, Duration::from_secs(300));
+        assert_eq!(by_fingerprint.len(), 1);
+        assert!(by_fingerprint[0].to_string().starts_with("ja4 t13d: 1 events over 0s, risk 1"));
+    }
+
+    #[test]
+    fn test_correlator_flush() {
+        let mut correlator = Correlator::new(CorrelationKey::SrcIp, Duration::from_secs(60));
+        correlator.push(&detect_log(1, "1.1.1.1", 1000));
+        correlator.push(&detect_log(2, "2.2.2.2", 1030));
+        correlator.push(&detect_log(3, "", 1030));
+        assert!(correlator.flush(1050).is_empty());
+
+        let closed = correlator.flush(1070);
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].value, "1.1.1.1");
+        assert_eq!(correlator.open().count(), 1);
+        assert_eq!(correlator.skipped, 1);
+    }
+}
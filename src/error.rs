@@ -12,11 +12,30 @@ pub enum Error {
     #[error("API error (code: {code:?}): {message}")]
     ApiError { code: Option<String>, message: String },
 
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
     #[error("URL parsing error: {0}")]
     UrlError(#[from] url::ParseError),
 
     #[error("Invalid response format: {0}")]
     InvalidResponse(String),
+
+    #[error("Config format error: {0}")]
+    ConfigFormat(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("Operation timed out: {0}")]
+    Timeout(String),
+
+    #[error("Step '{step}' failed: {source}")]
+    StepFailed {
+        step: String,
+        #[source]
+        source: Box<Error>,
+    },
 }
 
 /// Result type alias for convenience
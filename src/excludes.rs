@@ -0,0 +1,329 @@
+//! Incremental exclude management
+//!
+//! `update_excludes` replaces the whole exclude list of a website. An
+//! `ExcludeChange` instead adds or removes individual path prefixes and
+//! content types and is merged into the current excludes, so applying the
+//! same change twice has no further effect. Before applying, the change
+//! can be previewed against the resources SafeLine has discovered for the
+//! website.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use std::fmt;
+use tracing::info;
+
+/// Check that a path prefix is usable as an exclude
+///
+/// Prefixes must start with `/` and must not contain whitespace, control
+/// characters, a query or fragment, or `..` segments.
+pub fn validate_prefix(prefix: &str) -> Result<()> {
+    let invalid = |reason: &str| Err(Error::InvalidInput(format!("invalid exclude prefix {:?}: {}", prefix, reason)));
+
+    if !prefix.starts_with('/') {
+        return invalid("must start with '/'");
+    }
+    if prefix.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return invalid("contains whitespace or control characters");
+    }
+    if prefix.contains(['?', '#']) {
+        return invalid("must not contain a query or fragment");
+    }
+    if prefix.split('/').any(|segment| segment == "..") {
+        return invalid("must not contain '..'");
+    }
+    Ok(())
+}
+
+/// Normalize a content type such as `Text/HTML; charset=utf-8` to `text/html`
+pub fn normalize_content_type(content_type: &str) -> Result<String> {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    let valid = essence.split_once('/').is_some_and(|(kind, sub)| {
+        let token = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+*".contains(c));
+        token(kind) && token(sub)
+    });
+
+    if valid {
+        Ok(essence)
+    } else {
+        Err(Error::InvalidInput(format!("invalid content type {:?}", content_type)))
+    }
+}
+
+/// Additions and removals of excluded prefixes and content types
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExcludeChange {
+    pub add_prefixes: Vec<String>,
+    pub remove_prefixes: Vec<String>,
+    pub add_content_types: Vec<String>,
+    pub remove_content_types: Vec<String>,
+}
+
+impl ExcludeChange {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Exclude requests whose path starts with `prefix`
+    pub fn add_prefix(mut self, prefix: &str) -> Self {
+        self.add_prefixes.push(prefix.to_string());
+        self
+    }
+
+    /// Stop excluding `prefix`
+    pub fn remove_prefix(mut self, prefix: &str) -> Self {
+        self.remove_prefixes.push(prefix.to_string());
+        self
+    }
+
+    /// Exclude responses of the given content type
+    pub fn add_content_type(mut self, content_type: &str) -> Self {
+        self.add_content_types.push(content_type.to_string());
+        self
+    }
+
+    /// Stop excluding the given content type
+    pub fn remove_content_type(mut self, content_type: &str) -> Self {
+        self.remove_content_types.push(content_type.to_string());
+        self
+    }
+
+    /// Validate the change and merge it into `current`
+    ///
+    /// Existing entries keep their order, new entries are appended, and
+    /// adding an entry that is already present or removing one that is
+    /// absent is a no-op.
+    pub fn merge(&self, current: &SiteExcludes) -> Result<SiteExcludes> {
+        for prefix in &self.add_prefixes {
+            validate_prefix(prefix)?;
+        }
+        let add_types = self
+            .add_content_types
+            .iter()
+            .map(|t| normalize_content_type(t))
+            .collect::<Result<Vec<_>>>()?;
+        let remove_types = self
+            .remove_content_types
+            .iter()
+            .map(|t| normalize_content_type(t))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(SiteExcludes {
+            prefixes: merge_list(&current.prefixes, &self.add_prefixes, &self.remove_prefixes, |p| p.to_string()),
+            content_types: merge_list(&current.content_types, &add_types, &remove_types, |t| {
+                normalize_content_type(t).unwrap_or_else(|_| t.to_string())
+            }),
+        })
+    }
+}
+
+fn merge_list(current: &[String], add: &[String], remove: &[String], key: impl Fn(&str) -> String) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::new();
+    for item in current.iter().chain(add) {
+        let k = key(item);
+        if !remove.contains(&k) && !merged.iter().any(|m| key(m) == k) {
+            merged.push(item.clone());
+        }
+    }
+    merged
+}
+
+/// Check whether a discovered resource is covered by the excludes
+pub fn is_excluded(excludes: &SiteExcludes, resource: &Resource) -> bool {
+    let content_type = normalize_content_type(&resource.content_type).ok();
+    excludes.prefixes.iter().any(|p| resource.path.starts_with(p.as_str()))
+        || excludes
+            .content_types
+            .iter()
+            .any(|t| normalize_content_type(t).ok().is_some_and(|t| Some(t) == content_type))
+}
+
+/// Effect of an exclude change on the discovered resources
+#[derive(Debug, Clone)]
+pub struct ExcludePreview {
+    pub current: SiteExcludes,
+    pub proposed: SiteExcludes,
+    /// Resources that the change would start excluding
+    pub newly_excluded: Vec<Resource>,
+    /// Resources that the change would stop excluding
+    pub no_longer_excluded: Vec<Resource>,
+}
+
+impl ExcludePreview {
+    /// Compute the effect of moving from `current` to `proposed`
+    pub fn new(current: SiteExcludes, proposed: SiteExcludes, resources: &[Resource]) -> Self {
+        let mut newly_excluded = Vec::new();
+        let mut no_longer_excluded = Vec::new();
+        for resource in resources {
+            match (is_excluded(&current, resource), is_excluded(&proposed, resource)) {
+                (false, true) => newly_excluded.push(resource.clone()),
+                (true, false) => no_longer_excluded.push(resource.clone()),
+                _ => {}
+            }
+        }
+
+        Self {
+            current,
+            proposed,
+            newly_excluded,
+            no_longer_excluded,
+        }
+    }
+
+    /// Whether the proposed excludes differ from the current ones
+    pub fn is_changed(&self) -> bool {
+        self.current != self.proposed
+    }
+}
+
+impl fmt::Display for ExcludePreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for prefix in self.proposed.prefixes.iter().filter(|p| !self.current.prefixes.contains(p)) {
+            writeln!(f, "+ prefix {}", prefix)?;
+        }
+        for prefix in self.current.prefixes.iter().filter(|p| !self.proposed.prefixes.contains(p)) {
+            writeln!(f, "- prefix {}", prefix)?;
+        }
+        for t in self.proposed.content_types.iter().filter(|t| !self.current.content_types.contains(t)) {
+            writeln!(f, "+ content type {}", t)?;
+        }
+        for t in self.current.content_types.iter().filter(|t| !self.proposed.content_types.contains(t)) {
+            writeln!(f, "- content type {}", t)?;
+        }
+        for r in &self.newly_excluded {
+            writeln!(f, "  excludes {} {} ({})", r.method, r.path, r.content_type)?;
+        }
+        for r in &self.no_longer_excluded {
+            writeln!(f, "  includes {} {} ({})", r.method, r.path, r.content_type)?;
+        }
+        write!(
+            f,
+            "Excludes: {} resources newly excluded, {} no longer excluded",
+            self.newly_excluded.len(),
+            self.no_longer_excluded.len()
+        )
+    }
+}
+
+impl Client {
+    /// Preview exclude change
+    ///
+    /// Merges the change into the website's current excludes and reports
+    /// which discovered resources would start or stop being excluded.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `change` - Prefixes and content types to add or remove
+    ///
+    /// # Returns
+    ///
+    /// Returns the current and proposed excludes with affected resources
+    pub async fn preview_excludes(&self, id: i32, change: &ExcludeChange) -> Result<ExcludePreview> {
+        let current = self.get_website_excludes(id).await?;
+        let proposed = change.merge(&current)?;
+        let resources = self.get_website_resources(id).await?.nodes;
+        Ok(ExcludePreview::new(current, proposed, &resources))
+    }
+
+    /// Apply exclude change
+    ///
+    /// Merges the change into the website's current excludes and updates
+    /// them if anything changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `change` - Prefixes and content types to add or remove
+    ///
+    /// # Returns
+    ///
+    /// Returns the resulting excludes
+    pub async fn apply_excludes(&self, id: i32, change: &ExcludeChange) -> Result<SiteExcludes> {
+        let current = self.get_website_excludes(id).await?;
+        let proposed = change.merge(&current)?;
+
+        if proposed != current {
+            self.update_excludes(
+                id,
+                &UpdateExcludesRequest {
+                    prefixes: proposed.prefixes.clone(),
+                    content_types: proposed.content_types.clone(),
+                },
+            )
+            .await?;
+            info!("updated excludes of website {}", id);
+        }
+
+        Ok(proposed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(path: &str, content_type: &str) -> Resource {
+        Resource {
+            id: 0,
+            site_id: 1,
+            method: "GET".to_string(),
+            path: path.to_string(),
+            content_type: content_type.to_string(),
+            content_length: 0,
+            status_code: 200,
+            response_time: 0,
+            req_today: 0,
+            req_header: String::new(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_merge_is_idempotent() {
+        let current = SiteExcludes {
+            prefixes: vec!["/static/".to_string(), "/old/".to_string()],
+            content_types: vec!["image/png".to_string()],
+        };
+        let change = ExcludeChange::new()
+            .add_prefix("/static/")
+            .add_prefix("/assets/")
+            .remove_prefix("/old/")
+            .add_content_type("Image/PNG")
+            .add_content_type("font/woff2; charset=binary");
+
+        let merged = change.merge(&current).unwrap();
+        assert_eq!(merged.prefixes, vec!["/static/", "/assets/"]);
+        assert_eq!(merged.content_types, vec!["image/png", "font/woff2"]);
+        assert_eq!(change.merge(&merged).unwrap(), merged);
+
+        assert!(ExcludeChange::new().add_prefix("static/").merge(&current).is_err());
+        assert!(ExcludeChange::new().add_prefix("/a/../b").merge(&current).is_err());
+        assert!(ExcludeChange::new().add_content_type("png").merge(&current).is_err());
+    }
+
+    #[test]
+    fn test_preview() {
+        let current = SiteExcludes {
+            prefixes: vec!["/old/".to_string()],
+            content_types: vec![],
+        };
+        let resources = vec![
+            resource("/assets/app.js", "application/javascript"),
+            resource("/old/page", "text/html"),
+            resource("/logo", "image/png; charset=binary"),
+            resource("/api/items", "application/json"),
+        ];
+        let change = ExcludeChange::new()
+            .add_prefix("/assets/")
+            .remove_prefix("/old/")
+            .add_content_type("image/png");
+
+        let preview = ExcludePreview::new(current.clone(), change.merge(&current).unwrap(), &resources);
+        let paths = |rs: &[Resource]| rs.iter().map(|r| r.path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths(&preview.newly_excluded), vec!["/assets/app.js", "/logo"]);
+        assert_eq!(paths(&preview.no_longer_excluded), vec!["/old/page"]);
+        assert!(preview.is_changed());
+    }
+}
@@ -0,0 +1,58 @@
+//! Minimal glob matching for server names and file paths
+//!
+//! Supports `*` (any run of characters except `/`), `**` (any run of
+//! characters including `/`) and `?` (a single character except `/`).
+
+/// Check whether `text` matches the glob `pattern`
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&pattern, &text)
+}
+
+fn match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            // `**/` also matches zero directories
+            if rest.first() == Some(&'/') && match_from(&rest[1..], text) {
+                return true;
+            }
+            (0..=text.len()).any(|i| match_from(rest, &text[i..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if match_from(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => matches!(text.first(), Some(c) if *c != '/') && match_from(&pattern[1..], &text[1..]),
+        Some(c) => text.first() == Some(c) && match_from(&pattern[1..], &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.example.com", "api.example.com"));
+        assert!(glob_match("*.example.com", "a.b.example.com"));
+        assert!(!glob_match("*.example.com", "example.com"));
+        assert!(glob_match("shop-??.example.com", "shop-01.example.com"));
+
+        assert!(glob_match("*.css", "site.css"));
+        assert!(!glob_match("*.css", "css/site.css"));
+        assert!(glob_match("**/*.css", "css/site.css"));
+        assert!(glob_match("**/*.css", "site.css"));
+        assert!(glob_match("assets/**", "assets/img/logo.png"));
+    }
+}
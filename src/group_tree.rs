@@ -0,0 +1,508 @@
+//! Website group layout
+//!
+//! `GroupTree` holds the ordered website groups together with their ordered
+//! websites. Groups are addressed by name and websites by their first
+//! server name, so a layout can be edited without dealing with positional
+//! sort indices. `plan_group_tree` computes the shortest sequence of group
+//! creations, site moves and single-item sort calls that turns one layout
+//! into another.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use tracing::info;
+
+/// A website within a group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiteRef {
+    pub id: i32,
+    /// First server name of the website
+    pub name: String,
+}
+
+/// A website group and its websites in display order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupNode {
+    /// `None` for groups that do not exist on the server yet and for the
+    /// unnamed group of websites outside any listed group
+    pub id: Option<i32>,
+    pub name: String,
+    pub sites: Vec<SiteRef>,
+}
+
+/// Ordered website groups with their websites
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupTree {
+    pub groups: Vec<GroupNode>,
+}
+
+impl GroupTree {
+    /// Build the tree from the group and website listings
+    ///
+    /// Websites whose group is not listed are collected in a single unnamed
+    /// group at the end, which cannot be sorted or moved into.
+    pub fn from_listing(groups: &[WebsiteGroupItem], sites: &[WebsiteItem]) -> Self {
+        let mut groups: Vec<&WebsiteGroupItem> = groups.iter().collect();
+        groups.sort_by_key(|g| g.position);
+        let mut nodes: Vec<GroupNode> = groups
+            .iter()
+            .map(|g| GroupNode {
+                id: Some(g.id),
+                name: g.name.clone(),
+                sites: Vec::new(),
+            })
+            .collect();
+
+        let mut ungrouped = GroupNode {
+            id: None,
+            name: String::new(),
+            sites: Vec::new(),
+        };
+
+        let mut sites: Vec<&WebsiteItem> = sites.iter().collect();
+        sites.sort_by_key(|s| s.position);
+        for site in sites {
+            let site_ref = SiteRef {
+                id: site.id,
+                name: site.server_names.first().cloned().unwrap_or_default(),
+            };
+            match nodes.iter_mut().find(|n| n.id == Some(site.group_id)) {
+                Some(node) => node.sites.push(site_ref),
+                None => ungrouped.sites.push(site_ref),
+            }
+        }
+
+        if !ungrouped.sites.is_empty() {
+            nodes.push(ungrouped);
+        }
+        Self { groups: nodes }
+    }
+
+    pub fn group(&self, name: &str) -> Option<&GroupNode> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    fn group_index(&self, name: &str) -> Result<usize> {
+        self.groups
+            .iter()
+            .position(|g| g.name == name)
+            .ok_or_else(|| Error::InvalidInput(format!("unknown website group {:?}", name)))
+    }
+
+    fn site_location(&self, name: &str) -> Result<(usize, usize)> {
+        self.groups
+            .iter()
+            .enumerate()
+            .find_map(|(g, group)| group.sites.iter().position(|s| s.name == name).map(|s| (g, s)))
+            .ok_or_else(|| Error::InvalidInput(format!("unknown website {:?}", name)))
+    }
+
+    /// Append a new, empty group; does nothing if the group exists
+    pub fn add_group(&mut self, name: &str) {
+        if self.group(name).is_none() {
+            self.groups.push(GroupNode {
+                id: None,
+                name: name.to_string(),
+                sites: Vec::new(),
+            });
+        }
+    }
+
+    /// Move a website to the end of another group
+    pub fn move_site(&mut self, site: &str, group: &str) -> Result<()> {
+        let target = self.group_index(group)?;
+        let (from, index) = self.site_location(site)?;
+        if from != target {
+            let site = self.groups[from].sites.remove(index);
+            self.groups[target].sites.push(site);
+        }
+        Ok(())
+    }
+
+    /// Put the named groups first, in the given order
+    ///
+    /// Groups that are not named keep their relative order after them.
+    pub fn order_groups(&mut self, names: &[&str]) -> Result<()> {
+        let indices = names.iter().map(|n| self.group_index(n)).collect::<Result<Vec<_>>>()?;
+        self.groups = reorder(std::mem::take(&mut self.groups), &indices);
+        Ok(())
+    }
+
+    /// Put the named websites first within a group, in the given order
+    ///
+    /// Websites that are not named keep their relative order after them.
+    pub fn order_sites(&mut self, group: &str, names: &[&str]) -> Result<()> {
+        let g = self.group_index(group)?;
+        let sites = &self.groups[g].sites;
+        let indices = names
+            .iter()
+            .map(|n| {
+                sites
+                    .iter()
+                    .position(|s| s.name == *n)
+                    .ok_or_else(|| Error::InvalidInput(format!("website {:?} is not in group {:?}", n, group)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.groups[g].sites = reorder(std::mem::take(&mut self.groups[g].sites), &indices);
+        Ok(())
+    }
+}
+
+fn reorder<T>(items: Vec<T>, first: &[usize]) -> Vec<T> {
+    let mut slots: Vec<Option<T>> = items.into_iter().map(Some).collect();
+    let mut ordered: Vec<T> = first.iter().filter_map(|&i| slots[i].take()).collect();
+    ordered.extend(slots.into_iter().flatten());
+    ordered
+}
+
+impl fmt::Display for GroupTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for group in &self.groups {
+            writeln!(f, "{}", group.name)?;
+            for site in &group.sites {
+                writeln!(f, "  {}", site.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single API call of a layout change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupOp {
+    /// Create a group (`create_website_group`)
+    CreateGroup { name: String },
+    /// Move a website into a group (`update_site_group`)
+    MoveSite { site_id: i32, site: String, group: String },
+    /// Move a group to a 1-based position (`sort_website_groups`)
+    SortGroup { group: String, position: i32 },
+    /// Move a website to a 1-based position within its group (`sort_websites`)
+    SortSite { site_id: i32, site: String, position: i32 },
+}
+
+impl fmt::Display for GroupOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupOp::CreateGroup { name } => write!(f, "+ create group {}", name),
+            GroupOp::MoveSite { site, group, .. } => write!(f, "> move {} to group {}", site, group),
+            GroupOp::SortGroup { group, position } => write!(f, "~ move group {} to position {}", group, position),
+            GroupOp::SortSite { site, position, .. } => write!(f, "~ move {} to position {}", site, position),
+        }
+    }
+}
+
+/// Indices of a longest increasing subsequence of `values`
+fn longest_increasing(values: &[usize]) -> HashSet<usize> {
+    // tails[k]: index of the smallest tail of an increasing run of length k + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev = vec![None; values.len()];
+    for i in 0..values.len() {
+        let k = tails.partition_point(|&t| values[t] < values[i]);
+        prev[i] = k.checked_sub(1).map(|k| tails[k]);
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+
+    let mut kept = HashSet::new();
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        kept.insert(i);
+        next = prev[i];
+    }
+    kept
+}
+
+/// Shortest sequence of single-item moves turning `current` into `desired`
+///
+/// Returns `(item, 1-based position)` pairs where each move takes the item
+/// out and reinserts it at the position, or `Error::InvalidInput` if the
+/// lists do not hold the same distinct items.
+pub fn sort_moves<T: Clone + Eq + Hash>(current: &[T], desired: &[T]) -> Result<Vec<(T, i32)>> {
+    let rank: HashMap<&T, usize> = desired.iter().enumerate().map(|(i, x)| (x, i)).collect();
+    let ranks: Option<Vec<usize>> = current.iter().map(|x| rank.get(x).copied()).collect();
+    let distinct = current.iter().collect::<HashSet<_>>().len() == current.len();
+    let ranks = match ranks {
+        Some(ranks) if distinct && rank.len() == desired.len() && current.len() == desired.len() => ranks,
+        _ => return Err(Error::InvalidInput("sort order lists differ or hold duplicates".to_string())),
+    };
+    let fixed: HashSet<&T> = longest_increasing(&ranks).into_iter().map(|i| &current[i]).collect();
+
+    let mut order: Vec<T> = current.to_vec();
+    let mut moves = Vec::new();
+    for (i, item) in desired.iter().enumerate() {
+        if fixed.contains(item) {
+            continue;
+        }
+        let from = order.iter().position(|x| x == item).expect("item is in the list");
+        order.remove(from);
+        let to = match i {
+            0 => 0,
+            _ => order.iter().position(|x| *x == desired[i - 1]).expect("item is in the list") + 1,
+        };
+        order.insert(to, item.clone());
+        moves.push((item.clone(), to as i32 + 1));
+    }
+    Ok(moves)
+}
+
+/// Compute the calls that turn the `current` layout into `desired`
+///
+/// Groups missing from `desired` are kept after the desired ones, and
+/// websites missing from `desired` stay where they are. Moved websites are
+/// assumed to be appended to their new group before sorting.
+pub fn plan_group_tree(current: &GroupTree, desired: &GroupTree) -> Result<Vec<GroupOp>> {
+    let mut ops = Vec::new();
+
+    for group in &desired.groups {
+        if current.group(&group.name).is_none() {
+            ops.push(GroupOp::CreateGroup { name: group.name.clone() });
+        }
+    }
+
+    // Layout after creations and moves, before sorting
+    let mut layout: Vec<(String, Vec<SiteRef>)> = current
+        .groups
+        .iter()
+        .map(|g| (g.name.clone(), g.sites.clone()))
+        .collect();
+    for group in &desired.groups {
+        if current.group(&group.name).is_none() {
+            layout.push((group.name.clone(), Vec::new()));
+        }
+    }
+
+    for group in &desired.groups {
+        for site in &group.sites {
+            let (from, index) = layout
+                .iter()
+                .enumerate()
+                .find_map(|(g, (_, sites))| sites.iter().position(|s| s.id == site.id).map(|i| (g, i)))
+                .ok_or_else(|| Error::InvalidInput(format!("unknown website {:?}", site.name)))?;
+            if layout[from].0 != group.name {
+                let moved = layout[from].1.remove(index);
+                let to = layout.iter().position(|(name, _)| *name == group.name).expect("group was added");
+                layout[to].1.push(moved);
+                ops.push(GroupOp::MoveSite {
+                    site_id: site.id,
+                    site: site.name.clone(),
+                    group: group.name.clone(),
+                });
+            }
+        }
+    }
+
+    let current_groups: Vec<String> = layout.iter().map(|(name, _)| name.clone()).collect();
+    let mut desired_groups: Vec<String> = desired.groups.iter().map(|g| g.name.clone()).collect();
+    desired_groups.extend(current_groups.iter().filter(|n| !desired_groups.contains(n)).cloned().collect::<Vec<_>>());
+    for (group, position) in sort_moves(&current_groups, &desired_groups)? {
+        ops.push(GroupOp::SortGroup { group, position });
+    }
+
+    for group in &desired.groups {
+        let (_, sites) = layout.iter().find(|(name, _)| *name == group.name).expect("group was added");
+        let current_sites: Vec<i32> = sites.iter().map(|s| s.id).collect();
+        let mut desired_sites: Vec<i32> = group.sites.iter().map(|s| s.id).collect();
+        desired_sites.extend(current_sites.iter().filter(|id| !desired_sites.contains(id)).copied().collect::<Vec<_>>());
+        for (site_id, position) in sort_moves(&current_sites, &desired_sites)? {
+            let site = sites.iter().find(|s| s.id == site_id).expect("site is in the group");
+            ops.push(GroupOp::SortSite {
+                site_id,
+                site: site.name.clone(),
+                position,
+            });
+        }
+    }
+
+    Ok(ops)
+}
+
+impl Client {
+    /// Load website group layout
+    ///
+    /// # Returns
+    ///
+    /// Returns groups and their websites in display order
+    pub async fn load_group_tree(&self) -> Result<GroupTree> {
+        let groups = self.list_website_groups().await?.data;
+        let sites = self.list_websites().await?.data;
+        Ok(GroupTree::from_listing(&groups, &sites))
+    }
+
+    /// Apply website group layout
+    ///
+    /// Plans the calls needed to reach `desired` from the current layout
+    /// and executes them in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `desired` - Desired layout, usually an edited `load_group_tree` result
+    ///
+    /// # Returns
+    ///
+    /// Returns the executed calls
+    pub async fn apply_group_tree(&self, desired: &GroupTree) -> Result<Vec<GroupOp>> {
+        let current = self.load_group_tree().await?;
+        let ops = plan_group_tree(&current, desired)?;
+
+        let mut ids: HashMap<String, i32> = current
+            .groups
+            .iter()
+            .filter_map(|g| g.id.map(|id| (g.name.clone(), id)))
+            .collect();
+        let group_id = |ids: &HashMap<String, i32>, name: &str| {
+            ids.get(name)
+                .copied()
+                .ok_or_else(|| Error::InvalidInput(format!("unknown website group {:?}", name)))
+        };
+
+        for op in &ops {
+            info!("group layout: {}", op);
+            match op {
+                GroupOp::CreateGroup { name } => {
+                    let id = self
+                        .create_website_group(&CreateGroupRequest { name: name.clone() })
+                        .await?
+                        .data;
+                    ids.insert(name.clone(), id);
+                }
+                GroupOp::MoveSite { site_id, group, .. } => {
+                    let group_id = group_id(&ids, group)?;
+                    self.update_site_group(*site_id, &UpdateSiteGroupRequest { group_id })
+                        .await?;
+                }
+                GroupOp::SortGroup { group, position } => {
+                    let id = group_id(&ids, group)?;
+                    self.sort_website_groups(id, &SortGroupRequest { position: *position })
+                        .await?;
+                }
+                GroupOp::SortSite { site_id, position, .. } => {
+                    self.sort_websites(*site_id, &SortWebsiteRequest { position: *position })
+                        .await?;
+                }
+            }
+        }
+
+        Ok(ops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_moves<T: Clone + Eq + Hash>(mut items: Vec<T>, moves: &[(T, i32)]) -> Vec<T> {
+        for (item, position) in moves {
+            let from = items.iter().position(|x| x == item).unwrap();
+            let item = items.remove(from);
+            items.insert(*position as usize - 1, item);
+        }
+        items
+    }
+
+    #[test]
+    fn test_sort_moves_are_minimal() {
+        let current = vec!['a', 'b', 'c', 'd', 'e'];
+        let desired = vec!['b', 'c', 'd', 'e', 'a'];
+        let moves = sort_moves(&current, &desired).unwrap();
+        assert_eq!(moves, vec![('a', 5)]);
+
+        let desired = vec!['e', 'b', 'a', 'd', 'c'];
+        let moves = sort_moves(&current, &desired).unwrap();
+        assert_eq!(moves.len(), 3);
+        assert_eq!(apply_moves(current.clone(), &moves), desired);
+
+        assert!(sort_moves(&current, &current).unwrap().is_empty());
+        assert!(sort_moves(&current, &['a', 'b', 'c', 'd']).is_err());
+        assert!(sort_moves(&current, &['a', 'b', 'c', 'd', 'x']).is_err());
+        assert!(sort_moves(&['a', 'a', 'b'], &['a', 'b', 'b']).is_err());
+    }
+
+    #[test]
+    fn test_from_listing_merges_ungrouped_sites() {
+        let site = |id: i32, group_id: i32, position: i32| -> WebsiteItem {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "server_names": [format!("s{}.example.com", id)],
+                "ports": ["80"],
+                "upstreams": [],
+                "group_id": group_id,
+                "is_enabled": true,
+                "mode": 0,
+                "title": "",
+                "position": position,
+                "created_at": "",
+                "updated_at": "",
+                "acl_enabled": false,
+            }))
+            .unwrap()
+        };
+        let groups = vec![WebsiteGroupItem {
+            id: 1,
+            name: "prod".to_string(),
+            position: 0,
+            site_count: 1,
+        }];
+        let sites = vec![site(10, 1, 0), site(11, 0, 2), site(12, 7, 1)];
+
+        let tree = GroupTree::from_listing(&groups, &sites);
+        assert_eq!(tree.groups.len(), 2);
+        let ungrouped = tree.group("").unwrap();
+        assert_eq!(ungrouped.id, None);
+        assert_eq!(ungrouped.sites.iter().map(|s| s.id).collect::<Vec<_>>(), vec![12, 11]);
+    }
+
+    #[test]
+    fn test_plan_group_tree() {
+        let site = |id, name: &str| SiteRef { id, name: name.to_string() };
+        let current = GroupTree {
+            groups: vec![
+                GroupNode {
+                    id: Some(1),
+                    name: "prod".to_string(),
+                    sites: vec![site(10, "a.example.com"), site(11, "b.example.com"), site(12, "c.example.com")],
+                },
+                GroupNode {
+                    id: Some(2),
+                    name: "staging".to_string(),
+                    sites: vec![site(20, "s.example.com")],
+                },
+            ],
+        };
+
+        let mut desired = current.clone();
+        desired.add_group("archive");
+        desired.move_site("b.example.com", "archive").unwrap();
+        desired.order_sites("prod", &["c.example.com"]).unwrap();
+        desired.order_groups(&["staging"]).unwrap();
+        assert!(desired.move_site("missing.example.com", "prod").is_err());
+
+        let ops = plan_group_tree(&current, &desired).unwrap();
+        assert_eq!(
+            ops,
+            vec![
+                GroupOp::CreateGroup { name: "archive".to_string() },
+                GroupOp::MoveSite {
+                    site_id: 11,
+                    site: "b.example.com".to_string(),
+                    group: "archive".to_string()
+                },
+                GroupOp::SortGroup {
+                    group: "prod".to_string(),
+                    position: 2
+                },
+                GroupOp::SortSite {
+                    site_id: 10,
+                    site: "a.example.com".to_string(),
+                    position: 2
+                },
+            ]
+        );
+        assert!(plan_group_tree(&desired, &desired).unwrap().is_empty());
+    }
+}
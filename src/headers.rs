@@ -0,0 +1,323 @@
+//! Typed HTTP header rewrite rules
+//!
+//! `ProxyConfig.http_headers` stores header operations with free-form `op`
+//! and `target` strings. `HeaderRules` is a typed view of that list: rules
+//! are built with set/add/remove helpers, header names and values are
+//! validated, and rules that contradict each other are reported before the
+//! list is written back to the website's proxy config.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use std::fmt;
+
+/// Which message a header rule applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeaderTarget {
+    Request,
+    Response,
+}
+
+impl HeaderTarget {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeaderTarget::Request => "request",
+            HeaderTarget::Response => "response",
+        }
+    }
+}
+
+/// What a header rule does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeaderOp {
+    /// Replace any existing value
+    Set,
+    /// Append a value, keeping existing ones
+    Add,
+    /// Drop the header
+    Remove,
+}
+
+impl HeaderOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeaderOp::Set => "set",
+            HeaderOp::Add => "add",
+            HeaderOp::Remove => "del",
+        }
+    }
+}
+
+/// A single typed header rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderRule {
+    pub target: HeaderTarget,
+    pub op: HeaderOp,
+    pub name: String,
+    pub value: String,
+}
+
+impl fmt::Display for HeaderRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.target.as_str(), self.op.as_str(), self.name)?;
+        if self.op != HeaderOp::Remove {
+            write!(f, ": {}", self.value)?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&HttpHeader> for HeaderRule {
+    type Error = Error;
+
+    /// A missing target is read as a response header rule
+    fn try_from(header: &HttpHeader) -> Result<Self> {
+        let target = match header.target.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            None | Some("") | Some("response") => HeaderTarget::Response,
+            Some("request") => HeaderTarget::Request,
+            Some(other) => return Err(Error::InvalidInput(format!("unknown header target {:?}", other))),
+        };
+        let op = match header.op.to_ascii_lowercase().as_str() {
+            "set" => HeaderOp::Set,
+            "add" | "append" => HeaderOp::Add,
+            "del" | "delete" | "remove" => HeaderOp::Remove,
+            other => return Err(Error::InvalidInput(format!("unknown header op {:?}", other))),
+        };
+
+        Ok(Self {
+            target,
+            op,
+            name: header.key.clone(),
+            value: header.val.clone(),
+        })
+    }
+}
+
+impl From<&HeaderRule> for HttpHeader {
+    fn from(rule: &HeaderRule) -> Self {
+        Self {
+            key: rule.name.clone(),
+            op: rule.op.as_str().to_string(),
+            val: rule.value.clone(),
+            target: Some(rule.target.as_str().to_string()),
+        }
+    }
+}
+
+/// Check a header name against the RFC 7230 token grammar
+pub fn validate_header_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidInput(format!("invalid header name {:?}", name)))
+    }
+}
+
+/// Check that a header value contains no line breaks or control characters
+pub fn validate_header_value(value: &str) -> Result<()> {
+    if value.chars().any(|c| c.is_control() && c != '\t') {
+        return Err(Error::InvalidInput(format!("invalid header value {:?}", value)));
+    }
+    Ok(())
+}
+
+/// Two rules that affect the same header in contradicting ways
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderConflict {
+    pub first: HeaderRule,
+    pub second: HeaderRule,
+}
+
+impl fmt::Display for HeaderConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\" conflicts with \"{}\"", self.first, self.second)
+    }
+}
+
+/// An ordered list of header rules
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderRules {
+    pub rules: Vec<HeaderRule>,
+}
+
+impl HeaderRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the rules of a proxy config
+    pub fn from_proxy(proxy: &ProxyConfig) -> Result<Self> {
+        let rules = proxy
+            .http_headers
+            .iter()
+            .flat_map(|headers| &headers.value)
+            .map(HeaderRule::try_from)
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    fn push(mut self, target: HeaderTarget, op: HeaderOp, name: &str, value: &str) -> Self {
+        self.rules.push(HeaderRule {
+            target,
+            op,
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+
+    pub fn set_request(self, name: &str, value: &str) -> Self {
+        self.push(HeaderTarget::Request, HeaderOp::Set, name, value)
+    }
+
+    pub fn add_request(self, name: &str, value: &str) -> Self {
+        self.push(HeaderTarget::Request, HeaderOp::Add, name, value)
+    }
+
+    pub fn remove_request(self, name: &str) -> Self {
+        self.push(HeaderTarget::Request, HeaderOp::Remove, name, "")
+    }
+
+    pub fn set_response(self, name: &str, value: &str) -> Self {
+        self.push(HeaderTarget::Response, HeaderOp::Set, name, value)
+    }
+
+    pub fn add_response(self, name: &str, value: &str) -> Self {
+        self.push(HeaderTarget::Response, HeaderOp::Add, name, value)
+    }
+
+    pub fn remove_response(self, name: &str) -> Self {
+        self.push(HeaderTarget::Response, HeaderOp::Remove, name, "")
+    }
+
+    /// Drop every rule for a header
+    pub fn without(mut self, target: HeaderTarget, name: &str) -> Self {
+        self.rules
+            .retain(|r| !(r.target == target && r.name.eq_ignore_ascii_case(name)));
+        self
+    }
+
+    /// Validate every rule's header name and value
+    pub fn validate(&self) -> Result<()> {
+        for rule in &self.rules {
+            validate_header_name(&rule.name)?;
+            validate_header_value(&rule.value)?;
+        }
+        Ok(())
+    }
+
+    /// Pairs of rules on the same header where at least one sets or removes
+    /// it; several `add` rules for one header are not a conflict
+    pub fn conflicts(&self) -> Vec<HeaderConflict> {
+        let mut conflicts = Vec::new();
+        for (i, first) in self.rules.iter().enumerate() {
+            for second in &self.rules[i + 1..] {
+                if first.target == second.target
+                    && first.name.eq_ignore_ascii_case(&second.name)
+                    && !(first.op == HeaderOp::Add && second.op == HeaderOp::Add)
+                {
+                    conflicts.push(HeaderConflict {
+                        first: first.clone(),
+                        second: second.clone(),
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Validate the rules and reject conflicts
+    pub fn check(&self) -> Result<()> {
+        self.validate()?;
+        let conflicts = self.conflicts();
+        if !conflicts.is_empty() {
+            let list: Vec<String> = conflicts.iter().map(|c| c.to_string()).collect();
+            return Err(Error::InvalidInput(format!("conflicting header rules: {}", list.join("; "))));
+        }
+        Ok(())
+    }
+
+    /// Convert to the representation used by `ProxyConfig`
+    pub fn to_http_headers(&self) -> Vec<HttpHeader> {
+        self.rules.iter().map(HttpHeader::from).collect()
+    }
+}
+
+impl Client {
+    /// Get website header rules
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    ///
+    /// # Returns
+    ///
+    /// Returns the typed header rules of the website's proxy config
+    pub async fn get_website_headers(&self, id: i32) -> Result<HeaderRules> {
+        HeaderRules::from_proxy(&self.get_website_proxy(id).await?)
+    }
+
+    /// Set website header rules
+    ///
+    /// Checks the rules, then replaces the header list of the website's
+    /// proxy config. All other proxy settings are kept as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `rules` - New header rules
+    ///
+    /// # Returns
+    ///
+    /// Returns the updated proxy config
+    pub async fn set_website_headers(&self, id: i32, rules: &HeaderRules) -> Result<ProxyConfig> {
+        rules.check()?;
+        let proxy = self.get_website_proxy(id).await?;
+        self.put_website_proxy(
+            id,
+            &ProxyConfig {
+                http_headers: Some(ProxyItemHttpHeaders {
+                    global: false,
+                    value: rules.to_http_headers(),
+                }),
+                ..proxy
+            },
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_rules() {
+        let rules = HeaderRules::new()
+            .set_request("X-Forwarded-Proto", "https")
+            .add_response("Set-Cookie", "a=1")
+            .add_response("set-cookie", "b=2")
+            .remove_response("Server");
+        assert!(rules.check().is_ok());
+
+        let headers = rules.to_http_headers();
+        assert_eq!(headers[3].op, "del");
+        assert_eq!(headers[0].target.as_deref(), Some("request"));
+        let proxy_rules: Vec<HeaderRule> = headers.iter().map(|h| HeaderRule::try_from(h).unwrap()).collect();
+        assert_eq!(proxy_rules, rules.rules);
+
+        let conflicting = rules.clone().set_response("server", "nginx");
+        let conflicts = conflicting.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].first.name, "Server");
+        assert!(conflicting.check().is_err());
+        assert!(conflicting.without(HeaderTarget::Response, "SERVER").check().is_ok());
+
+        assert!(HeaderRules::new().set_request("Bad Header", "x").check().is_err());
+        assert!(HeaderRules::new().set_request("X-Ok", "a\r\nInjected: 1").check().is_err());
+    }
+}
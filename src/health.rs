@@ -0,0 +1,337 @@
+//! Upstream health watcher
+//!
+//! `HealthWatcher` periodically polls the health state of every website,
+//! keeps a per-upstream history of samples and reports up/down transitions
+//! as a `Stream` of events. Availability over a time window is computed
+//! from the recorded samples.
+
+use crate::client::Client;
+use crate::error::Result;
+use crate::models::*;
+use futures::stream::{self, Stream};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Health of an upstream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Up,
+    Down,
+    /// The server did not report a recognizable state
+    Unknown,
+}
+
+/// `HealthState.state` of an upstream that passes its health check
+pub const STATE_HEALTHY: i32 = 0;
+/// `HealthState.state` of an upstream that fails its health check
+pub const STATE_UNHEALTHY: i32 = 1;
+
+impl Health {
+    /// Interpret the health state reported for an upstream
+    ///
+    /// The state code decides: a healthy code with an error message is
+    /// still down, and codes other than `STATE_HEALTHY` and
+    /// `STATE_UNHEALTHY` are `Unknown`.
+    pub fn from_state(state: &HealthState) -> Self {
+        match state.state {
+            STATE_HEALTHY if state.error.as_deref().is_some_and(|e| !e.is_empty()) => Health::Down,
+            STATE_HEALTHY => Health::Up,
+            STATE_UNHEALTHY => Health::Down,
+            _ => Health::Unknown,
+        }
+    }
+
+    /// Interpret a health check result, which is empty for a healthy
+    /// upstream and an error message otherwise
+    pub fn from_check(result: &str) -> Self {
+        if result.is_empty() {
+            Health::Up
+        } else {
+            Health::Down
+        }
+    }
+}
+
+/// An upstream of a website
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target {
+    pub site_id: i32,
+    pub upstream: String,
+}
+
+/// A single health observation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub at: SystemTime,
+    pub health: Health,
+    /// State code as reported by the server, if any
+    pub state: Option<i32>,
+}
+
+/// An upstream changed between up and down
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthEvent {
+    pub target: Target,
+    pub from: Health,
+    pub to: Health,
+    pub at: SystemTime,
+}
+
+/// Polls website health and keeps per-upstream history
+#[derive(Debug, Clone)]
+pub struct HealthWatcher {
+    client: Client,
+    interval: Duration,
+    retention: Duration,
+    checks: Vec<WebsiteHealthCheckRequest>,
+    history: BTreeMap<Target, VecDeque<Sample>>,
+}
+
+impl HealthWatcher {
+    /// Create a watcher polling every `interval`, keeping one day of history
+    pub fn new(client: Client, interval: Duration) -> Self {
+        Self {
+            client,
+            interval,
+            retention: Duration::from_secs(24 * 60 * 60),
+            checks: Vec::new(),
+            history: BTreeMap::new(),
+        }
+    }
+
+    /// Set how long samples are kept
+    pub fn retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Trigger a health check for the given hosts and upstreams on every poll
+    pub fn check(mut self, hosts: Vec<String>, upstreams: Vec<String>) -> Self {
+        self.checks.push(WebsiteHealthCheckRequest { hosts, upstreams });
+        self
+    }
+
+    /// Poll once
+    ///
+    /// Triggers the configured health checks, reads the health state of
+    /// every website and records a sample for each upstream. Health check
+    /// results take precedence over the reported state.
+    ///
+    /// # Returns
+    ///
+    /// Returns the up/down transitions observed by this poll
+    pub async fn poll(&mut self) -> Result<Vec<HealthEvent>> {
+        let mut checked = HashMap::new();
+        for check in &self.checks {
+            match self.client.website_health_check(check).await {
+                Ok(results) => checked.extend(results),
+                Err(e) => warn!("health check of {:?} failed: {}", check.upstreams, e),
+            }
+        }
+
+        let sites = self.client.list_websites().await?.data;
+        Ok(self.record(SystemTime::now(), &sites, &checked))
+    }
+
+    /// Record the health state of the given websites
+    ///
+    /// Every upstream reported in a site's `health_state` or listed in its
+    /// `upstreams` gets a sample; `checked` holds health check results by
+    /// upstream. Returns the up/down transitions compared to the last known
+    /// state of each upstream. `Unknown` samples are kept but never cause an
+    /// event.
+    pub fn record(&mut self, at: SystemTime, sites: &[WebsiteItem], checked: &HashMap<String, String>) -> Vec<HealthEvent> {
+        let mut events = Vec::new();
+        let cutoff = at.checked_sub(self.retention);
+
+        for site in sites {
+            let states = site.health_state.clone().unwrap_or_default();
+            let upstreams: BTreeSet<&String> = states.keys().chain(&site.upstreams).collect();
+
+            for upstream in upstreams {
+                let state = states.get(upstream);
+                let health = match (checked.get(upstream), state) {
+                    (Some(result), _) => Health::from_check(result),
+                    (None, Some(state)) => Health::from_state(state),
+                    (None, None) => Health::Unknown,
+                };
+                let target = Target {
+                    site_id: site.id,
+                    upstream: upstream.clone(),
+                };
+                let samples = self.history.entry(target.clone()).or_default();
+
+                let last = samples.iter().rev().map(|s| s.health).find(|h| *h != Health::Unknown);
+                if let Some(from) = last {
+                    if health != Health::Unknown && health != from {
+                        info!("upstream {} of site {}: {:?} -> {:?}", upstream, site.id, from, health);
+                        events.push(HealthEvent {
+                            target,
+                            from,
+                            to: health,
+                            at,
+                        });
+                    }
+                }
+
+                samples.push_back(Sample {
+                    at,
+                    health,
+                    state: state.map(|s| s.state),
+                });
+                if let Some(cutoff) = cutoff {
+                    while samples.front().is_some_and(|s| s.at < cutoff) {
+                        samples.pop_front();
+                    }
+                }
+            }
+        }
+
+        events
+    }
+
+    /// Recorded samples of an upstream, oldest first
+    pub fn history(&self, target: &Target) -> impl Iterator<Item = &Sample> {
+        self.history.get(target).into_iter().flatten()
+    }
+
+    /// Upstreams with recorded samples
+    pub fn targets(&self) -> impl Iterator<Item = &Target> {
+        self.history.keys()
+    }
+
+    /// Availability of an upstream over the last `window`
+    ///
+    /// Returns the percentage of known samples in the window that were up,
+    /// or `None` if there are none.
+    pub fn availability(&self, target: &Target, window: Duration) -> Option<f64> {
+        let since = SystemTime::now().checked_sub(window).unwrap_or(SystemTime::UNIX_EPOCH);
+        self.availability_since(target, since)
+    }
+
+    /// Availability of an upstream since the given time
+    pub fn availability_since(&self, target: &Target, since: SystemTime) -> Option<f64> {
+        let (up, known) = self
+            .history(target)
+            .filter(|s| s.at >= since && s.health != Health::Unknown)
+            .fold((0usize, 0usize), |(up, known), s| (up + (s.health == Health::Up) as usize, known + 1));
+
+        (known > 0).then(|| up as f64 * 100.0 / known as f64)
+    }
+
+    /// Poll forever, yielding transitions as they are observed
+    ///
+    /// Poll errors are yielded as `Err` items; the watcher keeps polling
+    /// after an error.
+    pub fn events(self) -> impl Stream<Item = Result<HealthEvent>> {
+        let ticker = tokio::time::interval(self.interval);
+        stream::unfold((self, ticker, VecDeque::new()), |(mut watcher, mut ticker, mut pending)| async move {
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some((Ok(event), (watcher, ticker, pending)));
+                }
+                ticker.tick().await;
+                match watcher.poll().await {
+                    Ok(events) => pending.extend(events),
+                    Err(e) => return Some((Err(e), (watcher, ticker, pending))),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(error: Option<&str>) -> WebsiteItem {
+        let health_state = match error {
+            Some(error) => {
+                let state = if error.is_empty() { STATE_HEALTHY } else { STATE_UNHEALTHY };
+                serde_json::json!({ "http://10.0.0.1:8080": { "state": state, "error": error } })
+            }
+            None => serde_json::json!({}),
+        };
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "server_names": ["app.example.com"],
+            "ports": ["80"],
+            "upstreams": ["http://10.0.0.1:8080"],
+            "group_id": 0,
+            "is_enabled": true,
+            "mode": 0,
+            "title": "",
+            "position": 0,
+            "created_at": "",
+            "updated_at": "",
+            "acl_enabled": false,
+            "health_state": health_state,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_transitions_and_availability() {
+        let mut watcher = HealthWatcher::new(Client::new("http://localhost", "token"), Duration::from_secs(10));
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let at = |secs| start + Duration::from_secs(secs);
+        let none = HashMap::new();
+
+        assert!(watcher.record(at(0), &[site(Some(""))], &none).is_empty());
+        assert!(watcher.record(at(10), &[site(None)], &none).is_empty());
+        let events = watcher.record(at(20), &[site(Some("connection refused"))], &none);
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].from, events[0].to), (Health::Up, Health::Down));
+        assert!(watcher.record(at(30), &[site(Some("timeout"))], &none).is_empty());
+
+        // A health check result overrides the reported state
+        let checked = HashMap::from([("http://10.0.0.1:8080".to_string(), String::new())]);
+        assert_eq!(watcher.record(at(40), &[site(Some("timeout"))], &checked)[0].to, Health::Up);
+
+        let target = watcher.targets().next().unwrap().clone();
+        assert_eq!(watcher.history(&target).count(), 5);
+        assert_eq!(watcher.history(&target).next().unwrap().state, Some(STATE_HEALTHY));
+        assert_eq!(watcher.availability_since(&target, at(0)), Some(50.0));
+        assert_eq!(watcher.availability_since(&target, at(30)), Some(50.0));
+        assert_eq!(watcher.availability_since(&target, at(50)), None);
+    }
+
+    #[test]
+    fn test_from_state() {
+        let state = |state, error: &str| HealthState {
+            state,
+            error: Some(error.to_string()),
+        };
+        assert_eq!(Health::from_state(&state(STATE_HEALTHY, "")), Health::Up);
+        assert_eq!(Health::from_state(&state(STATE_HEALTHY, "refused")), Health::Down);
+        assert_eq!(Health::from_state(&state(STATE_UNHEALTHY, "")), Health::Down);
+        assert_eq!(Health::from_state(&state(7, "")), Health::Unknown);
+    }
+
+    #[test]
+    fn test_upstreams_from_health_state() {
+        let mut watcher = HealthWatcher::new(Client::new("http://localhost", "token"), Duration::from_secs(10));
+        let mut item = site(Some(""));
+        item.health_state.as_mut().unwrap().insert(
+            "http://10.0.0.2:8080".to_string(),
+            HealthState {
+                state: 0,
+                error: Some("refused".to_string()),
+            },
+        );
+        watcher.record(SystemTime::UNIX_EPOCH, &[item], &HashMap::new());
+
+        let health: Vec<_> = watcher
+            .targets()
+            .map(|t| (t.upstream.clone(), watcher.history(t).next().unwrap().health))
+            .collect();
+        assert_eq!(
+            health,
+            vec![
+                ("http://10.0.0.1:8080".to_string(), Health::Up),
+                ("http://10.0.0.2:8080".to_string(), Health::Down),
+            ]
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use crate::client::Client;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::models::*;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+use tracing::info;
 
 impl Client {
     /// Create IP Group (POST /open/ipgroup)
@@ -139,8 +142,308 @@ impl Client {
         params.insert("comment".to_string(), request.comment.clone());
         params.insert("url".to_string(), request.url.clone());
         
-        self.get_with_query("/open/ipgroup/link", &params).await
+        self.post_with_query("/open/ipgroup/link", &params).await
+    }
+
+    /// Sync a URL-backed IP group
+    ///
+    /// Creates an IP group from `url` if none references it yet, otherwise
+    /// re-fetches the link and replaces the group's IPs. Polls until the
+    /// server reports the fetched group.
+    ///
+    /// # Arguments
+    ///
+    /// * `comment` - Comment used when the group has to be created
+    /// * `url` - Link the IP list is fetched from
+    /// * `options` - Polling options
+    ///
+    /// # Returns
+    ///
+    /// Returns the group ID, its current IPs and the diff against the previous contents
+    pub async fn ip_group_sync_link(&self, comment: &str, url: &str, options: &PollOptions) -> Result<LinkSyncResult> {
+        let existing = self.ip_group_find_by_reference(url).await?;
+
+        match existing {
+            Some(group) => {
+                let id = group.id.ok_or_else(|| Error::InvalidResponse("IP group without id".to_string()))?;
+                let previous = self.ip_group_ip_set(id).await?;
+
+                let fetched = self
+                    .ip_group_link(&IPGroupLinkRequest { href: url.to_string() })
+                    .await?;
+                let ips = IpSet::from_iter(fetched.data.ips);
+
+                self.ip_group_update(&IPGroupUpdateRequest {
+                    id,
+                    builtin: group.builtin,
+                    comment: group.comment.clone(),
+                    ips: Some(ips.to_vec()),
+                    reference: group.reference.clone(),
+                })
+                .await?;
+
+                let ips = self
+                    .ip_group_poll(id, options, |detail| IpSet::from_group(detail) == ips)
+                    .await?;
+                Ok(LinkSyncResult {
+                    id,
+                    created: false,
+                    diff: previous.diff(&ips),
+                    ips,
+                })
+            }
+            None => {
+                self.ip_group_create_by_link(&IPGroupCreateByLinkRequest {
+                    comment: comment.to_string(),
+                    url: url.to_string(),
+                })
+                .await?;
+
+                let deadline = Instant::now() + options.timeout;
+                let id = loop {
+                    if let Some(id) = self.ip_group_find_by_reference(url).await?.and_then(|g| g.id) {
+                        break id;
+                    }
+                    if Instant::now() >= deadline {
+                        return Err(Error::Timeout(format!("IP group for {} was not created", url)));
+                    }
+                    sleep(options.interval).await;
+                };
+
+                // The group exists before the link has been fetched; it is
+                // done once it has IPs or has been updated since
+                let created = self.ip_group_detail(&IPGroupDetailRequest { id }).await?.data;
+                let ips = self
+                    .ip_group_poll(id, options, |detail| {
+                        detail.ips.as_ref().is_some_and(|ips| !ips.is_empty())
+                            || detail.updated_at != created.updated_at
+                    })
+                    .await?;
+                Ok(LinkSyncResult {
+                    id,
+                    created: true,
+                    diff: IpSet::default().diff(&ips),
+                    ips,
+                })
+            }
+        }
+    }
+
+    /// Refresh the search engine spider group
+    ///
+    /// Triggers a spider IP update and waits until the group has been refreshed.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Polling options
+    ///
+    /// # Returns
+    ///
+    /// Returns the spider group ID, its new size and the diff against the previous contents
+    pub async fn ip_group_crawler_refresh(&self, options: &PollOptions) -> Result<CrawlerRefreshResult> {
+        let id = self.ip_group_crawler().await?.data;
+        let before = self.ip_group_detail(&IPGroupDetailRequest { id }).await?.data;
+        let previous = IpSet::from_group(&before);
+
+        self.ip_group_crawler_update().await?;
+
+        let ips = self
+            .ip_group_poll(id, options, |detail| detail.updated_at != before.updated_at)
+            .await?;
+        Ok(CrawlerRefreshResult {
+            id,
+            total: ips.len(),
+            diff: previous.diff(&ips),
+        })
+    }
+
+    /// Find an IP group by comment
+    ///
+    /// # Arguments
+    ///
+    /// * `comment` - Group comment, which the UI shows as the group name
+    ///
+    /// # Returns
+    ///
+    /// Returns the first group with that comment, if any
+    pub async fn ip_group_find_by_comment(&self, comment: &str) -> Result<Option<IPGroup>> {
+        Ok(self
+            .ip_group_list_all()
+            .await?
+            .into_iter()
+            .find(|group| group.comment.as_deref() == Some(comment)))
+    }
+
+    /// Find an IP group by comment, or create it
+    ///
+    /// An existing group is left as it is; a new one is created from
+    /// `request`.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Group to create; its comment is the lookup key
+    ///
+    /// # Returns
+    ///
+    /// Returns the group ID and whether the group was created
+    pub async fn ip_group_find_or_create(&self, request: &IPGroupCreateRequest) -> Result<(i32, bool)> {
+        let comment = request
+            .comment
+            .as_deref()
+            .ok_or_else(|| Error::InvalidInput("IP group has no comment".to_string()))?;
+        if let Some(group) = self.ip_group_find_by_comment(comment).await? {
+            let id = group.id.ok_or_else(|| Error::InvalidResponse("IP group without id".to_string()))?;
+            return Ok((id, false));
+        }
+        let id = self.ip_group_create(request).await?.data;
+        info!("created IP group {} ({})", comment, id);
+        Ok((id, true))
+    }
+
+    /// List every IP group
+    ///
+    /// The list endpoint has no paging, only a `top` limit; if the default
+    /// listing holds fewer groups than `total`, it is fetched again with
+    /// `top` set to the total.
+    async fn ip_group_list_all(&self) -> Result<Vec<IPGroup>> {
+        let groups = self.ip_group_list(&IPGroupListRequest { top: None }).await?;
+        if groups.nodes.len() >= groups.total as usize {
+            return Ok(groups.nodes);
+        }
+        let groups = self
+            .ip_group_list(&IPGroupListRequest { top: Some(groups.total) })
+            .await?;
+        Ok(groups.nodes)
+    }
+
+    /// Find an IP group whose reference equals `reference`
+    async fn ip_group_find_by_reference(&self, reference: &str) -> Result<Option<IPGroup>> {
+        Ok(self
+            .ip_group_list_all()
+            .await?
+            .into_iter()
+            .find(|group| group.reference.as_deref() == Some(reference)))
+    }
+
+    /// Fetch the IPs of a group as an `IpSet`
+    async fn ip_group_ip_set(&self, id: i32) -> Result<IpSet> {
+        let detail = self.ip_group_detail(&IPGroupDetailRequest { id }).await?;
+        Ok(IpSet::from_group(&detail.data))
+    }
+
+    /// Poll group detail until `done` returns true or the timeout is reached
+    async fn ip_group_poll<F>(&self, id: i32, options: &PollOptions, done: F) -> Result<IpSet>
+    where
+        F: Fn(&IPGroup) -> bool,
+    {
+        let deadline = Instant::now() + options.timeout;
+        loop {
+            let detail = self.ip_group_detail(&IPGroupDetailRequest { id }).await?.data;
+            if done(&detail) {
+                return Ok(IpSet::from_group(&detail));
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout(format!("IP group {} was not refreshed", id)));
+            }
+            sleep(options.interval).await;
+        }
+    }
+}
+
+/// Polling options for asynchronous IP group operations
+#[derive(Debug, Clone)]
+pub struct PollOptions {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for PollOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(2),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Normalized set of IP addresses and CIDR ranges
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpSet(BTreeSet<String>);
+
+impl IpSet {
+    /// Build a set from the IPs of an IP group
+    pub fn from_group(group: &IPGroup) -> Self {
+        group.ips.iter().flatten().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, ip: &str) -> bool {
+        self.0.contains(ip.trim())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.0.iter()
+    }
+
+    pub fn to_vec(&self) -> Vec<String> {
+        self.0.iter().cloned().collect()
     }
+
+    /// Compute the changes needed to go from `self` to `other`
+    pub fn diff(&self, other: &IpSet) -> IpSetDiff {
+        IpSetDiff {
+            added: other.0.difference(&self.0).cloned().collect(),
+            removed: self.0.difference(&other.0).cloned().collect(),
+        }
+    }
+}
+
+impl FromIterator<String> for IpSet {
+    fn from_iter<I: IntoIterator<Item = String>>(iter: I) -> Self {
+        IpSet(
+            iter.into_iter()
+                .map(|ip| ip.trim().to_string())
+                .filter(|ip| !ip.is_empty())
+                .collect(),
+        )
+    }
+}
+
+/// Difference between two IP sets
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpSetDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl IpSetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Result of syncing a URL-backed IP group
+#[derive(Debug, Clone)]
+pub struct LinkSyncResult {
+    pub id: i32,
+    pub created: bool,
+    pub ips: IpSet,
+    pub diff: IpSetDiff,
+}
+
+/// Result of refreshing the search engine spider group
+#[derive(Debug, Clone)]
+pub struct CrawlerRefreshResult {
+    pub id: i32,
+    pub total: usize,
+    pub diff: IpSetDiff,
 }
 
 #[cfg(test)]
@@ -203,4 +506,22 @@ mod tests {
         };
         let _create_by_link_req_serialized = serde_json::to_string(&create_by_link_req).unwrap();
     }
+
+    #[test]
+    fn test_ip_set_diff() {
+        let old: IpSet = vec!["10.0.0.1".to_string(), " 10.0.0.2 ".to_string(), "".to_string()]
+            .into_iter()
+            .collect();
+        let new: IpSet = vec!["10.0.0.2".to_string(), "192.168.0.0/16".to_string()]
+            .into_iter()
+            .collect();
+
+        assert_eq!(old.len(), 2);
+        assert!(old.contains("10.0.0.2"));
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec!["192.168.0.0/16".to_string()]);
+        assert_eq!(diff.removed, vec!["10.0.0.1".to_string()]);
+        assert!(new.diff(&new).is_empty());
+    }
 }
\ No newline at end of file
@@ -25,19 +25,40 @@
 //! ```
 
 pub mod acl;
+pub mod acl_bans;
+pub mod acl_rule;
+pub mod attack_detail;
 pub mod auth;
 pub mod cert;
+pub mod challenge_stats;
 pub mod client;
+pub mod correlate;
 pub mod error;
+pub mod excludes;
+mod glob;
+pub mod group_tree;
+pub mod headers;
+pub mod health;
 pub mod ipgroup;
 pub mod ja4;
 pub mod models;
+pub mod mode_change;
+pub mod nginx;
 pub mod other;
+mod paging;
 pub mod policy;
+pub mod reconcile;
 pub mod record;
+pub mod responder;
 pub mod site;
+pub mod site_clone;
+pub mod site_config;
+pub mod site_log;
 pub mod stat;
+pub mod static_sync;
 pub mod system;
+pub mod tls;
+mod time;
 
 pub use client::Client;
 pub use error::{Error, Result};
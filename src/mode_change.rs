@@ -0,0 +1,210 @@
+//! Bulk protection mode switching with rollback
+//!
+//! A `ModeChange` records the current mode of every selected website (and
+//! optionally the global mode) before switching them, so the change can be
+//! reverted later. The record is serializable and can be saved to disk,
+//! allowing a different process to perform the rollback.
+
+use crate::client::Client;
+use crate::error::Result;
+use crate::glob::glob_match;
+use crate::models::*;
+use crate::time::unix_now;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Selects the websites affected by a mode change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SiteSelector {
+    /// Every website
+    All,
+    /// Websites in the given group
+    Group(i32),
+    /// Websites with a server name matching the glob, e.g. `*.example.com`
+    ServerName(String),
+}
+
+impl SiteSelector {
+    /// Check whether the selector matches a website
+    pub fn matches(&self, site: &WebsiteItem) -> bool {
+        match self {
+            SiteSelector::All => true,
+            SiteSelector::Group(id) => site.group_id == *id,
+            SiteSelector::ServerName(pattern) => site.server_names.iter().any(|name| glob_match(pattern, name)),
+        }
+    }
+}
+
+/// Mode of a website before the change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SiteModeRecord {
+    pub id: i32,
+    pub server_names: Vec<String>,
+    pub mode: i32,
+}
+
+/// A recorded mode change that can be rolled back
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModeChange {
+    /// Unix timestamp of when the change was applied
+    pub applied_at: i64,
+    /// Mode the websites were switched to
+    pub mode: i32,
+    /// Global mode before the change, if it was switched too
+    pub global_mode: Option<i32>,
+    /// Websites and their modes before the change
+    pub sites: Vec<SiteModeRecord>,
+}
+
+impl ModeChange {
+    /// Save the record as JSON so another process can roll it back
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Load a record saved with `save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Website IDs grouped by their mode before the change
+    pub fn previous_modes(&self) -> BTreeMap<i32, Vec<i32>> {
+        let mut modes: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+        for site in &self.sites {
+            modes.entry(site.mode).or_default().push(site.id);
+        }
+        modes
+    }
+}
+
+impl Client {
+    /// Begin mode change
+    ///
+    /// Records the current mode of every website matched by `selector`,
+    /// then switches them to `mode`. With `include_global` the global mode
+    /// is recorded and switched as well. If switching fails, the recorded
+    /// modes are restored before the error is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `selector` - Websites to switch
+    /// * `mode` - New protection mode
+    /// * `include_global` - Also switch the global mode
+    ///
+    /// # Returns
+    ///
+    /// Returns the record needed to roll the change back
+    pub async fn begin_mode_change(&self, selector: &SiteSelector, mode: i32, include_global: bool) -> Result<ModeChange> {
+        let sites = self
+            .list_websites()
+            .await?
+            .data
+            .into_iter()
+            .filter(|site| selector.matches(site))
+            .map(|site| SiteModeRecord {
+                id: site.id,
+                server_names: site.server_names,
+                mode: site.mode,
+            })
+            .collect();
+
+        let global_mode = if include_global {
+            Some(self.get_global_mode().await?.mode)
+        } else {
+            None
+        };
+
+        let change = ModeChange {
+            applied_at: unix_now(),
+            mode,
+            global_mode,
+            sites,
+        };
+
+        if let Err(e) = self.apply_mode_change(&change).await {
+            warn!("mode change to {} failed: {}, restoring previous modes", mode, e);
+            if let Err(rollback) = self.rollback_mode_change(&change).await {
+                warn!("restoring previous modes failed: {}", rollback);
+            }
+            return Err(e);
+        }
+
+        info!("switched {} websites to mode {}", change.sites.len(), mode);
+        Ok(change)
+    }
+
+    /// Roll back mode change
+    ///
+    /// Restores the modes recorded by `begin_mode_change`.
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - Recorded mode change
+    pub async fn rollback_mode_change(&self, change: &ModeChange) -> Result<()> {
+        for (mode, ids) in change.previous_modes() {
+            self.put_website_mode(&PutWebsiteModeRequest { ids, mode }).await?;
+        }
+
+        if let Some(mode) = change.global_mode {
+            self.set_global_mode(&DetectorRequest { mode, ts: unix_now() })
+                .await?;
+        }
+
+        info!("restored modes of {} websites", change.sites.len());
+        Ok(())
+    }
+
+    async fn apply_mode_change(&self, change: &ModeChange) -> Result<()> {
+        if !change.sites.is_empty() {
+            self.put_website_mode(&PutWebsiteModeRequest {
+                ids: change.sites.iter().map(|site| site.id).collect(),
+                mode: change.mode,
+            })
+            .await?;
+        }
+
+        if change.global_mode.is_some() {
+            self.set_global_mode(&DetectorRequest {
+                mode: change.mode,
+                ts: unix_now(),
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_previous_modes_and_persistence() {
+        let change = ModeChange {
+            applied_at: 1_700_000_000,
+            mode: 1,
+            global_mode: Some(0),
+            sites: vec![
+                SiteModeRecord { id: 1, server_names: vec!["a.example.com".to_string()], mode: 0 },
+                SiteModeRecord { id: 2, server_names: vec!["b.example.com".to_string()], mode: 2 },
+                SiteModeRecord { id: 3, server_names: vec!["c.example.com".to_string()], mode: 0 },
+            ],
+        };
+
+        let modes = change.previous_modes();
+        assert_eq!(modes[&0], vec![1, 3]);
+        assert_eq!(modes[&2], vec![2]);
+
+        let path = std::env::temp_dir().join(format!("safeline-mode-change-{}.json", std::process::id()));
+        change.save(&path).unwrap();
+        let loaded = ModeChange::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, change);
+    }
+}
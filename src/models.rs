@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 // ============================================================================
 // IP Group Models
@@ -292,14 +293,15 @@ pub struct WebsiteItem {
     pub cc_bot: Option<bool>,
     pub tamper_refresh: Option<i32>,
     pub tamper_refresh_state: Option<i32>,
-    pub health_state: Option<HealthState>,
+    /// Health state keyed by upstream
+    pub health_state: Option<HashMap<String, HealthState>>,
     pub init: Option<bool>,
     pub wr_id: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealthState {
-    pub state: String,
+    pub state: i32,
     pub error: Option<String>,
 }
 
@@ -374,7 +376,8 @@ pub struct WebsiteDetailData {
     pub cc_bot: Option<bool>,
     pub tamper_refresh: Option<i32>,
     pub tamper_refresh_state: Option<i32>,
-    pub health_state: Option<HealthState>,
+    /// Health state keyed by upstream
+    pub health_state: Option<HashMap<String, HealthState>>,
     pub init: Option<bool>,
     pub wr_id: Option<i32>,
 }
@@ -399,7 +402,7 @@ pub struct PutWebsiteDefenseRequest {
     pub id: i32,
     pub enable: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub pattern: Option<String>,
+    pub pattern: Option<Pattern>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub negate: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -449,7 +452,7 @@ pub struct WebsiteChallengeRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub level: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub pattern: Option<String>,
+    pub pattern: Option<Pattern>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub negate: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -514,13 +517,15 @@ pub struct DeleteGroupRequest {
 /// Sort group request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SortGroupRequest {
-    pub position: Vec<i32>,
+    /// New 1-based position
+    pub position: i32,
 }
 
 /// Sort website request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SortWebsiteRequest {
-    pub position: Vec<i32>,
+    /// New 1-based position
+    pub position: i32,
 }
 
 /// Group switch request
@@ -587,13 +592,15 @@ pub struct PolicyRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub level: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub pattern: Option<String>,
+    pub pattern: Option<Pattern>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expire: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_enabled: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub log: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_source_ids: Option<Vec<i32>>,
 }
 
 /// Policy response
@@ -603,7 +610,7 @@ pub struct PolicyResponse {
     pub name: String,
     pub action: i32,
     pub level: i32,
-    pub pattern: Option<String>,
+    pub pattern: Option<Pattern>,
     pub expire: Option<i32>,
     pub is_enabled: bool,
     pub log: bool,
@@ -679,9 +686,9 @@ pub struct ACLConfig {
     pub site_id: i32,
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub r#type: Option<i32>,
+    pub r#type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub action: Option<i32>,
+    pub action: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enabled: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -697,26 +704,30 @@ pub struct ACLConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub count: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub conditions: Option<Vec<MatchCondition>>,
+    pub conditions: Option<Vec<RuleCondition>>,
+    /// Unix timestamp
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub created_at: Option<String>,
+    pub created_at: Option<i64>,
+    /// Unix timestamp
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub updated_at: Option<String>,
+    pub updated_at: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchCondition {
     pub field: String,
     pub operator: String,
-    pub value: String,
+    /// Alternatives; the condition holds if any value matches
+    pub value: Vec<String>,
 }
 
 /// ACL relieve request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ACLRelieveRequest {
-    pub id: i32,
+    /// ACL log IDs to relieve
+    pub id: Vec<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub search: Option<String>,
+    pub search: Option<ACLSearchRequest>,
 }
 
 /// ACL logs response
@@ -733,12 +744,15 @@ pub struct ACLLog {
     pub site_id: i32,
     pub action: String,
     pub result: String,
-    pub status: String,
+    pub status: i32,
     pub period: i32,
     pub count: i32,
     pub denied_count: i32,
+    /// What was counted: "req", "attack" or "error"
     pub reason: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_min: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub city: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub country: Option<String>,
@@ -799,17 +813,18 @@ pub struct DetectLog {
     pub host: String,
     pub method: String,
     pub url_path: String,
-    pub protocol: String,
+    pub protocol: i32,
     pub module: String,
-    pub attack_type: String,
-    pub risk_level: String,
-    pub action: String,
+    pub attack_type: i32,
+    pub risk_level: i32,
+    /// 0: passed, 1: blocked
+    pub action: i32,
     pub reason: String,
-    pub rule_id: i32,
+    pub rule_id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub short_rule_id: Option<i32>,
+    pub short_rule_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub rule_id_list: Option<Vec<i32>>,
+    pub rule_id_list: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub socket_ip: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -843,9 +858,9 @@ pub struct DetectLog {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub province: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub lat: Option<f64>,
+    pub lat: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub lng: Option<f64>,
+    pub lng: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub location: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -890,6 +905,59 @@ pub struct RecordEventListResponse {
     pub total: i32,
 }
 
+/// Detect log search request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DetectLogSearchRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attack_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+}
+
+/// Challenge record search request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChallengeRecordSearchRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at_begin: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at_end: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
+}
+
+/// Waiting room record search request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WaitingRoomSearchRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at_begin: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at_end: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
+}
+
 // ============================================================================
 // Certificate Models
 // ============================================================================
@@ -1832,11 +1900,12 @@ pub struct DetectorRequest {
 /// Skynet info
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SkynetInfo {
-    pub id: i32,
+    pub id: String,
     pub name: String,
-    pub attack_type: String,
-    pub mode: i32,
-    pub risk_level: String,
+    pub attack_type: i32,
+    /// "strict", "default", "dry_run", "disable" or "deny"
+    pub mode: String,
+    pub risk_level: i32,
 }
 
 // ============================================================================
@@ -1968,14 +2037,10 @@ pub struct SetSiteSecurityPostureRequest {
 // ============================================================================
 
 /// Get global semantics response
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GetGlobalSemanticsResponse {
-    pub data: GetGlobalSemanticsData,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetGlobalSemanticsData {
-    pub semantics: Vec<String>,
+    /// Semantics settings by name
+    pub semantics: HashMap<String, String>,
 }
 
 /// Semantics config params
@@ -1986,14 +2051,10 @@ pub struct SemanticsConfigParams {
 }
 
 /// Get skynet rule response
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GetSkynetRuleResponse {
-    pub data: GetSkynetRuleData,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetSkynetRuleData {
-    pub rules: Vec<SkynetInfo>,
+    /// Rules keyed by rule ID
+    pub rules: HashMap<String, SkynetInfo>,
 }
 
 /// Put skynet rule request
@@ -2301,12 +2362,16 @@ pub struct OauthConfig {
 }
 
 /// Global proxy config
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GlobalProxyConfig {
-    pub ip_source: i32,
-    pub ipv6: bool,
-    pub ssl_protocols: Vec<String>,
-    pub ssl_ciphers: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip_source: Option<ProxyItemString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssl_protocols: Option<ProxyItemString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssl_ciphers: Option<ProxyItemString>,
 }
 
 /// Website resource
@@ -2368,7 +2433,7 @@ pub struct WaitingRoomLog {
     pub top_waiting: i32,
     pub total_waiting: i32,
     pub avg_wait_sec: i32,
-    pub bounce_rate: i32,
+    pub bounce_rate: f64,
 }
 
 /// Waiting room
@@ -2404,7 +2469,7 @@ pub struct Chaos {
 }
 
 /// Site excludes
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SiteExcludes {
     pub prefixes: Vec<String>,
     pub content_types: Vec<String>,
@@ -2421,39 +2486,74 @@ pub struct SkynetModule {
 }
 
 /// Proxy config
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Each setting carries a `global` flag; when set, the website follows the
+/// global setting instead of `value`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProxyConfig {
-    pub id: i32,
-    pub site_id: i32,
-    pub host: String,
-    pub ip_source: i32,
-    pub ip_value: String,
-    pub ipv6: bool,
-    pub force_https: bool,
-    pub http_1_0: bool,
-    pub http2: bool,
-    pub http3: bool,
-    pub gzip: bool,
-    pub br: bool,
-    pub sse: bool,
-    pub hsts: bool,
-    pub hsts_max_age: i32,
-    pub hsts_preload: bool,
-    pub hsts_sub: bool,
-    pub ssl_protocols: Vec<String>,
-    pub ssl_ciphers: Vec<String>,
-    pub ntlm: bool,
-    pub reset_xff: bool,
-    pub xfh: bool,
-    pub xfp: bool,
-    pub default_server: bool,
-    pub global: bool,
-    pub http_headers: Vec<HttpHeader>,
-    pub created_at: String,
-    pub updated_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<ProxyItemString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip_source: Option<ProxyItemString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip_value: Option<ProxyItemString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub force_https: Option<ProxyItemBool>,
+    #[serde(rename = "http_1.0", skip_serializing_if = "Option::is_none")]
+    pub http_1_0: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http2: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http3: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gzip: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub br: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sse: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hsts: Option<ProxyItemBool>,
+    /// Seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hsts_max_age: Option<ProxyItemString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hsts_preload: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hsts_sub: Option<ProxyItemBool>,
+    /// Space separated, e.g. "TLSv1.2 TLSv1.3"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssl_protocols: Option<ProxyItemString>,
+    /// Colon separated OpenSSL cipher list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssl_ciphers: Option<ProxyItemString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ntlm: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_xff: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xfh: Option<ProxyItemString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xfp: Option<ProxyItemString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_server: Option<ProxyItemBool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_headers: Option<ProxyItemHttpHeaders>,
+    /// Global values of the settings marked `global`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global: Option<GlobalProxyConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HttpHeader {
     pub key: String,
     pub op: String,
@@ -2463,19 +2563,19 @@ pub struct HttpHeader {
 }
 
 /// Proxy item
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProxyItemString {
     pub global: bool,
     pub value: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProxyItemBool {
     pub global: bool,
     pub value: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProxyItemHttpHeaders {
     pub global: bool,
     pub value: Vec<HttpHeader>,
@@ -2500,20 +2600,24 @@ pub struct PortalConfig {
 }
 
 /// Rule pattern
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RulePattern {
     pub k: String,
     pub op: String,
-    pub v: String,
+    pub v: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sub_k: Option<String>,
 }
 
+/// Rule pattern groups: a request matches if all patterns of any group match
+pub type Pattern = Vec<Vec<RulePattern>>;
+
 /// Rule condition
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RuleCondition {
-    pub condition: String,
-    pub strategy: Vec<RulePattern>,
+    pub condition: MatchCondition,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub strategy: String,
 }
 
 /// Set share behaviour request
@@ -2532,7 +2636,7 @@ pub struct SetShareFingerprintRequest {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileInfo {
     pub name: String,
-    pub dir: String,
+    pub dir: bool,
     pub size: i32,
     pub content_type: String,
     pub modify_time: i64,
@@ -2544,7 +2648,7 @@ pub struct FileInfo {
 pub struct AddStaticRequest {
     pub path: String,
     pub dir: bool,
-    pub page: bool,
+    pub page: String,
     pub zip: bool,
 }
 
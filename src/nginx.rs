@@ -0,0 +1,552 @@
+//! Nginx custom config checking
+//!
+//! Parses the custom snippets stored in `NginxConfigData` into a directive
+//! tree and rejects common mistakes (unbalanced braces, missing semicolons,
+//! directives used in the wrong context) before they are sent to the
+//! server. Proposed changes can be previewed as a unified diff against the
+//! current config.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use std::fmt;
+
+/// Block context a directive appears in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Server,
+    Location,
+    If,
+    LimitExcept,
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Context::Server => "server",
+            Context::Location => "location",
+            Context::If => "if",
+            Context::LimitExcept => "limit_except",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A parsed nginx directive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    pub name: String,
+    pub args: Vec<String>,
+    /// Child directives if this is a block directive
+    pub block: Option<Vec<Directive>>,
+    /// 1-based line the directive starts on
+    pub line: usize,
+}
+
+impl fmt::Display for Directive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_directive(f, self, 0)
+    }
+}
+
+fn write_directive(f: &mut fmt::Formatter<'_>, directive: &Directive, depth: usize) -> fmt::Result {
+    let indent = "    ".repeat(depth);
+    write!(f, "{}{}", indent, directive.name)?;
+    for arg in &directive.args {
+        write!(f, " {}", arg)?;
+    }
+    match directive.block {
+        None => writeln!(f, ";"),
+        Some(ref children) => {
+            writeln!(f, " {{")?;
+            for child in children {
+                write_directive(f, child, depth + 1)?;
+            }
+            writeln!(f, "{}}}", indent)
+        }
+    }
+}
+
+/// Render directives back to config text
+pub fn render(directives: &[Directive]) -> String {
+    directives.iter().map(|d| d.to_string()).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Semicolon,
+    Open,
+    Close,
+}
+
+fn syntax_error(line: usize, message: impl fmt::Display) -> Error {
+    Error::InvalidInput(format!("nginx config line {}: {}", line, message))
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+            }
+            ';' => tokens.push((Token::Semicolon, line)),
+            '{' => tokens.push((Token::Open, line)),
+            '}' => tokens.push((Token::Close, line)),
+            '"' | '\'' => {
+                let start = line;
+                let mut word = String::from(c);
+                loop {
+                    match chars.next() {
+                        None => return Err(syntax_error(start, "unterminated quoted string")),
+                        Some('\\') => {
+                            word.push('\\');
+                            if let Some(escaped) = chars.next() {
+                                word.push(escaped);
+                            }
+                        }
+                        Some(q) if q == c => {
+                            word.push(q);
+                            break;
+                        }
+                        Some(other) => {
+                            if other == '\n' {
+                                line += 1;
+                            }
+                            word.push(other);
+                        }
+                    }
+                }
+                tokens.push((Token::Word(word), start));
+            }
+            _ => {
+                let mut word = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, ';' | '}' | '"' | '\'') {
+                        break;
+                    }
+                    // `${var}` is part of the word, any other `{` opens a block
+                    if next == '{' && !word.ends_with('$') {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                    if next == '{' {
+                        for c in chars.by_ref() {
+                            word.push(c);
+                            if c == '}' {
+                                break;
+                            }
+                        }
+                    }
+                }
+                tokens.push((Token::Word(word), line));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parse a config snippet into a directive tree
+///
+/// Fails on unbalanced braces, unterminated strings and directives that
+/// are not terminated by `;` or a block.
+pub fn parse(input: &str) -> Result<Vec<Directive>> {
+    let tokens = tokenize(input)?;
+    let mut pos = 0;
+    let directives = parse_block(&tokens, &mut pos, None)?;
+    Ok(directives)
+}
+
+fn parse_block(tokens: &[(Token, usize)], pos: &mut usize, opened_at: Option<usize>) -> Result<Vec<Directive>> {
+    let mut directives = Vec::new();
+
+    loop {
+        let Some((token, line)) = tokens.get(*pos) else {
+            return match opened_at {
+                Some(line) => Err(syntax_error(line, "unclosed '{'")),
+                None => Ok(directives),
+            };
+        };
+        *pos += 1;
+
+        let name = match token {
+            Token::Word(name) => name.clone(),
+            Token::Close => {
+                return match opened_at {
+                    Some(_) => Ok(directives),
+                    None => Err(syntax_error(*line, "unexpected '}'")),
+                };
+            }
+            Token::Semicolon => return Err(syntax_error(*line, "unexpected ';'")),
+            Token::Open => return Err(syntax_error(*line, "unexpected '{'")),
+        };
+
+        let mut args = Vec::new();
+        let block = loop {
+            match tokens.get(*pos) {
+                Some((Token::Word(arg), _)) => {
+                    args.push(arg.clone());
+                    *pos += 1;
+                }
+                Some((Token::Semicolon, _)) => {
+                    *pos += 1;
+                    break None;
+                }
+                Some((Token::Open, _)) => {
+                    *pos += 1;
+                    break Some(parse_block(tokens, pos, Some(*line))?);
+                }
+                Some((Token::Close, close_line)) => {
+                    return Err(syntax_error(*close_line, format!("missing ';' after \"{}\"", name)));
+                }
+                None => return Err(syntax_error(*line, format!("missing ';' after \"{}\"", name))),
+            }
+        };
+
+        directives.push(Directive {
+            name,
+            args,
+            block,
+            line: *line,
+        });
+    }
+}
+
+struct Rule {
+    name: &'static str,
+    contexts: &'static [Context],
+    /// Context of the directive's children, `None` for simple directives
+    child: Option<Context>,
+    min_args: usize,
+    max_args: Option<usize>,
+}
+
+const fn rule(name: &'static str, contexts: &'static [Context], min_args: usize, max_args: Option<usize>) -> Rule {
+    Rule {
+        name,
+        contexts,
+        child: None,
+        min_args,
+        max_args,
+    }
+}
+
+const S: Context = Context::Server;
+const L: Context = Context::Location;
+const I: Context = Context::If;
+const E: Context = Context::LimitExcept;
+
+const RULES: &[Rule] = &[
+    Rule {
+        name: "location",
+        contexts: &[S, L],
+        child: Some(L),
+        min_args: 1,
+        max_args: Some(2),
+    },
+    Rule {
+        name: "if",
+        contexts: &[S, L],
+        child: Some(I),
+        min_args: 1,
+        max_args: None,
+    },
+    Rule {
+        name: "limit_except",
+        contexts: &[L],
+        child: Some(E),
+        min_args: 1,
+        max_args: None,
+    },
+    rule("proxy_pass", &[L, I, E], 1, Some(1)),
+    rule("proxy_set_header", &[S, L], 2, Some(2)),
+    rule("proxy_hide_header", &[S, L], 1, Some(1)),
+    rule("proxy_redirect", &[S, L], 1, Some(2)),
+    rule("proxy_buffering", &[S, L], 1, Some(1)),
+    rule("proxy_connect_timeout", &[S, L], 1, Some(1)),
+    rule("proxy_read_timeout", &[S, L], 1, Some(1)),
+    rule("proxy_send_timeout", &[S, L], 1, Some(1)),
+    rule("add_header", &[S, L, I], 2, Some(3)),
+    rule("return", &[S, L, I], 1, Some(2)),
+    rule("rewrite", &[S, L, I], 2, Some(3)),
+    rule("set", &[S, L, I], 2, Some(2)),
+    rule("break", &[S, L, I], 0, Some(0)),
+    rule("root", &[S, L, I], 1, Some(1)),
+    rule("alias", &[L], 1, Some(1)),
+    rule("index", &[S, L], 1, None),
+    rule("try_files", &[S, L], 2, None),
+    rule("internal", &[L], 0, Some(0)),
+    rule("allow", &[S, L, E], 1, Some(1)),
+    rule("deny", &[S, L, E], 1, Some(1)),
+    rule("client_max_body_size", &[S, L], 1, Some(1)),
+    rule("error_page", &[S, L, I], 2, None),
+    rule("expires", &[S, L, I], 1, Some(2)),
+    rule("default_type", &[S, L], 1, Some(1)),
+    rule("charset", &[S, L, I], 1, Some(1)),
+    rule("gzip", &[S, L, I], 1, Some(1)),
+    rule("access_log", &[S, L, I, E], 1, None),
+    rule("error_log", &[S, L], 1, Some(2)),
+];
+
+/// Check directives against the contexts and argument counts of common
+/// directives
+///
+/// Directives not known to the checker are accepted as long as they are
+/// syntactically valid.
+pub fn validate(directives: &[Directive], context: Context) -> Result<()> {
+    for directive in directives {
+        let Some(rule) = RULES.iter().find(|r| r.name == directive.name) else {
+            if let Some(ref children) = directive.block {
+                validate(children, context)?;
+            }
+            continue;
+        };
+
+        if !rule.contexts.contains(&context) {
+            return Err(syntax_error(
+                directive.line,
+                format!("\"{}\" is not allowed in {} context", directive.name, context),
+            ));
+        }
+
+        let count = directive.args.len();
+        if count < rule.min_args {
+            return Err(syntax_error(
+                directive.line,
+                format!("invalid number of arguments in \"{}\"", directive.name),
+            ));
+        }
+        if rule.max_args.is_some_and(|max| count > max) {
+            // Excess arguments usually mean the `;` of this line is missing
+            return Err(syntax_error(
+                directive.line,
+                format!("too many arguments in \"{}\", missing ';'?", directive.name),
+            ));
+        }
+
+        match (rule.child, &directive.block) {
+            (Some(child), Some(children)) => validate(children, child)?,
+            (Some(_), None) => {
+                return Err(syntax_error(directive.line, format!("\"{}\" has no block", directive.name)));
+            }
+            (None, Some(_)) => {
+                return Err(syntax_error(
+                    directive.line,
+                    format!("\"{}\" does not take a block", directive.name),
+                ));
+            }
+            (None, None) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse and validate a snippet in the given context
+pub fn check(input: &str, context: Context) -> Result<Vec<Directive>> {
+    let directives = parse(input)?;
+    validate(&directives, context)?;
+    Ok(directives)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+fn diff_ops(old: &[&str], new: &[&str]) -> Vec<Op> {
+    // Longest common subsequence table, filled from the end
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut ops = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push(Op::Equal(i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(Op::Delete(i));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(j));
+            j += 1;
+        }
+    }
+    ops
+}
+
+fn hunk_range(start: usize, count: usize) -> String {
+    // An empty range points at the line before it
+    let start = if count == 0 { start } else { start + 1 };
+    if count == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, count)
+    }
+}
+
+/// Render a unified diff between two texts with three lines of context
+///
+/// Returns an empty string if the texts have the same lines.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    const CONTEXT: usize = 3;
+
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_ops(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = (0..ops.len()).filter(|&k| !matches!(ops[k], Op::Equal(..))).collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Group changes whose context overlaps into hunks of op indices
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &k in &changes {
+        let start = k.saturating_sub(CONTEXT);
+        let end = (k + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks {
+        // Position of the hunk in both files, counted from the ops before it
+        let old_start = ops[..start].iter().filter(|op| !matches!(op, Op::Insert(_))).count();
+        let new_start = ops[..start].iter().filter(|op| !matches!(op, Op::Delete(_))).count();
+        let old_count = ops[start..end].iter().filter(|op| !matches!(op, Op::Insert(_))).count();
+        let new_count = ops[start..end].iter().filter(|op| !matches!(op, Op::Delete(_))).count();
+
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_count),
+            hunk_range(new_start, new_count)
+        ));
+        for op in &ops[start..end] {
+            match *op {
+                Op::Equal(i, _) => out.push_str(&format!(" {}\n", old_lines[i])),
+                Op::Delete(i) => out.push_str(&format!("-{}\n", old_lines[i])),
+                Op::Insert(j) => out.push_str(&format!("+{}\n", new_lines[j])),
+            }
+        }
+    }
+    out
+}
+
+impl Client {
+    /// Diff nginx config
+    ///
+    /// Checks a proposed custom location snippet and renders a unified diff
+    /// against the website's current one.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `custom_location` - Proposed custom location snippet
+    ///
+    /// # Returns
+    ///
+    /// Returns the diff, empty if nothing changes
+    pub async fn diff_nginx_config(&self, id: i32, custom_location: &str) -> Result<String> {
+        check(custom_location, Context::Server)?;
+        let current = self.get_nginx_config(id).await?.data;
+        Ok(unified_diff(
+            &current.custom_location,
+            custom_location,
+            "current",
+            "proposed",
+        ))
+    }
+
+    /// Update nginx config after checking it
+    ///
+    /// Like `update_nginx_config`, but rejects snippets that fail `check`
+    /// without contacting the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `custom_location` - Custom location snippet
+    pub async fn update_nginx_config_checked(&self, id: i32, custom_location: &str) -> Result<()> {
+        check(custom_location, Context::Server)?;
+        self.update_nginx_config(
+            id,
+            &NginxConfigUpdateRequest {
+                custom_location: custom_location.to_string(),
+            },
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_validate() {
+        let input = r#"
+            # health endpoint
+            location = /health {
+                return 200 "ok";
+            }
+            location /api/ {
+                proxy_set_header X-Real-IP $remote_addr;
+                if ($http_user_agent ~* "curl") { return 403; }
+                proxy_pass http://backend;
+            }
+        "#;
+        let directives = check(input, Context::Server).unwrap();
+        assert_eq!(directives.len(), 2);
+        assert_eq!(directives[1].args, vec!["/api/"]);
+        assert_eq!(directives[1].block.as_ref().unwrap()[1].name, "if");
+        let rendered = render(&directives);
+        assert_eq!(render(&parse(&rendered).unwrap()), rendered);
+
+        let error = |input: &str| check(input, Context::Server).unwrap_err().to_string();
+        assert!(error("location / {\n return 200;\n").contains("line 1: unclosed"));
+        assert!(error("return 200;\n}").contains("line 2: unexpected '}'"));
+        assert!(error("location / { return 200 }").contains("missing ';'"));
+        assert!(error("location / {\nproxy_pass http://a\nproxy_read_timeout 5s;\n}").contains("missing ';'?"));
+        assert!(error("proxy_pass http://a;").contains("not allowed in server context"));
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let new = "a\nb\nc\nD\ne\nf\ng\nh\ni\n";
+        assert_eq!(
+            unified_diff(old, new, "current", "proposed"),
+            "--- current\n+++ proposed\n@@ -1,8 +1,9 @@\n a\n b\n c\n-d\n+D\n e\n f\n g\n h\n+i\n"
+        );
+        assert_eq!(unified_diff(old, old, "a", "b"), "");
+        assert_eq!(unified_diff("", "x\n", "a", "b"), "--- a\n+++ b\n@@ -0,0 +1 @@\n+x\n");
+    }
+}
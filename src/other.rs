@@ -813,26 +813,26 @@ impl Client {
     // Skynet
     // ============================================================================
 
-    /// Get global semantics (GET /open/skynet/rule)
+    /// Get global semantics (GET /open/global/mode)
     ///
     /// Retrieves global semantics configuration.
     ///
     /// # Returns
     ///
     /// Returns global semantics configuration
-    pub async fn get_global_semantics(&self) -> Result<GetGlobalSemanticsResponse> {
-        self.get("/open/skynet/rule").await
+    pub async fn get_global_semantics(&self) -> Result<GetGlobalSemanticsData> {
+        self.get("/open/global/mode").await
     }
 
-    /// Get skynet rule (GET /commercial/skynet/rule)
+    /// Get skynet rule (GET /open/skynet/rule)
     ///
-    /// Retrieves skynet rule configuration.
+    /// Retrieves the Skynet rules.
     ///
     /// # Returns
     ///
-    /// Returns skynet rule configuration
-    pub async fn get_skynet_rule(&self) -> Result<GetSkynetRuleResponse> {
-        self.get("/commercial/skynet/rule").await
+    /// Returns the rules keyed by rule ID
+    pub async fn get_skynet_rule(&self) -> Result<GetSkynetRuleData> {
+        self.get("/open/skynet/rule").await
     }
 
     /// Put skynet rule (PUT /commercial/skynet/rule)
@@ -0,0 +1,74 @@
+//! Lazy streams over paged list endpoints
+
+use crate::error::Result;
+use futures::stream::{self, Stream};
+use std::collections::VecDeque;
+use std::future::Future;
+
+/// Stream the items of a paged endpoint
+///
+/// `fetch` is called with page numbers counting up from `first` and returns
+/// the items of the page and the total count. Pages are fetched lazily
+/// until a short page or `total` is reached. A failed page is yielded as an
+/// `Err` item and ends the stream.
+pub(crate) fn paged<T, F, Fut>(first: i32, page_size: i32, fetch: F) -> impl Stream<Item = Result<T>>
+where
+    F: Fn(i32) -> Fut,
+    Fut: Future<Output = Result<(Vec<T>, i32)>>,
+{
+    stream::unfold(
+        (Some(first), VecDeque::new(), 0usize, fetch),
+        move |(mut next, mut pending, mut fetched, fetch)| async move {
+            loop {
+                if let Some(item) = pending.pop_front() {
+                    return Some((Ok(item), (next, pending, fetched, fetch)));
+                }
+                let page = next.take()?;
+                match fetch(page).await {
+                    Ok((items, total)) => {
+                        let len = items.len();
+                        fetched += len;
+                        if len > 0 && len as i32 >= page_size && fetched < total as usize {
+                            next = Some(page + 1);
+                        }
+                        pending.extend(items);
+                    }
+                    Err(e) => return Some((Err(e), (None, pending, fetched, fetch))),
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use futures::StreamExt;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    #[tokio::test]
+    async fn test_paged() {
+        let calls = AtomicI32::new(0);
+        let items: Vec<Result<i32>> = paged(1, 2, |page| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move { Ok((vec![page * 10, page * 10 + 1], 5)) }
+        })
+        .take(5)
+        .collect()
+        .await;
+        assert_eq!(items.into_iter().collect::<Result<Vec<_>>>().unwrap(), vec![10, 11, 20, 21, 30]);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let items: Vec<Result<i32>> = paged(1, 2, |page| async move {
+            match page {
+                1 => Ok((vec![1, 2], 10)),
+                _ => Err(Error::InvalidInput("page failed".to_string())),
+            }
+        })
+        .collect()
+        .await;
+        assert_eq!(items.len(), 3);
+        assert!(items[2].is_err());
+    }
+}
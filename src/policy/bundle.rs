@@ -0,0 +1,506 @@
+//! Portable policy bundles
+//!
+//! A `PolicyBundle` carries the custom policies of one SafeLine instance
+//! together with the IP groups and auth defense sources they reference, so
+//! that they can be imported into another instance. IDs differ between
+//! instances: on import, IP groups and auth sources are matched by name or
+//! created, and the new IDs are written into the imported policies.
+//! Policies themselves are matched by name and handled according to a
+//! `ConflictStrategy`.
+
+use super::dsl::Op;
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+use tracing::info;
+
+/// An IP group referenced by a bundled policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundledIpGroup {
+    /// ID on the exporting instance
+    pub id: i32,
+    /// Group name, used to find the group on the importing instance
+    pub comment: String,
+    pub ips: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+}
+
+/// An auth defense source referenced by a bundled policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundledAuthSource {
+    /// ID on the exporting instance
+    pub id: i32,
+    /// Source title, used to find the source on the importing instance
+    pub title: String,
+    pub r#type: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<PolicyAuth>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub association: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_proxy: Option<NetworkProxy>,
+}
+
+/// A custom policy without instance specific fields
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundledPolicy {
+    pub name: String,
+    pub action: i32,
+    pub level: i32,
+    #[serde(default)]
+    pub pattern: Pattern,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire: Option<i32>,
+    pub is_enabled: bool,
+    pub log: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auth_source_ids: Vec<i32>,
+}
+
+impl From<&PolicyResponse> for BundledPolicy {
+    fn from(policy: &PolicyResponse) -> Self {
+        Self {
+            name: policy.name.clone(),
+            action: policy.action,
+            level: policy.level,
+            pattern: policy.pattern.clone().unwrap_or_default(),
+            expire: policy.expire.filter(|e| *e > 0),
+            is_enabled: policy.is_enabled,
+            log: policy.log,
+            auth_source_ids: policy.auth_source_ids.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Policies with the IP groups and auth sources they reference
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyBundle {
+    pub policies: Vec<BundledPolicy>,
+    #[serde(default)]
+    pub ip_groups: Vec<BundledIpGroup>,
+    #[serde(default)]
+    pub auth_sources: Vec<BundledAuthSource>,
+}
+
+impl PolicyBundle {
+    /// Parse a bundle from JSON
+    pub fn from_json(input: &str) -> Result<Self> {
+        serde_json::from_str(input).map_err(|e| Error::ConfigFormat(e.to_string()))
+    }
+
+    /// Render the bundle as JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::ConfigFormat(e.to_string()))
+    }
+
+    /// Parse a bundle from YAML
+    pub fn from_yaml(input: &str) -> Result<Self> {
+        serde_yaml::from_str(input).map_err(|e| Error::ConfigFormat(e.to_string()))
+    }
+
+    /// Render the bundle as YAML
+    pub fn to_yaml(&self) -> Result<String> {
+        serde_yaml::to_string(self).map_err(|e| Error::ConfigFormat(e.to_string()))
+    }
+
+    /// Write the bundle to a file; `.yaml`/`.yml` files are written as
+    /// YAML, anything else as JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let content = if is_yaml(path.as_ref()) { self.to_yaml()? } else { self.to_json()? };
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Read a bundle written by `save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let content = std::fs::read_to_string(path.as_ref())?;
+        if is_yaml(path.as_ref()) {
+            Self::from_yaml(&content)
+        } else {
+            Self::from_json(&content)
+        }
+    }
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml")
+}
+
+fn is_ip_group_ref(pattern: &RulePattern) -> bool {
+    pattern.k == "src_ip" && matches!(Op::parse(&pattern.op), Ok(Op::In | Op::NotIn))
+}
+
+/// IP group IDs referenced by `src_ip in` conditions of a pattern
+pub fn pattern_ip_groups(pattern: &Pattern) -> BTreeSet<i32> {
+    pattern
+        .iter()
+        .flatten()
+        .filter(|p| is_ip_group_ref(p))
+        .flat_map(|p| p.v.iter().filter_map(|v| v.parse().ok()))
+        .collect()
+}
+
+/// Replace IP group IDs in a pattern according to `ids` (old to new);
+/// fails if a referenced group has no mapping
+pub fn rewrite_ip_groups(pattern: &Pattern, ids: &BTreeMap<i32, i32>) -> Result<Pattern> {
+    let mut pattern = pattern.clone();
+    for rule in pattern.iter_mut().flatten().filter(|p| is_ip_group_ref(p)) {
+        for value in rule.v.iter_mut() {
+            if let Ok(old) = value.parse::<i32>() {
+                let new = ids
+                    .get(&old)
+                    .ok_or_else(|| Error::InvalidInput(format!("IP group {} is not in the bundle", old)))?;
+                *value = new.to_string();
+            }
+        }
+    }
+    Ok(pattern)
+}
+
+/// What to do with a bundled policy whose name already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Keep the existing policy
+    Skip,
+    /// Replace the existing policy
+    Overwrite,
+    /// Import under a new, unused name
+    Rename,
+}
+
+/// Planned handling of one bundled policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyImport {
+    Create { name: String },
+    Overwrite { id: i32, name: String },
+    Rename { from: String, to: String },
+    Skip { name: String },
+}
+
+impl fmt::Display for PolicyImport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyImport::Create { name } => write!(f, "+ policy {}", name),
+            PolicyImport::Overwrite { id, name } => write!(f, "~ policy {} (#{})", name, id),
+            PolicyImport::Rename { from, to } => write!(f, "+ policy {} (renamed from {})", to, from),
+            PolicyImport::Skip { name } => write!(f, "  policy {} exists, skipped", name),
+        }
+    }
+}
+
+/// Decide how each bundled policy is imported
+///
+/// Builtin policies are never overwritten; a bundled policy named like one
+/// is renamed or skipped instead.
+pub fn plan_policy_import(
+    bundle: &PolicyBundle,
+    existing: &[PolicyResponse],
+    strategy: ConflictStrategy,
+) -> Vec<PolicyImport> {
+    let mut names: BTreeSet<String> = existing.iter().map(|p| p.name.clone()).collect();
+
+    bundle
+        .policies
+        .iter()
+        .map(|policy| {
+            let name = policy.name.clone();
+            let Some(current) = existing.iter().find(|p| p.name == name) else {
+                names.insert(name.clone());
+                return PolicyImport::Create { name };
+            };
+            match strategy {
+                ConflictStrategy::Overwrite if !current.builtin => PolicyImport::Overwrite { id: current.id, name },
+                ConflictStrategy::Skip | ConflictStrategy::Overwrite => PolicyImport::Skip { name },
+                ConflictStrategy::Rename => {
+                    let to = (2..)
+                        .map(|n| format!("{} ({})", name, n))
+                        .find(|candidate| !names.contains(candidate))
+                        .unwrap_or_default();
+                    names.insert(to.clone());
+                    PolicyImport::Rename { from: name, to }
+                }
+            }
+        })
+        .collect()
+}
+
+/// Result of importing a bundle
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BundleImport {
+    /// Bundle IP group ID to ID on this instance
+    pub ip_groups: BTreeMap<i32, i32>,
+    /// Bundle auth source ID to ID on this instance
+    pub auth_sources: BTreeMap<i32, i32>,
+    pub created_ip_groups: Vec<String>,
+    pub created_auth_sources: Vec<String>,
+    pub policies: Vec<PolicyImport>,
+}
+
+impl fmt::Display for BundleImport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.created_ip_groups {
+            writeln!(f, "+ ip group {}", name)?;
+        }
+        for title in &self.created_auth_sources {
+            writeln!(f, "+ auth source {}", title)?;
+        }
+        for policy in &self.policies {
+            writeln!(f, "{}", policy)?;
+        }
+        let skipped = self.policies.iter().filter(|p| matches!(p, PolicyImport::Skip { .. })).count();
+        write!(
+            f,
+            "Import: {} policies imported, {} skipped, {} IP groups and {} auth sources created",
+            self.policies.len() - skipped,
+            skipped,
+            self.created_ip_groups.len(),
+            self.created_auth_sources.len()
+        )
+    }
+}
+
+impl Client {
+    /// Export custom policies as a bundle
+    ///
+    /// Builtin policies are skipped. IP groups referenced by `src_ip in`
+    /// conditions and auth defense sources referenced by auth policies are
+    /// included.
+    ///
+    /// # Returns
+    ///
+    /// Returns the policy bundle
+    pub async fn export_policy_bundle(&self) -> Result<PolicyBundle> {
+        let policies: Vec<BundledPolicy> = self
+            .list_policies()
+            .await?
+            .data
+            .iter()
+            .filter(|p| !p.builtin)
+            .map(BundledPolicy::from)
+            .collect();
+
+        let group_ids: BTreeSet<i32> = policies.iter().flat_map(|p| pattern_ip_groups(&p.pattern)).collect();
+        let mut ip_groups = Vec::new();
+        for id in group_ids {
+            let group = self.ip_group_detail(&IPGroupDetailRequest { id }).await?.data;
+            ip_groups.push(BundledIpGroup {
+                id,
+                comment: group.comment.filter(|c| !c.is_empty()).unwrap_or_else(|| format!("ipgroup-{}", id)),
+                ips: group.ips.unwrap_or_default(),
+                reference: group.reference.filter(|r| !r.is_empty()),
+            });
+        }
+
+        let source_ids: BTreeSet<i32> = policies.iter().flat_map(|p| p.auth_source_ids.iter().copied()).collect();
+        let mut auth_sources = Vec::new();
+        for id in source_ids {
+            let source = self.get_auth_defense_source(id).await?;
+            auth_sources.push(BundledAuthSource {
+                id,
+                title: source.title,
+                r#type: source.r#type,
+                auth: source.auth,
+                association: source.association,
+                network_proxy: source.network_proxy,
+            });
+        }
+
+        info!(
+            "exported {} policies, {} IP groups, {} auth sources",
+            policies.len(),
+            ip_groups.len(),
+            auth_sources.len()
+        );
+        Ok(PolicyBundle {
+            policies,
+            ip_groups,
+            auth_sources,
+        })
+    }
+
+    /// Import a policy bundle
+    ///
+    /// IP groups and auth sources are matched by name; missing ones are
+    /// created first. Their IDs on this instance are then written into the
+    /// policies, which are created, overwritten, renamed or skipped
+    /// according to `strategy`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bundle` - Bundle to import
+    /// * `strategy` - How to handle policies whose name already exists
+    ///
+    /// # Returns
+    ///
+    /// Returns the ID mappings and what happened to each policy
+    pub async fn import_policy_bundle(&self, bundle: &PolicyBundle, strategy: ConflictStrategy) -> Result<BundleImport> {
+        let mut result = BundleImport::default();
+
+        for group in &bundle.ip_groups {
+            let (id, created) = self
+                .ip_group_find_or_create(&IPGroupCreateRequest {
+                    comment: Some(group.comment.clone()),
+                    ips: group.ips.clone(),
+                    reference: group.reference.clone(),
+                })
+                .await?;
+            if created {
+                result.created_ip_groups.push(group.comment.clone());
+            }
+            result.ip_groups.insert(group.id, id);
+        }
+
+        if !bundle.auth_sources.is_empty() {
+            let mut sources = Vec::new();
+            for item in self.list_auth_defense_sources().await?.data {
+                sources.push(self.get_auth_defense_source(item.id).await?);
+            }
+            for source in &bundle.auth_sources {
+                let id = match sources.iter().find(|s| s.title == source.title) {
+                    Some(existing) => existing.id,
+                    None => {
+                        let id = self
+                            .create_auth_defense_source(&AuthDefenseCreateSourceRequest {
+                                title: source.title.clone(),
+                                r#type: source.r#type,
+                                auth: source.auth.clone(),
+                                association: source.association,
+                                network_proxy: source.network_proxy.clone(),
+                            })
+                            .await?
+                            .data
+                            .id;
+                        info!("created auth source {} ({})", source.title, id);
+                        result.created_auth_sources.push(source.title.clone());
+                        id
+                    }
+                };
+                result.auth_sources.insert(source.id, id);
+            }
+        }
+
+        let existing = self.list_policies().await?.data;
+        let plan = plan_policy_import(bundle, &existing, strategy);
+        for (policy, import) in bundle.policies.iter().zip(&plan) {
+            let (id, name) = match import {
+                PolicyImport::Skip { .. } => continue,
+                PolicyImport::Create { name } => (None, name),
+                PolicyImport::Overwrite { id, name } => (Some(*id), name),
+                PolicyImport::Rename { to, .. } => (None, to),
+            };
+            let auth_source_ids = policy
+                .auth_source_ids
+                .iter()
+                .map(|old| {
+                    result
+                        .auth_sources
+                        .get(old)
+                        .copied()
+                        .ok_or_else(|| Error::InvalidInput(format!("auth source {} is not in the bundle", old)))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let request = PolicyRequest {
+                id,
+                name: name.clone(),
+                action: Some(policy.action),
+                level: Some(policy.level),
+                pattern: Some(rewrite_ip_groups(&policy.pattern, &result.ip_groups)?),
+                expire: policy.expire,
+                is_enabled: Some(policy.is_enabled),
+                log: Some(policy.log),
+                auth_source_ids: Some(auth_source_ids).filter(|ids| !ids.is_empty()),
+            };
+
+            if id.is_some() {
+                self.update_policy(&request).await?;
+            } else {
+                self.create_policy(&request).await?;
+            }
+            info!("{}", import);
+        }
+
+        result.policies = plan;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::dsl;
+
+    fn existing(id: i32, name: &str, builtin: bool) -> PolicyResponse {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "name": name, "action": 1, "level": 0, "pattern": null, "expire": null,
+            "is_enabled": true, "log": true, "builtin": builtin, "created_at": "", "updated_at": ""
+        }))
+        .unwrap()
+    }
+
+    fn bundle(names: &[&str]) -> PolicyBundle {
+        PolicyBundle {
+            policies: names
+                .iter()
+                .map(|name| BundledPolicy {
+                    name: name.to_string(),
+                    action: 1,
+                    level: 0,
+                    pattern: Vec::new(),
+                    expire: None,
+                    is_enabled: true,
+                    log: true,
+                    auth_source_ids: Vec::new(),
+                })
+                .collect(),
+            ip_groups: Vec::new(),
+            auth_sources: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_plan_policy_import() {
+        let current = vec![existing(1, "a", false), existing(2, "a (2)", false), existing(3, "builtin", true)];
+        let bundle = bundle(&["a", "b", "builtin"]);
+
+        assert_eq!(
+            plan_policy_import(&bundle, &current, ConflictStrategy::Overwrite),
+            vec![
+                PolicyImport::Overwrite { id: 1, name: "a".to_string() },
+                PolicyImport::Create { name: "b".to_string() },
+                PolicyImport::Skip { name: "builtin".to_string() },
+            ]
+        );
+        assert_eq!(
+            plan_policy_import(&bundle, &current, ConflictStrategy::Rename)[0],
+            PolicyImport::Rename { from: "a".to_string(), to: "a (3)".to_string() }
+        );
+        assert!(matches!(
+            plan_policy_import(&bundle, &current, ConflictStrategy::Skip)[0],
+            PolicyImport::Skip { .. }
+        ));
+    }
+
+    #[test]
+    fn test_rewrite_ip_groups() {
+        let pattern = dsl::compile(r#"src_ip in [ipgroup(3), 10.0.0.0/8] or (src_ip not_in ipgroup(4) and host eq "a")"#).unwrap();
+        assert_eq!(pattern_ip_groups(&pattern), BTreeSet::from([3, 4]));
+
+        let ids = BTreeMap::from([(3, 30), (4, 40)]);
+        let rewritten = rewrite_ip_groups(&pattern, &ids).unwrap();
+        assert_eq!(
+            dsl::decompile(&rewritten).unwrap(),
+            r#"src_ip in [ipgroup(30), 10.0.0.0/8] or (src_ip not_in ipgroup(40) and host eq "a")"#
+        );
+        assert!(rewrite_ip_groups(&pattern, &BTreeMap::from([(3, 30)])).is_err());
+
+        let mut bundle = bundle(&["a"]);
+        bundle.policies[0].pattern = pattern;
+        let yaml = bundle.to_yaml().unwrap();
+        assert_eq!(PolicyBundle::from_yaml(&yaml).unwrap().policies, bundle.policies);
+    }
+}
@@ -0,0 +1,500 @@
+//! Text DSL for policy rule patterns
+//!
+//! Rules are written as conditions combined with `and`, `or` and
+//! parentheses, for example:
+//!
+//! ```text
+//! src_ip in 10.0.0.0/8 and (url_path prefix "/admin" or header["user-agent"] re "sqlmap")
+//! ```
+//!
+//! A condition is a field, an operator and one value or a `[...]` list of
+//! values, any of which may match. `ipgroup(3)` refers to an IP group in
+//! `src_ip in` conditions. Expressions compile to the SafeLine `Pattern`
+//! (any group of patterns where all patterns match) and existing patterns
+//! decompile back to the DSL.
+
+use crate::error::{Error, Result};
+use crate::models::*;
+use std::fmt;
+use std::net::IpAddr;
+
+/// Request fields understood by SafeLine rules
+pub const FIELDS: &[&str] = &["src_ip", "host", "method", "uri", "url_path", "query_string", "header", "body"];
+
+/// Rule operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Eq,
+    NotEq,
+    Has,
+    NotHas,
+    Prefix,
+    Re,
+    In,
+    NotIn,
+}
+
+impl Op {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Op::Eq => "eq",
+            Op::NotEq => "not_eq",
+            Op::Has => "has",
+            Op::NotHas => "not_has",
+            Op::Prefix => "prefix",
+            Op::Re => "re",
+            Op::In => "in",
+            Op::NotIn => "not_in",
+        }
+    }
+
+    pub fn parse(op: &str) -> Result<Self> {
+        Ok(match op {
+            "eq" => Op::Eq,
+            "not_eq" => Op::NotEq,
+            "has" => Op::Has,
+            "not_has" => Op::NotHas,
+            "prefix" => Op::Prefix,
+            "re" => Op::Re,
+            "in" => Op::In,
+            "not_in" => Op::NotIn,
+            other => return Err(Error::InvalidInput(format!("unknown rule operator {:?}", other))),
+        })
+    }
+}
+
+/// A single field comparison
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub field: String,
+    /// Header name for `header[...]`
+    pub key: Option<String>,
+    pub op: Op,
+    /// Alternatives; the condition holds if any value matches
+    pub values: Vec<String>,
+}
+
+impl Condition {
+    /// IP group IDs referenced by a `src_ip in` / `not_in` condition
+    pub fn ip_groups(&self) -> Vec<i32> {
+        if self.field != "src_ip" || !matches!(self.op, Op::In | Op::NotIn) {
+            return Vec::new();
+        }
+        self.values.iter().filter_map(|v| v.parse().ok()).collect()
+    }
+
+    fn to_rule_pattern(&self) -> RulePattern {
+        RulePattern {
+            k: self.field.clone(),
+            op: self.op.as_str().to_string(),
+            v: self.values.clone(),
+            sub_k: self.key.clone(),
+        }
+    }
+
+    fn from_rule_pattern(pattern: &RulePattern) -> Result<Self> {
+        Ok(Self {
+            field: pattern.k.clone(),
+            key: pattern.sub_k.clone().filter(|k| !k.is_empty()),
+            op: Op::parse(&pattern.op)?,
+            values: pattern.v.clone(),
+        })
+    }
+}
+
+/// Rule expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Cond(Condition),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    /// Parse a DSL expression
+    pub fn parse(input: &str) -> Result<Self> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(parser.error(format!("unexpected {}", token))),
+        }
+    }
+
+    /// Normalize to SafeLine's pattern: any group matches if all of its
+    /// conditions match
+    pub fn to_pattern(&self) -> Pattern {
+        self.groups()
+            .into_iter()
+            .map(|group| group.iter().map(|c| c.to_rule_pattern()).collect())
+            .collect()
+    }
+
+    /// Rebuild an expression from a SafeLine pattern
+    pub fn from_pattern(pattern: &[Vec<RulePattern>]) -> Result<Self> {
+        let mut groups = pattern
+            .iter()
+            .filter(|group| !group.is_empty())
+            .map(|group| {
+                let mut conds = group
+                    .iter()
+                    .map(|p| Condition::from_rule_pattern(p).map(Expr::Cond))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(if conds.len() == 1 { conds.remove(0) } else { Expr::And(conds) })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        match groups.len() {
+            0 => Err(Error::InvalidInput("empty rule pattern".to_string())),
+            1 => Ok(groups.remove(0)),
+            _ => Ok(Expr::Or(groups)),
+        }
+    }
+
+    /// Disjunctive normal form: a list of groups of conditions
+    pub fn groups(&self) -> Vec<Vec<Condition>> {
+        match self {
+            Expr::Cond(c) => vec![vec![c.clone()]],
+            Expr::Or(children) => children.iter().flat_map(|c| c.groups()).collect(),
+            Expr::And(children) => children.iter().fold(vec![Vec::new()], |acc, child| {
+                let child = child.groups();
+                acc.iter()
+                    .flat_map(|left| {
+                        child.iter().map(move |right| {
+                            let mut group = left.clone();
+                            group.extend(right.iter().cloned());
+                            group
+                        })
+                    })
+                    .collect()
+            }),
+        }
+    }
+
+    /// All conditions in the expression
+    pub fn conditions(&self) -> Vec<&Condition> {
+        match self {
+            Expr::Cond(c) => vec![c],
+            Expr::And(children) | Expr::Or(children) => children.iter().flat_map(|c| c.conditions()).collect(),
+        }
+    }
+}
+
+/// Compile DSL source to a SafeLine pattern
+pub fn compile(input: &str) -> Result<Pattern> {
+    Ok(Expr::parse(input)?.to_pattern())
+}
+
+/// Render a SafeLine pattern as DSL source
+pub fn decompile(pattern: &[Vec<RulePattern>]) -> Result<String> {
+    Ok(Expr::from_pattern(pattern)?.to_string())
+}
+
+fn is_bare(value: &str) -> bool {
+    value.parse::<IpAddr>().is_ok()
+        || value.split_once('/').is_some_and(|(ip, bits)| ip.parse::<IpAddr>().is_ok() && bits.parse::<u8>().is_ok())
+        || value.parse::<i64>().is_ok()
+}
+
+fn write_value(f: &mut fmt::Formatter<'_>, cond: &Condition, value: &str) -> fmt::Result {
+    if cond.field == "src_ip" && matches!(cond.op, Op::In | Op::NotIn) && value.parse::<i32>().is_ok() {
+        write!(f, "ipgroup({})", value)
+    } else if is_bare(value) {
+        f.write_str(value)
+    } else {
+        write!(f, "{:?}", value)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.field)?;
+        if let Some(ref key) = self.key {
+            write!(f, "[{:?}]", key)?;
+        }
+        write!(f, " {} ", self.op.as_str())?;
+        if self.values.len() == 1 {
+            return write_value(f, self, &self.values[0]);
+        }
+        f.write_str("[")?;
+        for (i, value) in self.values.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write_value(f, self, value)?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (children, separator) = match self {
+            Expr::Cond(c) => return write!(f, "{}", c),
+            Expr::And(children) => (children, " and "),
+            Expr::Or(children) => (children, " or "),
+        };
+        for (i, child) in children.iter().enumerate() {
+            if i > 0 {
+                f.write_str(separator)?;
+            }
+            // `or` inside `and` needs parentheses; `and` groups inside
+            // `or` get them for readability
+            let wrap = match (self, child) {
+                (_, Expr::Cond(_)) => false,
+                (Expr::Or(_), Expr::And(_)) => children.len() > 1,
+                _ => true,
+            };
+            if wrap {
+                write!(f, "({})", child)?;
+            } else {
+                write!(f, "{}", child)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "'{}'", w),
+            Token::Str(s) => write!(f, "{:?}", s),
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+            Token::OpenBracket => f.write_str("'['"),
+            Token::CloseBracket => f.write_str("']'"),
+            Token::Comma => f.write_str("','"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '[' => tokens.push(Token::OpenBracket),
+            ']' => tokens.push(Token::CloseBracket),
+            ',' => tokens.push(Token::Comma),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(Error::InvalidInput("unterminated string in rule".to_string())),
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(other) => value.push(other),
+                            None => return Err(Error::InvalidInput("unterminated string in rule".to_string())),
+                        },
+                        Some(other) => value.push(other),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            _ => {
+                let mut word = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "()[],\"".contains(next) {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, message: impl fmt::Display) -> Error {
+        Error::InvalidInput(format!("rule syntax error at token {}: {}", self.pos + 1, message))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(self.error(format!("expected {}, found {}", expected, token))),
+            None => Err(self.error(format!("expected {}", expected))),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut children = vec![self.and()?];
+        while self.keyword("or") {
+            children.push(self.and()?);
+        }
+        Ok(if children.len() == 1 { children.remove(0) } else { Expr::Or(children) })
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut children = vec![self.atom()?];
+        while self.keyword("and") {
+            children.push(self.atom()?);
+        }
+        Ok(if children.len() == 1 { children.remove(0) } else { Expr::And(children) })
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let expr = self.or()?;
+            self.expect(Token::Close)?;
+            return Ok(expr);
+        }
+        self.condition().map(Expr::Cond)
+    }
+
+    fn condition(&mut self) -> Result<Condition> {
+        let field = match self.next() {
+            Some(Token::Word(w)) if FIELDS.contains(&w.as_str()) => w,
+            Some(Token::Word(w)) => return Err(self.error(format!("unknown field '{}'", w))),
+            Some(token) => return Err(self.error(format!("expected a field, found {}", token))),
+            None => return Err(self.error("expected a field")),
+        };
+
+        let mut key = None;
+        if self.peek() == Some(&Token::OpenBracket) {
+            self.pos += 1;
+            match self.next() {
+                Some(Token::Str(k)) | Some(Token::Word(k)) => key = Some(k),
+                _ => return Err(self.error("expected a header name")),
+            }
+            self.expect(Token::CloseBracket)?;
+        }
+        if field == "header" && key.is_none() {
+            return Err(self.error("header conditions need a name, e.g. header[\"user-agent\"]"));
+        }
+        if field != "header" && key.is_some() {
+            return Err(self.error(format!("field '{}' does not take a name", field)));
+        }
+
+        let op = match self.next() {
+            Some(Token::Word(op)) => Op::parse(&op).map_err(|_| self.error(format!("unknown operator '{}'", op)))?,
+            _ => return Err(self.error("expected an operator")),
+        };
+
+        let mut values = Vec::new();
+        if self.peek() == Some(&Token::OpenBracket) {
+            self.pos += 1;
+            loop {
+                values.push(self.value(&field, op)?);
+                match self.next() {
+                    Some(Token::Comma) => continue,
+                    Some(Token::CloseBracket) => break,
+                    _ => return Err(self.error("expected ',' or ']'")),
+                }
+            }
+        } else {
+            values.push(self.value(&field, op)?);
+        }
+
+        Ok(Condition { field, key, op, values })
+    }
+
+    fn value(&mut self, field: &str, op: Op) -> Result<String> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(s),
+            Some(Token::Word(w)) if w == "ipgroup" => {
+                if field != "src_ip" || !matches!(op, Op::In | Op::NotIn) {
+                    return Err(self.error("ipgroup() is only allowed in 'src_ip in' conditions"));
+                }
+                self.expect(Token::Open)?;
+                let id = match self.next() {
+                    Some(Token::Word(id)) if id.parse::<i32>().is_ok() => id,
+                    _ => return Err(self.error("expected an IP group ID")),
+                };
+                self.expect(Token::Close)?;
+                Ok(id)
+            }
+            Some(Token::Word(w)) if !["and", "or"].contains(&w.to_ascii_lowercase().as_str()) => Ok(w),
+            Some(token) => Err(self.error(format!("expected a value, found {}", token))),
+            None => Err(self.error("expected a value")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_and_decompile() {
+        let source = r#"src_ip in 10.0.0.0/8 and (url_path prefix "/admin" or header["user-agent"] re "sqlmap")"#;
+        let expr = Expr::parse(source).unwrap();
+        assert_eq!(expr.to_string(), source);
+
+        let pattern = expr.to_pattern();
+        assert_eq!(pattern.len(), 2);
+        assert_eq!(pattern[0][0].k, "src_ip");
+        assert_eq!(pattern[0][1].v, vec!["/admin"]);
+        assert_eq!(pattern[1][1].sub_k.as_deref(), Some("user-agent"));
+
+        let decompiled = decompile(&pattern).unwrap();
+        assert_eq!(
+            decompiled,
+            r#"(src_ip in 10.0.0.0/8 and url_path prefix "/admin") or (src_ip in 10.0.0.0/8 and header["user-agent"] re "sqlmap")"#
+        );
+        assert_eq!(compile(&decompiled).unwrap(), pattern);
+
+        let expr = Expr::parse(r#"src_ip not_in [ipgroup(3), 192.168.1.1] AND method eq ["POST", "PUT"]"#).unwrap();
+        assert_eq!(expr.conditions()[0].ip_groups(), vec![3]);
+        assert_eq!(expr.to_string(), r#"src_ip not_in [ipgroup(3), 192.168.1.1] and method eq ["POST", "PUT"]"#);
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let error = |source: &str| Expr::parse(source).unwrap_err().to_string();
+        assert!(error(r#"path prefix "/""#).contains("unknown field 'path'"));
+        assert!(error(r#"url_path starts "/""#).contains("unknown operator"));
+        assert!(error(r#"header re "x""#).contains("need a name"));
+        assert!(error(r#"(host eq "a""#).contains("expected ')'"));
+        assert!(error(r#"host eq ipgroup(1)"#).contains("only allowed"));
+        assert!(error(r#"host eq "a" host eq "b""#).contains("unexpected"));
+    }
+}
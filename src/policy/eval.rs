@@ -0,0 +1,552 @@
+//! Local policy evaluation
+//!
+//! An `Evaluator` runs policy patterns against a `SampleRequest` with the
+//! same operators SafeLine uses, so the effect of a rule can be checked
+//! before it is enabled. Policies are tried in order and the first one
+//! that matches decides the action; `negate` inverts a policy's pattern.
+
+use super::dsl::Op;
+use super::Action;
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use crate::time::unix_now;
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An IP network such as `10.0.0.0/8`; a bare address is a single host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidInput(format!("invalid IP or CIDR {:?}", s));
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse::<u8>().map_err(|_| invalid())?)),
+            None => (s.trim(), None),
+        };
+        let addr = addr.parse::<IpAddr>().map_err(|_| invalid())?.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// The parts of an HTTP request that rules look at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleRequest {
+    pub ip: IpAddr,
+    pub method: String,
+    pub host: String,
+    pub path: String,
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl SampleRequest {
+    /// A `GET /` request from `ip`
+    pub fn new(ip: IpAddr) -> Self {
+        Self {
+            ip,
+            method: "GET".to_string(),
+            host: String::new(),
+            path: "/".to_string(),
+            query: String::new(),
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn method(mut self, method: &str) -> Self {
+        self.method = method.to_string();
+        self
+    }
+
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = host.to_string();
+        self
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    pub fn query(mut self, query: &str) -> Self {
+        self.query = query.to_string();
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+
+    /// Set host, path and query from an absolute URL
+    pub fn url(mut self, url: &str) -> Result<Self> {
+        let url = url::Url::parse(url)?;
+        self.host = url.host_str().unwrap_or_default().to_string();
+        self.path = url.path().to_string();
+        self.query = url.query().unwrap_or_default().to_string();
+        Ok(self)
+    }
+
+    /// Values of a rule field; empty if the request does not have it
+    fn field(&self, field: &str, key: Option<&str>) -> Vec<String> {
+        match field {
+            "src_ip" => vec![self.ip.to_string()],
+            "host" => vec![self.host.clone()],
+            "method" => vec![self.method.clone()],
+            "url_path" => vec![self.path.clone()],
+            "query_string" => vec![self.query.clone()],
+            "uri" if self.query.is_empty() => vec![self.path.clone()],
+            "uri" => vec![format!("{}?{}", self.path, self.query)],
+            "body" => vec![self.body.clone()],
+            "header" => self
+                .headers
+                .iter()
+                .filter(|(name, _)| key.is_some_and(|k| name.eq_ignore_ascii_case(k)))
+                .map(|(_, value)| value.clone())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    Regex(Regex),
+    Net(Cidr),
+    Group(i32),
+}
+
+/// A compiled `RulePattern`
+#[derive(Debug, Clone)]
+struct Matcher {
+    field: String,
+    key: Option<String>,
+    op: Op,
+    values: Vec<Value>,
+}
+
+impl Matcher {
+    fn compile(pattern: &RulePattern) -> Result<Self> {
+        let op = Op::parse(&pattern.op)?;
+        let values = pattern
+            .v
+            .iter()
+            .map(|v| {
+                Ok(match op {
+                    Op::Re => Value::Regex(
+                        Regex::new(v).map_err(|e| Error::InvalidInput(format!("invalid regex {:?}: {}", v, e)))?,
+                    ),
+                    Op::In | Op::NotIn if pattern.k == "src_ip" && v.parse::<i32>().is_ok() => {
+                        Value::Group(v.parse().unwrap_or_default())
+                    }
+                    Op::Eq | Op::NotEq | Op::In | Op::NotIn if pattern.k == "src_ip" => Value::Net(v.parse()?),
+                    _ => Value::Text(v.clone()),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            field: pattern.k.clone(),
+            key: pattern.sub_k.clone().filter(|k| !k.is_empty()),
+            op,
+            values,
+        })
+    }
+
+    fn value_matches(&self, actual: &str, value: &Value, ip_groups: &HashMap<i32, Vec<Cidr>>) -> bool {
+        let case_insensitive = matches!(self.field.as_str(), "host" | "method");
+        match value {
+            Value::Regex(re) => re.is_match(actual),
+            Value::Net(net) => actual.parse().is_ok_and(|ip| net.contains(&ip)),
+            Value::Group(id) => actual
+                .parse()
+                .is_ok_and(|ip| ip_groups.get(id).is_some_and(|nets| nets.iter().any(|n| n.contains(&ip)))),
+            Value::Text(expected) => match self.op {
+                Op::Has | Op::NotHas if case_insensitive => {
+                    actual.to_ascii_lowercase().contains(&expected.to_ascii_lowercase())
+                }
+                Op::Has | Op::NotHas => actual.contains(expected.as_str()),
+                Op::Prefix if case_insensitive => actual.to_ascii_lowercase().starts_with(&expected.to_ascii_lowercase()),
+                Op::Prefix => actual.starts_with(expected.as_str()),
+                _ if case_insensitive => actual.eq_ignore_ascii_case(expected),
+                _ => actual == expected,
+            },
+        }
+    }
+
+    /// Positive operators hold if any value matches; negative ones if none
+    /// does. A missing header only satisfies negative operators.
+    fn matches(&self, request: &SampleRequest, ip_groups: &HashMap<i32, Vec<Cidr>>) -> bool {
+        let actual = request.field(&self.field, self.key.as_deref());
+        let any = actual
+            .iter()
+            .any(|a| self.values.iter().any(|v| self.value_matches(a, v, ip_groups)));
+        match self.op {
+            Op::NotEq | Op::NotHas | Op::NotIn => !any,
+            _ => any,
+        }
+    }
+}
+
+/// A policy compiled for local evaluation
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub id: Option<i32>,
+    pub name: String,
+    pub action: Action,
+    pub enabled: bool,
+    pub negate: bool,
+    /// Unix time after which the rule no longer applies; 0 means never
+    pub expire: i64,
+    groups: Vec<Vec<Matcher>>,
+}
+
+impl Rule {
+    fn new(id: Option<i32>, name: &str, action: i32, pattern: Option<&Pattern>) -> Result<Self> {
+        let groups = pattern
+            .map(|p| {
+                p.iter()
+                    .map(|group| group.iter().map(Matcher::compile).collect::<Result<Vec<_>>>())
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()
+            .map_err(|e| Error::InvalidInput(format!("policy {:?}: {}", name, e)))?
+            .unwrap_or_default();
+
+        Ok(Self {
+            id,
+            name: name.to_string(),
+            action: Action::from_code(action),
+            enabled: true,
+            negate: false,
+            expire: 0,
+            groups,
+        })
+    }
+
+    /// Compile an existing policy
+    pub fn from_policy(policy: &PolicyResponse) -> Result<Self> {
+        let mut rule = Self::new(Some(policy.id), &policy.name, policy.action, policy.pattern.as_ref())?;
+        rule.enabled = policy.is_enabled;
+        rule.negate = policy.negate.unwrap_or(false);
+        rule.expire = policy.expire.unwrap_or(0) as i64;
+        Ok(rule)
+    }
+
+    /// Compile a proposed policy
+    pub fn from_request(request: &PolicyRequest) -> Result<Self> {
+        let mut rule = Self::new(request.id, &request.name, request.action.unwrap_or(0), request.pattern.as_ref())?;
+        rule.enabled = request.is_enabled.unwrap_or(true);
+        rule.expire = request.expire.unwrap_or(0) as i64;
+        Ok(rule)
+    }
+
+    /// IP group IDs referenced by the rule
+    pub fn ip_groups(&self) -> BTreeSet<i32> {
+        self.groups
+            .iter()
+            .flatten()
+            .flat_map(|m| &m.values)
+            .filter_map(|v| match v {
+                Value::Group(id) => Some(*id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the rule is enabled and not expired at `now`
+    pub fn is_active(&self, now: i64) -> bool {
+        self.enabled && (self.expire <= 0 || self.expire > now)
+    }
+
+    /// Whether the request matches the pattern, after applying `negate`;
+    /// an empty pattern matches every request
+    pub fn matches(&self, request: &SampleRequest, ip_groups: &HashMap<i32, Vec<Cidr>>) -> bool {
+        let matched = self.groups.is_empty()
+            || self
+                .groups
+                .iter()
+                .any(|group| group.iter().all(|m| m.matches(request, ip_groups)));
+        matched != self.negate
+    }
+}
+
+/// A policy that matched a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub policy_id: Option<i32>,
+    pub name: String,
+    pub action: Action,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.policy_id {
+            Some(id) => write!(f, "{} (#{}): {}", self.name, id, self.action),
+            None => write!(f, "{}: {}", self.name, self.action),
+        }
+    }
+}
+
+/// Result of evaluating a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evaluation {
+    /// The policy that decides the request, if any
+    pub fired: Option<Verdict>,
+    /// Every considered policy that matched, in order
+    pub matched: Vec<Verdict>,
+}
+
+impl Evaluation {
+    /// Action taken for the request; unmatched requests are allowed
+    pub fn action(&self) -> Action {
+        self.fired.as_ref().map(|v| v.action).unwrap_or(Action::Allow)
+    }
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fired {
+            Some(ref verdict) => write!(f, "fired {}", verdict)?,
+            None => write!(f, "no policy matched")?,
+        }
+        for verdict in self.matched.iter().skip(1) {
+            write!(f, "\n  also matched {}", verdict)?;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates an ordered list of rules against sample requests
+#[derive(Debug, Clone, Default)]
+pub struct Evaluator {
+    pub rules: Vec<Rule>,
+    ip_groups: HashMap<i32, Vec<Cidr>>,
+    include_inactive: bool,
+    now: Option<i64>,
+}
+
+impl Evaluator {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            ..Self::default()
+        }
+    }
+
+    /// Compile policies in evaluation order
+    pub fn from_policies(policies: &[PolicyResponse]) -> Result<Self> {
+        Ok(Self::new(policies.iter().map(Rule::from_policy).collect::<Result<_>>()?))
+    }
+
+    /// Provide the addresses of an IP group referenced by the rules
+    pub fn with_ip_group(mut self, id: i32, ips: &[String]) -> Result<Self> {
+        let nets = ips.iter().map(|ip| ip.parse()).collect::<Result<_>>()?;
+        self.ip_groups.insert(id, nets);
+        Ok(self)
+    }
+
+    /// Also evaluate disabled and expired rules, e.g. to try a rule before
+    /// enabling it
+    pub fn include_inactive(mut self) -> Self {
+        self.include_inactive = true;
+        self
+    }
+
+    /// Evaluate as of the given Unix time instead of now
+    pub fn at(mut self, unix_secs: i64) -> Self {
+        self.now = Some(unix_secs);
+        self
+    }
+
+    /// Insert a rule at `index`, or append it if `index` is past the end
+    pub fn insert(&mut self, index: usize, rule: Rule) {
+        self.rules.insert(index.min(self.rules.len()), rule);
+    }
+
+    /// IP group IDs referenced by any rule
+    pub fn ip_groups(&self) -> BTreeSet<i32> {
+        self.rules.iter().flat_map(|r| r.ip_groups()).collect()
+    }
+
+    /// Indices of the rules that match the request, honoring activity
+    pub fn matching(&self, request: &SampleRequest) -> Vec<usize> {
+        let now = self.now.unwrap_or_else(unix_now);
+
+        self.rules
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| self.include_inactive || r.is_active(now))
+            .filter(|(_, r)| r.matches(request, &self.ip_groups))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Rules that match the request, honoring activity and order
+    pub fn evaluate(&self, request: &SampleRequest) -> Evaluation {
+        let matched: Vec<Verdict> = self
+            .matching(request)
+            .into_iter()
+            .map(|i| &self.rules[i])
+            .map(|r| Verdict {
+                policy_id: r.id,
+                name: r.name.clone(),
+                action: r.action,
+            })
+            .collect();
+
+        Evaluation {
+            fired: matched.first().cloned(),
+            matched,
+        }
+    }
+}
+
+impl Client {
+    /// Build a policy evaluator
+    ///
+    /// Compiles the current policies in list order and loads the IP
+    /// groups they reference.
+    ///
+    /// # Returns
+    ///
+    /// Returns an evaluator for the live policy set
+    pub async fn policy_evaluator(&self) -> Result<Evaluator> {
+        let evaluator = Evaluator::from_policies(&self.list_policies().await?.data)?;
+        self.load_ip_groups(evaluator).await
+    }
+
+    /// Load the IP groups referenced by an evaluator's rules
+    pub(crate) async fn load_ip_groups(&self, mut evaluator: Evaluator) -> Result<Evaluator> {
+        for id in evaluator.ip_groups() {
+            let group = self.ip_group_detail(&IPGroupDetailRequest { id }).await?.data;
+            evaluator = evaluator.with_ip_group(id, &group.ips.unwrap_or_default())?;
+        }
+        Ok(evaluator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::dsl;
+
+    fn rule(id: i32, action: i32, source: &str) -> Rule {
+        let request = PolicyRequest {
+            id: Some(id),
+            name: format!("rule {}", id),
+            action: Some(action),
+            level: None,
+            pattern: Some(dsl::compile(source).unwrap()),
+            expire: None,
+            is_enabled: Some(true),
+            log: None,
+            auth_source_ids: None,
+        };
+        Rule::from_request(&request).unwrap()
+    }
+
+    #[test]
+    fn test_cidr() {
+        let net: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(net.contains(&"10.1.2.3".parse().unwrap()));
+        assert!(net.contains(&"::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!net.contains(&"11.0.0.1".parse().unwrap()));
+        assert!("2001:db8::/32".parse::<Cidr>().unwrap().contains(&"2001:db8::1".parse().unwrap()));
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(&"1.2.3.4".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let evaluator = Evaluator::new(vec![
+            rule(1, 0, "src_ip in ipgroup(7)"),
+            rule(2, 1, r#"src_ip in 10.0.0.0/8 and (url_path prefix "/admin" or header["user-agent"] re "(?i)sqlmap")"#),
+            rule(3, 2, r#"method eq "post" and header["x-token"] not_eq "secret""#),
+        ])
+        .with_ip_group(7, &["10.9.0.0/16".to_string()])
+        .unwrap();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        let admin = SampleRequest::new(ip("10.1.1.1")).url("https://example.com/admin/users").unwrap();
+        let result = evaluator.evaluate(&admin);
+        assert_eq!(result.fired.as_ref().and_then(|v| v.policy_id), Some(2));
+        assert_eq!(result.action(), Action::Deny);
+
+        let allowed = evaluator.evaluate(&SampleRequest::new(ip("10.9.1.1")).path("/admin"));
+        assert_eq!(allowed.action(), Action::Allow);
+        assert_eq!(allowed.matched.len(), 2);
+
+        let scanner = SampleRequest::new(ip("10.2.2.2")).header("User-Agent", "SQLMap/1.7");
+        assert_eq!(evaluator.evaluate(&scanner).fired.unwrap().policy_id, Some(2));
+
+        let post = SampleRequest::new(ip("8.8.8.8")).method("POST");
+        assert_eq!(evaluator.evaluate(&post).action(), Action::Other(2));
+        let authorized = post.header("X-Token", "secret");
+        assert!(evaluator.evaluate(&authorized).fired.is_none());
+    }
+
+    #[test]
+    fn test_negate_and_inactive() {
+        let mut negated = rule(1, 1, r#"host eq "example.com""#);
+        negated.negate = true;
+        let mut disabled = rule(2, 2, r#"url_path prefix "/""#);
+        disabled.enabled = false;
+        let mut expired = rule(3, 1, r#"url_path prefix "/""#);
+        expired.expire = 1000;
+
+        let evaluator = Evaluator::new(vec![negated, disabled, expired]).at(2000);
+        let request = SampleRequest::new("1.1.1.1".parse().unwrap());
+        assert_eq!(evaluator.evaluate(&request.clone().host("other.com")).action(), Action::Deny);
+        assert!(evaluator.evaluate(&request.clone().host("EXAMPLE.com")).fired.is_none());
+
+        let all = evaluator.include_inactive();
+        let result = all.evaluate(&request.host("example.com"));
+        assert_eq!(result.action(), Action::Other(2));
+        assert_eq!(result.matched.len(), 2);
+    }
+}
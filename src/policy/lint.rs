@@ -0,0 +1,449 @@
+//! Policy linting
+//!
+//! `lint` checks an ordered policy list, either the live policies or
+//! proposed `PolicyRequest`s, for rules that are broken or likely to do
+//! something unintended. Every finding carries a stable code so that
+//! tooling can filter or suppress individual checks.
+
+use super::dsl::Op;
+use super::eval::Cidr;
+use super::Action;
+use crate::client::Client;
+use crate::error::Result;
+use crate::models::*;
+use crate::time::unix_now;
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// How serious a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LintSeverity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl LintSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintSeverity::Info => "info",
+            LintSeverity::Warning => "warning",
+            LintSeverity::Error => "error",
+        }
+    }
+}
+
+/// Stable identifier of a lint check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LintCode {
+    /// A `re` condition does not compile
+    InvalidRegex,
+    /// The rule matches every request
+    MatchesEverything,
+    /// An earlier allow rule matches everything a deny rule matches
+    ShadowedDeny,
+    /// Another rule has the same pattern
+    Duplicate,
+    /// The rule is enabled but past its expiry time
+    ExpiredEnabled,
+    /// A deny rule does not log its matches
+    DenyWithoutLog,
+    /// The rule references an IP group that does not exist
+    MissingIpGroup,
+    /// An unknown operator or malformed IP address
+    InvalidPattern,
+}
+
+impl LintCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintCode::InvalidRegex => "P001",
+            LintCode::MatchesEverything => "P002",
+            LintCode::ShadowedDeny => "P003",
+            LintCode::Duplicate => "P004",
+            LintCode::ExpiredEnabled => "P005",
+            LintCode::DenyWithoutLog => "P006",
+            LintCode::MissingIpGroup => "P007",
+            LintCode::InvalidPattern => "P008",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintCode::InvalidRegex => "invalid-regex",
+            LintCode::MatchesEverything => "matches-everything",
+            LintCode::ShadowedDeny => "shadowed-deny",
+            LintCode::Duplicate => "duplicate",
+            LintCode::ExpiredEnabled => "expired-enabled",
+            LintCode::DenyWithoutLog => "deny-without-log",
+            LintCode::MissingIpGroup => "missing-ip-group",
+            LintCode::InvalidPattern => "invalid-pattern",
+        }
+    }
+}
+
+/// A problem found in a policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub code: LintCode,
+    pub severity: LintSeverity,
+    pub policy_id: Option<i32>,
+    pub policy: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}] {:?}", self.severity.as_str(), self.code.as_str(), self.policy)?;
+        if let Some(id) = self.policy_id {
+            write!(f, " (#{})", id)?;
+        }
+        write!(f, ": {} ({})", self.message, self.code.name())
+    }
+}
+
+/// The parts of a policy the lints look at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintPolicy {
+    pub id: Option<i32>,
+    pub name: String,
+    pub action: Action,
+    pub pattern: Pattern,
+    pub enabled: bool,
+    pub negate: bool,
+    pub log: bool,
+    pub expire: i64,
+}
+
+impl From<&PolicyResponse> for LintPolicy {
+    fn from(policy: &PolicyResponse) -> Self {
+        Self {
+            id: Some(policy.id),
+            name: policy.name.clone(),
+            action: Action::from_code(policy.action),
+            pattern: policy.pattern.clone().unwrap_or_default(),
+            enabled: policy.is_enabled,
+            negate: policy.negate.unwrap_or(false),
+            log: policy.log,
+            expire: policy.expire.unwrap_or(0) as i64,
+        }
+    }
+}
+
+impl From<&PolicyRequest> for LintPolicy {
+    fn from(request: &PolicyRequest) -> Self {
+        Self {
+            id: request.id,
+            name: request.name.clone(),
+            action: Action::from_code(request.action.unwrap_or(0)),
+            pattern: request.pattern.clone().unwrap_or_default(),
+            enabled: request.is_enabled.unwrap_or(true),
+            negate: false,
+            log: request.log.unwrap_or(false),
+            expire: request.expire.unwrap_or(0) as i64,
+        }
+    }
+}
+
+fn is_ip_field(pattern: &RulePattern) -> bool {
+    pattern.k == "src_ip"
+}
+
+/// Whether a single condition holds for every request
+fn always_true(pattern: &RulePattern) -> bool {
+    let Ok(op) = Op::parse(&pattern.op) else {
+        return false;
+    };
+    pattern.v.iter().any(|v| match op {
+        Op::Prefix => v.is_empty() || (v == "/" && matches!(pattern.k.as_str(), "url_path" | "uri")),
+        Op::Has => v.is_empty(),
+        Op::Re => ["", ".*", "^", "^.*", ".*$", "^.*$"].contains(&v.as_str()),
+        Op::Eq | Op::In if is_ip_field(pattern) => v.parse::<Cidr>().is_ok_and(|n| n.prefix == 0),
+        _ => false,
+    })
+}
+
+fn matches_everything(policy: &LintPolicy) -> bool {
+    !policy.negate && (policy.pattern.is_empty() || policy.pattern.iter().any(|g| g.iter().all(always_true)))
+}
+
+/// Whether `general` holds whenever `specific` holds
+fn condition_implies(specific: &RulePattern, general: &RulePattern) -> bool {
+    if always_true(general) {
+        return true;
+    }
+    if specific.k != general.k || specific.sub_k != general.sub_k {
+        return false;
+    }
+    let (Ok(s_op), Ok(g_op)) = (Op::parse(&specific.op), Op::parse(&general.op)) else {
+        return false;
+    };
+
+    // Every alternative of the specific condition must be covered by some
+    // alternative of the general one
+    let covered = |s: &String| {
+        general.v.iter().any(|g| match (s_op, g_op) {
+            (Op::Eq | Op::In, Op::Eq | Op::In) if is_ip_field(specific) => {
+                match (s.parse::<Cidr>(), g.parse::<Cidr>()) {
+                    (Ok(s), Ok(g)) => s.prefix >= g.prefix && g.contains(&s.addr),
+                    _ => s == g,
+                }
+            }
+            (Op::Eq | Op::In, Op::Eq | Op::In) | (Op::Prefix, Op::Prefix) | (Op::Has, Op::Has) if s == g => true,
+            (Op::Eq | Op::In | Op::Prefix, Op::Prefix) => s.starts_with(g.as_str()),
+            (Op::Eq | Op::In | Op::Prefix | Op::Has, Op::Has) => s.contains(g.as_str()),
+            (Op::Re, Op::Re) => s == g,
+            _ => false,
+        })
+    };
+    !specific.v.is_empty() && specific.v.iter().all(covered)
+}
+
+/// Whether `general` matches every request `specific` matches
+fn pattern_covers(general: &Pattern, specific: &Pattern) -> bool {
+    general.is_empty()
+        || specific.iter().all(|s_group| {
+            general
+                .iter()
+                .any(|g_group| g_group.iter().all(|g| s_group.iter().any(|s| condition_implies(s, g))))
+        })
+}
+
+/// Field, header name, operator and sorted values of a condition
+type NormalizedCondition = (String, Option<String>, String, Vec<String>);
+
+/// Canonical form of a pattern for duplicate detection
+fn normalize(pattern: &Pattern) -> Vec<Vec<NormalizedCondition>> {
+    let mut groups: Vec<Vec<_>> = pattern
+        .iter()
+        .map(|group| {
+            let mut conds: Vec<_> = group
+                .iter()
+                .map(|p| {
+                    let mut values = p.v.clone();
+                    values.sort();
+                    values.dedup();
+                    (p.k.clone(), p.sub_k.clone().filter(|k| !k.is_empty()), p.op.clone(), values)
+                })
+                .collect();
+            conds.sort();
+            conds.dedup();
+            conds
+        })
+        .collect();
+    groups.sort();
+    groups.dedup();
+    groups
+}
+
+/// Lint an ordered list of policies
+///
+/// # Arguments
+///
+/// * `policies` - Policies in evaluation order
+/// * `ip_groups` - IDs of existing IP groups; `None` skips that check
+/// * `now` - Unix time used for the expiry check
+pub fn lint(policies: &[LintPolicy], ip_groups: Option<&BTreeSet<i32>>, now: i64) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for (i, policy) in policies.iter().enumerate() {
+        let mut finding = |code: LintCode, severity: LintSeverity, message: String| {
+            findings.push(Finding {
+                code,
+                severity,
+                policy_id: policy.id,
+                policy: policy.name.clone(),
+                message,
+            })
+        };
+
+        let mut valid = true;
+        for pattern in policy.pattern.iter().flatten() {
+            let op = match Op::parse(&pattern.op) {
+                Ok(op) => op,
+                Err(_) => {
+                    valid = false;
+                    finding(LintCode::InvalidPattern, LintSeverity::Error, format!("unknown operator {:?}", pattern.op));
+                    continue;
+                }
+            };
+            for value in &pattern.v {
+                match op {
+                    Op::Re => {
+                        if let Err(e) = Regex::new(value) {
+                            valid = false;
+                            let error = e.to_string().lines().last().unwrap_or_default().to_string();
+                            finding(LintCode::InvalidRegex, LintSeverity::Error, format!("invalid regex {:?}: {}", value, error));
+                        }
+                    }
+                    Op::In | Op::NotIn if is_ip_field(pattern) && value.parse::<i32>().is_ok() => {
+                        let id: i32 = value.parse().unwrap_or_default();
+                        if ip_groups.is_some_and(|groups| !groups.contains(&id)) {
+                            finding(LintCode::MissingIpGroup, LintSeverity::Error, format!("IP group {} does not exist", id));
+                        }
+                    }
+                    Op::Eq | Op::NotEq | Op::In | Op::NotIn if is_ip_field(pattern) && value.parse::<Cidr>().is_err() => {
+                        valid = false;
+                        finding(LintCode::InvalidPattern, LintSeverity::Error, format!("invalid IP or CIDR {:?}", value));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if matches_everything(policy) {
+            let severity = if policy.action == Action::Allow { LintSeverity::Warning } else { LintSeverity::Error };
+            finding(LintCode::MatchesEverything, severity, format!("{} rule matches every request", policy.action));
+        }
+
+        if policy.enabled && policy.expire > 0 && policy.expire <= now {
+            finding(LintCode::ExpiredEnabled, LintSeverity::Warning, format!("expired at {} but still enabled", policy.expire));
+        }
+
+        if policy.action == Action::Deny && !policy.log {
+            finding(LintCode::DenyWithoutLog, LintSeverity::Info, "deny rule does not log its matches".to_string());
+        }
+
+        let normalized = normalize(&policy.pattern);
+        if let Some(earlier) = policies[..i].iter().find(|p| p.negate == policy.negate && normalize(&p.pattern) == normalized) {
+            finding(
+                LintCode::Duplicate,
+                LintSeverity::Warning,
+                format!("same pattern as {:?} ({} vs {})", earlier.name, earlier.action, policy.action),
+            );
+        }
+
+        if valid && policy.action == Action::Deny && policy.enabled && !policy.negate {
+            let shadowing = policies[..i].iter().find(|p| {
+                p.action == Action::Allow && p.enabled && !p.negate && pattern_covers(&p.pattern, &policy.pattern)
+            });
+            if let Some(allow) = shadowing {
+                finding(
+                    LintCode::ShadowedDeny,
+                    LintSeverity::Warning,
+                    format!("never fires: earlier allow rule {:?} matches every request it matches", allow.name),
+                );
+            }
+        }
+    }
+
+    findings
+}
+
+impl Client {
+    /// Lint the live policies
+    ///
+    /// # Returns
+    ///
+    /// Returns the findings in policy order
+    pub async fn lint_policies(&self) -> Result<Vec<Finding>> {
+        let policies: Vec<LintPolicy> = self.list_policies().await?.data.iter().map(LintPolicy::from).collect();
+        self.lint_against_ip_groups(&policies).await
+    }
+
+    /// Lint proposed policies
+    ///
+    /// The policies are checked among themselves; IP group references are
+    /// checked against the live IP groups.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests` - Proposed policies in evaluation order
+    ///
+    /// # Returns
+    ///
+    /// Returns the findings in policy order
+    pub async fn lint_policy_requests(&self, requests: &[PolicyRequest]) -> Result<Vec<Finding>> {
+        let policies: Vec<LintPolicy> = requests.iter().map(LintPolicy::from).collect();
+        self.lint_against_ip_groups(&policies).await
+    }
+
+    async fn lint_against_ip_groups(&self, policies: &[LintPolicy]) -> Result<Vec<Finding>> {
+        let groups: BTreeSet<i32> = self
+            .ip_group_list(&IPGroupListRequest { top: None })
+            .await?
+            .nodes
+            .iter()
+            .filter_map(|g| g.id)
+            .collect();
+        let now = unix_now();
+        Ok(lint(policies, Some(&groups), now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::dsl;
+
+    fn policy(name: &str, action: Action, source: &str) -> LintPolicy {
+        LintPolicy {
+            id: None,
+            name: name.to_string(),
+            action,
+            pattern: if source.is_empty() { Vec::new() } else { dsl::compile(source).unwrap() },
+            enabled: true,
+            negate: false,
+            log: true,
+            expire: 0,
+        }
+    }
+
+    fn codes(findings: &[Finding]) -> Vec<(&str, &'static str)> {
+        findings.iter().map(|f| (f.policy.as_str(), f.code.as_str())).collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let mut expired = policy("expired", Action::Other(2), r#"host eq "old.example.com""#);
+        expired.expire = 100;
+        let mut quiet = policy("quiet deny", Action::Deny, r#"url_path prefix "/private""#);
+        quiet.log = false;
+
+        let policies = vec![
+            policy("trust office", Action::Allow, r#"src_ip in 10.0.0.0/8 and url_path prefix "/""#),
+            policy("block lab", Action::Deny, r#"src_ip in 10.1.0.0/16 and url_path prefix "/admin""#),
+            policy("bad regex", Action::Deny, r#"header["user-agent"] re "(sqlmap""#),
+            policy("everything", Action::Deny, r#"url_path prefix "/""#),
+            policy("empty", Action::Allow, ""),
+            policy("copy", Action::Deny, r#"url_path prefix "/admin" and src_ip in 10.1.0.0/16"#),
+            expired,
+            quiet,
+            policy("groups", Action::Allow, "src_ip in [ipgroup(1), ipgroup(2)]"),
+        ];
+        let groups = BTreeSet::from([1]);
+        let findings = lint(&policies, Some(&groups), 1000);
+
+        assert_eq!(
+            codes(&findings),
+            vec![
+                ("block lab", "P003"),
+                ("bad regex", "P001"),
+                ("everything", "P002"),
+                ("empty", "P002"),
+                ("copy", "P004"),
+                ("copy", "P003"),
+                ("expired", "P005"),
+                ("quiet deny", "P006"),
+                ("quiet deny", "P003"),
+                ("groups", "P007"),
+            ]
+        );
+        assert_eq!(findings[2].severity, LintSeverity::Error);
+        assert_eq!(findings[3].severity, LintSeverity::Warning);
+        assert!(findings[0].to_string().starts_with(r#"warning[P003] "block lab": never fires"#));
+    }
+
+    #[test]
+    fn test_shadowing_requires_coverage() {
+        let allow = |source: &str| policy("allow", Action::Allow, source);
+        let deny = policy("deny", Action::Deny, r#"url_path prefix "/api/admin" or host eq "b.com""#);
+        let shadowed = |a: LintPolicy| lint(&[a, deny.clone()], None, 0).iter().any(|f| f.code == LintCode::ShadowedDeny);
+
+        assert!(!shadowed(allow(r#"url_path prefix "/api""#)));
+        assert!(shadowed(allow(r#"url_path prefix "/api" or host eq ["a.com", "b.com"]"#)));
+        assert!(!shadowed(allow(r#"url_path has "admin" and host eq "a.com""#)));
+        assert!(shadowed(allow(r#"url_path has "admin" or host has "b.""#)));
+    }
+}
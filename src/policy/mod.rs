@@ -1,7 +1,54 @@
+pub mod bundle;
+pub mod dsl;
+pub mod eval;
+pub mod lint;
+pub mod replay;
+pub mod schedule;
+
 use crate::client::Client;
 use crate::error::Result;
 use crate::models::*;
 use std::collections::HashMap;
+use std::fmt;
+
+/// What a policy does with a matching request
+///
+/// The API only documents allow (0) and deny (1); other codes are kept
+/// as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Allow,
+    Deny,
+    Other(i32),
+}
+
+impl Action {
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            0 => Action::Allow,
+            1 => Action::Deny,
+            other => Action::Other(other),
+        }
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            Action::Allow => 0,
+            Action::Deny => 1,
+            Action::Other(code) => *code,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Allow => f.write_str("allow"),
+            Action::Deny => f.write_str("deny"),
+            Action::Other(code) => write!(f, "action {}", code),
+        }
+    }
+}
 
 impl Client {
     /// List policies (GET /open/policy)
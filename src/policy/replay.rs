@@ -0,0 +1,341 @@
+//! Replay historical records against candidate policies
+//!
+//! Detect logs, ACL logs and challenge records keep enough of the original
+//! request (source IP, host and, for detect logs, method, path, query and
+//! headers) to rebuild a `SampleRequest`. Replaying them through candidate
+//! rules shows how much past traffic each rule would have matched, which
+//! helps to spot false positives before a rule is rolled out.
+
+use super::eval::{Evaluator, Rule, SampleRequest};
+use super::Action;
+use crate::client::Client;
+use crate::error::Result;
+use crate::models::*;
+use crate::time::parse_timestamp;
+use futures::future;
+use futures::stream::{StreamExt, TryStreamExt};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::pin::pin;
+use tracing::info;
+
+/// Where a replayed request came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ReplaySource {
+    Detect,
+    Acl,
+    Challenge,
+}
+
+/// A historical request rebuilt from a record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplaySample {
+    pub source: ReplaySource,
+    pub request: SampleRequest,
+    /// Attack type of detect logs; `acl` or `challenge` for other records
+    pub attack_type: String,
+}
+
+/// Parse a raw header block into name/value pairs, skipping the request
+/// line and anything that is not a header
+pub fn parse_raw_headers(raw: &str) -> Vec<(String, String)> {
+    raw.lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| !name.is_empty() && !name.contains(char::is_whitespace))
+        .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+        .collect()
+}
+
+impl ReplaySample {
+    /// Rebuild the request of a detect log; `None` if its source IP is
+    /// unusable
+    pub fn from_detect_log(log: &DetectLog) -> Option<Self> {
+        let mut request = SampleRequest::new(log.src_ip.parse().ok()?)
+            .method(&log.method)
+            .host(&log.host)
+            .path(&log.url_path)
+            .query(log.query_string.as_deref().unwrap_or_default())
+            .body(log.req_body.as_deref().unwrap_or_default());
+        request.headers = parse_raw_headers(log.req_header.as_deref().unwrap_or_default());
+
+        Some(Self {
+            source: ReplaySource::Detect,
+            request,
+            attack_type: log.attack_type.to_string(),
+        })
+    }
+
+    /// Rebuild what is known of a banned request: source IP and site
+    pub fn from_acl_log(log: &ACLLog) -> Option<Self> {
+        let host = log.site_server_names.as_ref().and_then(|names| names.first()).cloned().unwrap_or_default();
+        Some(Self {
+            source: ReplaySource::Acl,
+            request: SampleRequest::new(log.ip.parse().ok()?).host(&host),
+            attack_type: "acl".to_string(),
+        })
+    }
+
+    /// Rebuild what is known of a challenged request: source IP and site
+    pub fn from_challenge_log(log: &ChallengeLog) -> Option<Self> {
+        let host = log.site_server_names.first().cloned().unwrap_or_default();
+        Some(Self {
+            source: ReplaySource::Challenge,
+            request: SampleRequest::new(log.ip.parse().ok()?).host(&host),
+            attack_type: "challenge".to_string(),
+        })
+    }
+}
+
+/// Matches of one rule over the replayed records
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleReplay {
+    pub policy_id: Option<i32>,
+    pub name: String,
+    pub action: Action,
+    pub matched: usize,
+    pub by_host: BTreeMap<String, usize>,
+    pub by_attack_type: BTreeMap<String, usize>,
+}
+
+/// Result of replaying records against a set of rules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Per-rule results in rule order; each rule is counted on its own,
+    /// regardless of which rule would have fired first
+    pub rules: Vec<RuleReplay>,
+    pub replayed: BTreeMap<ReplaySource, usize>,
+    /// Records whose request could not be rebuilt
+    pub skipped: usize,
+}
+
+impl ReplayReport {
+    /// Replay samples through every rule of the evaluator
+    pub fn new(evaluator: &Evaluator, samples: &[ReplaySample], skipped: usize) -> Self {
+        let mut rules: Vec<RuleReplay> = evaluator
+            .rules
+            .iter()
+            .map(|r| RuleReplay {
+                policy_id: r.id,
+                name: r.name.clone(),
+                action: r.action,
+                matched: 0,
+                by_host: BTreeMap::new(),
+                by_attack_type: BTreeMap::new(),
+            })
+            .collect();
+        let mut replayed = BTreeMap::new();
+
+        for sample in samples {
+            *replayed.entry(sample.source).or_insert(0) += 1;
+            for i in evaluator.matching(&sample.request) {
+                let rule = &mut rules[i];
+                rule.matched += 1;
+                *rule.by_host.entry(sample.request.host.clone()).or_insert(0) += 1;
+                *rule.by_attack_type.entry(sample.attack_type.clone()).or_insert(0) += 1;
+            }
+        }
+
+        Self {
+            rules,
+            replayed,
+            skipped,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.replayed.values().sum()
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        for rule in &self.rules {
+            let percent = if total > 0 { rule.matched as f64 * 100.0 / total as f64 } else { 0.0 };
+            writeln!(f, "{} ({}): {} matched ({:.1}%)", rule.name, rule.action, rule.matched, percent)?;
+            for (host, count) in &rule.by_host {
+                writeln!(f, "  host {}: {}", host, count)?;
+            }
+            for (attack_type, count) in &rule.by_attack_type {
+                writeln!(f, "  attack type {}: {}", attack_type, count)?;
+            }
+        }
+        write!(f, "Replayed {} records, {} skipped", total, self.skipped)
+    }
+}
+
+/// What to replay
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    /// Unix time range
+    pub start: i64,
+    pub end: i64,
+    pub include_acl: bool,
+    pub include_challenge: bool,
+    /// Page size of detect log and challenge record requests
+    pub page_size: i32,
+    /// Stop fetching a record type after this many records in the range
+    pub limit: Option<usize>,
+}
+
+impl ReplayOptions {
+    pub fn new(start: i64, end: i64) -> Self {
+        Self {
+            start,
+            end,
+            include_acl: true,
+            include_challenge: true,
+            page_size: 100,
+            limit: None,
+        }
+    }
+
+    pub fn detect_only(mut self) -> Self {
+        self.include_acl = false;
+        self.include_challenge = false;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+impl Client {
+    /// Replay historical records against candidate policies
+    ///
+    /// Fetches detect logs and, if enabled, ACL logs and challenge records
+    /// in the time range, rebuilds their requests and counts how many each
+    /// candidate would have matched. Disabled candidates are evaluated as
+    /// well.
+    ///
+    /// # Arguments
+    ///
+    /// * `candidates` - Proposed policies in evaluation order
+    /// * `options` - Time range and record types
+    ///
+    /// # Returns
+    ///
+    /// Returns per-rule match counts by host and attack type
+    pub async fn replay_policies(&self, candidates: &[PolicyRequest], options: &ReplayOptions) -> Result<ReplayReport> {
+        let rules = candidates.iter().map(Rule::from_request).collect::<Result<Vec<_>>>()?;
+        let evaluator = self.load_ip_groups(Evaluator::new(rules).include_inactive()).await?;
+
+        let mut samples = Vec::new();
+        let mut skipped = 0;
+        let limit = options.limit.unwrap_or(usize::MAX);
+        let mut add = |sample: Option<ReplaySample>| match sample {
+            Some(sample) => samples.push(sample),
+            None => skipped += 1,
+        };
+
+        let detect = self.detect_logs(DetectLogSearchRequest {
+            page_size: Some(options.page_size),
+            start: Some(options.start),
+            end: Some(options.end),
+            ..Default::default()
+        });
+        let mut detect = pin!(detect.take(limit));
+        while let Some(log) = detect.next().await {
+            add(ReplaySample::from_detect_log(&log?));
+        }
+
+        if options.include_acl {
+            // The ACL log endpoint has no time filter and lists the newest
+            // logs first, so paging stops at the first log before the range
+            let created = |log: &ACLLog| log.created_at.as_deref().and_then(parse_timestamp);
+            let acl = self
+                .acl_logs()
+                .try_take_while(|log| future::ready(Ok(created(log).is_none_or(|t| t >= options.start))))
+                .try_filter(|log| future::ready(created(log).is_none_or(|t| t <= options.end)));
+            let mut acl = pin!(acl.take(limit));
+            while let Some(log) = acl.next().await {
+                add(ReplaySample::from_acl_log(&log?));
+            }
+        }
+
+        if options.include_challenge {
+            let challenge = self.challenge_records(ChallengeRecordSearchRequest {
+                page_size: Some(options.page_size),
+                started_at_begin: Some(options.start),
+                started_at_end: Some(options.end),
+                ..Default::default()
+            });
+            let mut challenge = pin!(challenge.take(limit));
+            while let Some(log) = challenge.next().await {
+                add(ReplaySample::from_challenge_log(&log?));
+            }
+        }
+
+        info!("replaying {} records against {} rules", samples.len(), evaluator.rules.len());
+        Ok(ReplayReport::new(&evaluator, &samples, skipped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::dsl;
+
+    #[test]
+    fn test_parse_raw_headers() {
+        let raw = "GET /a?b=1 HTTP/1.1\r\nHost: example.com\r\nUser-Agent: curl/8.0\r\nX-Time: 10:30\r\n";
+        assert_eq!(
+            parse_raw_headers(raw),
+            vec![
+                ("Host".to_string(), "example.com".to_string()),
+                ("User-Agent".to_string(), "curl/8.0".to_string()),
+                ("X-Time".to_string(), "10:30".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay_report() {
+        let rule = |name: &str, source: &str| {
+            Rule::from_request(&PolicyRequest {
+                id: None,
+                name: name.to_string(),
+                action: Some(1),
+                level: None,
+                pattern: Some(dsl::compile(source).unwrap()),
+                expire: None,
+                is_enabled: Some(false),
+                log: None,
+                auth_source_ids: None,
+            })
+            .unwrap()
+        };
+        let evaluator = Evaluator::new(vec![
+            rule("admin", r#"url_path prefix "/admin""#),
+            rule("internal", "src_ip in 10.0.0.0/8"),
+        ])
+        .include_inactive();
+
+        let detect = |ip: &str, host: &str, path: &str, attack_type: &str| ReplaySample {
+            source: ReplaySource::Detect,
+            request: SampleRequest::new(ip.parse().unwrap()).host(host).path(path),
+            attack_type: attack_type.to_string(),
+        };
+        let samples = vec![
+            detect("10.0.0.1", "a.com", "/admin/x", "sqli"),
+            detect("1.1.1.1", "b.com", "/admin", "xss"),
+            detect("1.1.1.1", "a.com", "/", "xss"),
+            ReplaySample {
+                source: ReplaySource::Acl,
+                request: SampleRequest::new("10.0.0.2".parse().unwrap()).host("a.com"),
+                attack_type: "acl".to_string(),
+            },
+        ];
+
+        let report = ReplayReport::new(&evaluator, &samples, 1);
+        assert_eq!(report.total(), 4);
+        assert_eq!(report.rules[0].matched, 2);
+        assert_eq!(report.rules[0].by_host["b.com"], 1);
+        assert_eq!(report.rules[0].by_attack_type["xss"], 1);
+        assert_eq!(report.rules[1].matched, 2);
+        assert_eq!(report.rules[1].by_attack_type["acl"], 1);
+        assert!(report.to_string().ends_with("Replayed 4 records, 1 skipped"));
+    }
+}
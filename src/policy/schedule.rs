@@ -0,0 +1,422 @@
+//! Scheduled policy windows
+//!
+//! `PolicyRequest.expire` can only end a policy. A `PolicySchedule` instead
+//! says when a policy should be enabled: during one-off windows with a
+//! start and end time, or during recurring windows written as cron
+//! expressions (`minute hour day-of-month month day-of-week`) that match
+//! every minute the policy should be active. For example a rule that
+//! denies `/admin` outside business hours on weekdays is active during
+//! `* 0-8,18-23 * * 1-5` and `* * * * 0,6`.
+//!
+//! The schedule is stored as JSON. Reconciling compares the desired state
+//! of each policy with the live one and switches policies that differ, so
+//! transitions missed while the scheduler was not running are applied on
+//! the next start.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use crate::time::{civil_from_days, unix_now};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// A set of allowed values for one cron field, as a bit mask
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CronField(u64);
+
+impl CronField {
+    fn parse(field: &str, min: u32, max: u32) -> Result<Self> {
+        let invalid = || Error::InvalidInput(format!("invalid cron field {:?}", field));
+        let mut mask = 0u64;
+
+        for item in field.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+                None => (item, 1),
+            };
+            let (start, end) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((a, b)) => (a.parse().map_err(|_| invalid())?, b.parse().map_err(|_| invalid())?),
+                    None => {
+                        let value = range.parse().map_err(|_| invalid())?;
+                        // `5/15` means from 5 to the end in steps of 15
+                        (value, if item.contains('/') { max } else { value })
+                    }
+                },
+            };
+            if step == 0 || start < min || end > max || start > end {
+                return Err(invalid());
+            }
+            for value in (start..=end).step_by(step as usize) {
+                mask |= 1 << value;
+            }
+        }
+
+        Ok(Self(mask))
+    }
+
+    fn contains(&self, value: u32) -> bool {
+        self.0 & (1 << value) != 0
+    }
+
+    fn is_full(&self, min: u32, max: u32) -> bool {
+        (min..=max).all(|v| self.contains(v))
+    }
+}
+
+/// A five-field cron expression matching minutes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    source: String,
+    minute: CronField,
+    hour: CronField,
+    day: CronField,
+    month: CronField,
+    weekday: CronField,
+}
+
+impl FromStr for CronExpr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(Error::InvalidInput(format!("cron expression needs 5 fields: {:?}", s)));
+        }
+        let mut weekday = CronField::parse(fields[4], 0, 7)?;
+        // Both 0 and 7 are Sunday
+        if weekday.contains(7) {
+            weekday.0 |= 1;
+        }
+
+        Ok(Self {
+            source: fields.join(" "),
+            minute: CronField::parse(fields[0], 0, 59)?,
+            hour: CronField::parse(fields[1], 0, 23)?,
+            day: CronField::parse(fields[2], 1, 31)?,
+            month: CronField::parse(fields[3], 1, 12)?,
+            weekday,
+        })
+    }
+}
+
+impl fmt::Display for CronExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Serialize for CronExpr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for CronExpr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        source.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl CronExpr {
+    /// Whether the expression matches the minute containing `local`, a
+    /// Unix time already shifted to the schedule's time zone
+    pub fn matches(&self, local: i64) -> bool {
+        let days = local.div_euclid(86400);
+        let seconds = local.rem_euclid(86400);
+        let (_, month, day) = civil_from_days(days);
+        // 1970-01-01 was a Thursday
+        let weekday = (days + 4).rem_euclid(7) as u32;
+
+        // As in cron, a restricted day of month and day of week match if
+        // either does
+        let day_matches = match (self.day.is_full(1, 31), self.weekday.is_full(0, 6)) {
+            (true, _) => self.weekday.contains(weekday),
+            (false, true) => self.day.contains(day),
+            (false, false) => self.day.contains(day) || self.weekday.contains(weekday),
+        };
+
+        self.minute.contains((seconds / 60 % 60) as u32)
+            && self.hour.contains((seconds / 3600) as u32)
+            && self.month.contains(month)
+            && day_matches
+    }
+}
+
+/// When a policy should be enabled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Window {
+    /// From `start` until `end`, as Unix times
+    Once { start: i64, end: i64 },
+    /// Every minute matched by the cron expression
+    Recurring { cron: CronExpr },
+}
+
+impl Window {
+    /// A recurring window from a cron expression
+    pub fn cron(expr: &str) -> Result<Self> {
+        Ok(Window::Recurring { cron: expr.parse()? })
+    }
+
+    fn is_active(&self, at: i64, utc_offset: i64) -> bool {
+        match self {
+            Window::Once { start, end } => *start <= at && at < *end,
+            Window::Recurring { cron } => cron.matches(at + utc_offset),
+        }
+    }
+}
+
+/// Windows of one policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleEntry {
+    pub policy_id: i32,
+    pub windows: Vec<Window>,
+    /// State set by the last reconcile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applied: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applied_at: Option<i64>,
+}
+
+/// Scheduled windows of policies, persisted as JSON
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicySchedule {
+    /// Offset of the time zone recurring windows are written in
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    pub entries: Vec<ScheduleEntry>,
+}
+
+impl PolicySchedule {
+    pub fn new(utc_offset_minutes: i32) -> Self {
+        Self {
+            utc_offset_minutes,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add a window to a policy's schedule
+    pub fn add(&mut self, policy_id: i32, window: Window) {
+        match self.entries.iter_mut().find(|e| e.policy_id == policy_id) {
+            Some(entry) => entry.windows.push(window),
+            None => self.entries.push(ScheduleEntry {
+                policy_id,
+                windows: vec![window],
+                applied: None,
+                applied_at: None,
+            }),
+        }
+    }
+
+    /// Remove a policy from the schedule
+    pub fn remove(&mut self, policy_id: i32) {
+        self.entries.retain(|e| e.policy_id != policy_id);
+    }
+
+    /// Whether the policy should be enabled at `at`
+    pub fn desired(&self, entry: &ScheduleEntry, at: i64) -> bool {
+        let offset = self.utc_offset_minutes as i64 * 60;
+        entry.windows.iter().any(|w| w.is_active(at, offset))
+    }
+
+    /// The next time after `now`, within a week, at which a policy should
+    /// change state
+    pub fn next_transition(&self, now: i64) -> Option<i64> {
+        let current: Vec<bool> = self.entries.iter().map(|e| self.desired(e, now)).collect();
+        let changes = |at: i64| self.entries.iter().zip(&current).any(|(e, state)| self.desired(e, at) != *state);
+
+        let once = self
+            .entries
+            .iter()
+            .flat_map(|e| &e.windows)
+            .filter_map(|w| match w {
+                Window::Once { start, end } => Some([*start, *end]),
+                Window::Recurring { .. } => None,
+            })
+            .flatten()
+            .filter(|t| *t > now && changes(*t))
+            .min();
+        let first_minute = (now.div_euclid(60) + 1) * 60;
+        let recurring = (0..7 * 24 * 60).map(|m| first_minute + m * 60).find(|t| changes(*t));
+
+        once.into_iter().chain(recurring).min()
+    }
+
+    /// Save the schedule as JSON
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Load a schedule saved with `save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+/// A policy switched by the scheduler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleTransition {
+    pub policy_id: i32,
+    pub name: String,
+    pub enabled: bool,
+    pub at: i64,
+}
+
+impl fmt::Display for ScheduleTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.enabled { "enabled" } else { "disabled" };
+        write!(f, "{} policy {} (#{})", verb, self.name, self.policy_id)
+    }
+}
+
+impl Client {
+    /// Reconcile policies with a schedule
+    ///
+    /// Switches every scheduled policy whose live state differs from the
+    /// state the schedule wants at `now` and records the applied state in
+    /// the schedule. Policies that no longer exist are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `schedule` - Schedule to apply
+    /// * `now` - Unix time to evaluate the schedule at
+    ///
+    /// # Returns
+    ///
+    /// Returns the policies that were switched
+    pub async fn reconcile_policy_schedule(&self, schedule: &mut PolicySchedule, now: i64) -> Result<Vec<ScheduleTransition>> {
+        let policies = self.list_policies().await?.data;
+        let mut transitions = Vec::new();
+
+        for i in 0..schedule.entries.len() {
+            let entry = &schedule.entries[i];
+            let desired = schedule.desired(entry, now);
+            let Some(policy) = policies.iter().find(|p| p.id == entry.policy_id) else {
+                warn!("scheduled policy {} does not exist", entry.policy_id);
+                continue;
+            };
+
+            if policy.is_enabled != desired {
+                self.policy_switch(&PolicySwitchRequest {
+                    id: policy.id,
+                    is_enabled: desired,
+                })
+                .await?;
+                let transition = ScheduleTransition {
+                    policy_id: policy.id,
+                    name: policy.name.clone(),
+                    enabled: desired,
+                    at: now,
+                };
+                info!("{}", transition);
+                transitions.push(transition);
+            }
+
+            let entry = &mut schedule.entries[i];
+            if entry.applied != Some(desired) {
+                entry.applied = Some(desired);
+                entry.applied_at = Some(now);
+            }
+        }
+
+        Ok(transitions)
+    }
+
+    /// Run a policy schedule
+    ///
+    /// Reconciles immediately, so transitions missed while not running are
+    /// applied, then sleeps until the next transition and repeats. The
+    /// schedule file is re-read on every iteration so edits take effect,
+    /// and written back with the applied states. Failed reconciles are
+    /// retried after a minute. Runs until the schedule file cannot be read
+    /// or written.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Schedule file written by `PolicySchedule::save`
+    pub async fn run_policy_schedule(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        loop {
+            let mut schedule = PolicySchedule::load(path)?;
+            let now = unix_now();
+
+            let next = match self.reconcile_policy_schedule(&mut schedule, now).await {
+                Ok(_) => {
+                    schedule.save(path)?;
+                    schedule.next_transition(now).unwrap_or(now + 3600)
+                }
+                Err(e) => {
+                    warn!("policy schedule reconcile failed: {}", e);
+                    now + 60
+                }
+            };
+
+            tokio::time::sleep(Duration::from_secs((next - unix_now()).max(1) as u64)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-01-01 00:00:00 UTC, a Monday
+    const MONDAY: i64 = 1704067200;
+
+    #[test]
+    fn test_cron() {
+        let business = "* 9-17 * * 1-5".parse::<CronExpr>().unwrap();
+        assert!(business.matches(MONDAY + 9 * 3600));
+        assert!(!business.matches(MONDAY + 8 * 3600 + 59 * 60));
+        assert!(!business.matches(MONDAY + 5 * 86400 + 10 * 3600));
+
+        let quarter = "*/15 * 1 1 *".parse::<CronExpr>().unwrap();
+        assert!(quarter.matches(MONDAY + 45 * 60));
+        assert!(!quarter.matches(MONDAY + 46 * 60));
+        assert!(!quarter.matches(MONDAY + 86400));
+
+        // Day of month or day of week
+        let either = "0 0 15 * 0".parse::<CronExpr>().unwrap();
+        assert!(either.matches(MONDAY + 6 * 86400));
+        assert!(either.matches(MONDAY + 14 * 86400));
+        assert!(!either.matches(MONDAY + 86400));
+
+        assert!("* * * *".parse::<CronExpr>().is_err());
+        assert!("60 * * * *".parse::<CronExpr>().is_err());
+        assert!("*/0 * * * *".parse::<CronExpr>().is_err());
+    }
+
+    #[test]
+    fn test_schedule() {
+        // Deny /admin outside business hours in UTC+8
+        let mut schedule = PolicySchedule::new(8 * 60);
+        schedule.add(1, Window::cron("* 0-8,18-23 * * 1-5").unwrap());
+        schedule.add(1, Window::cron("* * * * 0,6").unwrap());
+        schedule.add(2, Window::Once { start: MONDAY + 100, end: MONDAY + 200 });
+
+        let entry = |id: i32| schedule.entries.iter().find(|e| e.policy_id == id).unwrap();
+        // Monday 08:00 local is 00:00 UTC; business hours start at 09:00
+        assert!(schedule.desired(entry(1), MONDAY - 60));
+        assert!(!schedule.desired(entry(1), MONDAY + 3600));
+        assert!(schedule.desired(entry(1), MONDAY + 10 * 3600));
+
+        assert_eq!(schedule.next_transition(MONDAY - 30), Some(MONDAY + 100));
+        assert_eq!(schedule.next_transition(MONDAY + 100), Some(MONDAY + 200));
+        assert_eq!(schedule.next_transition(MONDAY + 200), Some(MONDAY + 3600));
+        assert_eq!(schedule.next_transition(MONDAY + 3600), Some(MONDAY + 10 * 3600));
+
+        let json = serde_json::to_string(&schedule).unwrap();
+        assert!(json.contains(r#""cron":"* 0-8,18-23 * * 1-5""#));
+        assert_eq!(serde_json::from_str::<PolicySchedule>(&json).unwrap(), schedule);
+    }
+}
@@ -0,0 +1,430 @@
+//! Declarative website reconciliation
+//!
+//! Compares a list of desired websites against the current state of a
+//! SafeLine instance, produces a reviewable plan and applies it.
+//!
+//! Desired sites are keyed by their first server name. An existing site
+//! matches a desired one when its `server_names` contain that key.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Reconciliation options
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileOptions {
+    /// Delete sites that are not part of the desired state.
+    /// When false, unmanaged sites are never touched.
+    pub delete_unmanaged: bool,
+}
+
+/// A single field that differs between current and desired state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub current: String,
+    pub desired: String,
+}
+
+/// A planned change to one website
+#[derive(Debug, Clone)]
+pub enum PlanAction {
+    Create {
+        key: String,
+        request: WebsiteRequest,
+    },
+    Update {
+        key: String,
+        id: i32,
+        request: WebsiteRequest,
+        changes: Vec<FieldDiff>,
+    },
+    Delete {
+        key: String,
+        id: i32,
+    },
+}
+
+/// Reconciliation plan
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub actions: Vec<PlanAction>,
+    /// Keys of desired sites that already match the current state
+    pub unchanged: Vec<String>,
+}
+
+impl Plan {
+    /// Returns true when applying the plan would not change anything
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    fn count(&self, f: impl Fn(&PlanAction) -> bool) -> usize {
+        self.actions.iter().filter(|a| f(a)).count()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in &self.actions {
+            match action {
+                PlanAction::Create { key, request } => {
+                    writeln!(f, "+ create {}", key)?;
+                    writeln!(f, "    server_names: {}", display(&request.server_names))?;
+                    writeln!(f, "    upstreams: {}", display(&request.upstreams))?;
+                }
+                PlanAction::Update { key, id, changes, .. } => {
+                    writeln!(f, "~ update {} (id {})", key, id)?;
+                    for change in changes {
+                        writeln!(f, "    {}: {} -> {}", change.field, change.current, change.desired)?;
+                    }
+                }
+                PlanAction::Delete { key, id } => {
+                    writeln!(f, "- delete {} (id {})", key, id)?;
+                }
+            }
+        }
+        write!(
+            f,
+            "Plan: {} to create, {} to update, {} to delete, {} unchanged",
+            self.count(|a| matches!(a, PlanAction::Create { .. })),
+            self.count(|a| matches!(a, PlanAction::Update { .. })),
+            self.count(|a| matches!(a, PlanAction::Delete { .. })),
+            self.unchanged.len()
+        )
+    }
+}
+
+/// Outcome of applying a plan
+#[derive(Debug, Clone, Default)]
+pub struct ApplyReport {
+    /// Created sites as (key, new ID)
+    pub created: Vec<(String, i32)>,
+    pub updated: Vec<i32>,
+    pub deleted: Vec<i32>,
+}
+
+/// Current state of a website, normalized for comparison
+#[derive(Debug, Clone)]
+pub struct CurrentSite {
+    pub id: i32,
+    pub request: WebsiteRequest,
+}
+
+impl Client {
+    /// Plan website reconciliation
+    ///
+    /// Fetches the current websites and computes the changes needed to reach
+    /// the desired state.
+    ///
+    /// # Arguments
+    ///
+    /// * `desired` - Desired websites, keyed by their first server name
+    /// * `options` - Reconciliation options
+    ///
+    /// # Returns
+    ///
+    /// Returns the reconciliation plan
+    pub async fn reconcile_plan(&self, desired: &[WebsiteRequest], options: &ReconcileOptions) -> Result<Plan> {
+        let sites = self.list_websites().await?;
+
+        let mut current = Vec::with_capacity(sites.data.len());
+        for site in &sites.data {
+            let managed = desired
+                .iter()
+                .filter_map(site_key)
+                .any(|key| site.server_names.iter().any(|name| name == key));
+            let request = if managed {
+                WebsiteRequest::from(&self.get_website_detail(site.id).await?.data)
+            } else {
+                WebsiteRequest::from(site)
+            };
+            current.push(CurrentSite { id: site.id, request });
+        }
+
+        build_plan(&current, desired, options)
+    }
+
+    /// Apply a reconciliation plan
+    ///
+    /// Creates, updates and deletes websites in that order. Stops at the
+    /// first failing call.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - Plan produced by `reconcile_plan`
+    ///
+    /// # Returns
+    ///
+    /// Returns the applied changes
+    pub async fn reconcile_apply(&self, plan: &Plan) -> Result<ApplyReport> {
+        let mut report = ApplyReport::default();
+
+        for action in &plan.actions {
+            if let PlanAction::Create { key, request } = action {
+                let id = self.create_website(request).await?.data;
+                report.created.push((key.clone(), id));
+            }
+        }
+        for action in &plan.actions {
+            if let PlanAction::Update { id, request, .. } = action {
+                self.update_website(*id, request).await?;
+                report.updated.push(*id);
+            }
+        }
+
+        let ids: Vec<i32> = plan
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                PlanAction::Delete { id, .. } => Some(*id),
+                _ => None,
+            })
+            .collect();
+        if !ids.is_empty() {
+            self.delete_websites(&DeleteWebsiteRequest { ids: ids.clone() }).await?;
+            report.deleted = ids;
+        }
+
+        Ok(report)
+    }
+}
+
+/// Build a plan from already fetched current state
+///
+/// Desired websites are keyed by their first server name, which must be
+/// unique.
+pub fn build_plan(current: &[CurrentSite], desired: &[WebsiteRequest], options: &ReconcileOptions) -> Result<Plan> {
+    let mut plan = Plan::default();
+    let mut matched = Vec::new();
+    let mut keys = HashSet::new();
+
+    for request in desired {
+        let key = site_key(request)
+            .ok_or_else(|| Error::InvalidInput("desired website has no server name".to_string()))?
+            .to_string();
+        if !keys.insert(key.clone()) {
+            return Err(Error::InvalidInput(format!("duplicate desired website {:?}", key)));
+        }
+
+        let site = current
+            .iter()
+            .find(|site| site.request.server_names.contains(&key));
+
+        match site {
+            None => plan.actions.push(PlanAction::Create {
+                key,
+                request: WebsiteRequest { id: None, ..request.clone() },
+            }),
+            Some(site) => {
+                matched.push(site.id);
+                let changes = diff_site(&site.request, request);
+                if changes.is_empty() {
+                    plan.unchanged.push(key);
+                } else {
+                    plan.actions.push(PlanAction::Update {
+                        key,
+                        id: site.id,
+                        request: merge_site(&site.request, request),
+                        changes,
+                    });
+                }
+            }
+        }
+    }
+
+    if options.delete_unmanaged {
+        for site in current.iter().filter(|site| !matched.contains(&site.id)) {
+            plan.actions.push(PlanAction::Delete {
+                key: site_key(&site.request).unwrap_or_default().to_string(),
+                id: site.id,
+            });
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Compute the field-level differences between a current and a desired website.
+/// Optional fields left unset in `desired` are not compared.
+pub fn diff_site(current: &WebsiteRequest, desired: &WebsiteRequest) -> Vec<FieldDiff> {
+    let mut changes = Vec::new();
+
+    diff_list(&mut changes, "server_names", &current.server_names, &desired.server_names);
+    diff_list(&mut changes, "ports", &current.ports, &desired.ports);
+    diff_list(&mut changes, "upstreams", &current.upstreams, &desired.upstreams);
+    diff_value(&mut changes, "group_id", &current.group_id, &desired.group_id);
+    diff_option(&mut changes, "comment", &current.comment, &desired.comment);
+    diff_option(&mut changes, "email", &current.email, &desired.email);
+    diff_option(&mut changes, "cert_id", &current.cert_id, &desired.cert_id);
+    diff_option(&mut changes, "load_balance", &current.load_balance, &desired.load_balance);
+    diff_option(&mut changes, "redirect_status_code", &current.redirect_status_code, &desired.redirect_status_code);
+    diff_option(&mut changes, "stat_enabled", &current.stat_enabled, &desired.stat_enabled);
+    diff_option(&mut changes, "static_default", &current.static_default, &desired.static_default);
+    diff_option(&mut changes, "index", &current.index, &desired.index);
+    diff_option(&mut changes, "type", &current.r#type, &desired.r#type);
+
+    if let Some(ref desired_hc) = desired.health_check {
+        let same = current.health_check.as_ref().is_some_and(|hc| {
+            sorted(&hc.hosts) == sorted(&desired_hc.hosts) && sorted(&hc.upstreams) == sorted(&desired_hc.upstreams)
+        });
+        if !same {
+            changes.push(FieldDiff {
+                field: "health_check",
+                current: display(&current.health_check),
+                desired: display(&desired.health_check),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Overlay the fields set in `desired` onto the current website
+fn merge_site(current: &WebsiteRequest, desired: &WebsiteRequest) -> WebsiteRequest {
+    WebsiteRequest {
+        id: current.id,
+        server_names: desired.server_names.clone(),
+        ports: desired.ports.clone(),
+        upstreams: desired.upstreams.clone(),
+        group_id: desired.group_id,
+        comment: desired.comment.clone().or_else(|| current.comment.clone()),
+        email: desired.email.clone().or_else(|| current.email.clone()),
+        cert_id: desired.cert_id.or(current.cert_id),
+        load_balance: desired.load_balance.clone().or_else(|| current.load_balance.clone()),
+        redirect_status_code: desired.redirect_status_code.or(current.redirect_status_code),
+        health_check: desired.health_check.clone().or_else(|| current.health_check.clone()),
+        stat_enabled: desired.stat_enabled.or(current.stat_enabled),
+        static_default: desired.static_default.or(current.static_default),
+        index: desired.index.or(current.index),
+        r#type: desired.r#type.or(current.r#type),
+    }
+}
+
+fn site_key(request: &WebsiteRequest) -> Option<&str> {
+    request.server_names.first().map(String::as_str)
+}
+
+fn sorted(values: &[String]) -> Vec<&String> {
+    let mut values: Vec<&String> = values.iter().collect();
+    values.sort();
+    values
+}
+
+fn display<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn diff_list(changes: &mut Vec<FieldDiff>, field: &'static str, current: &[String], desired: &[String]) {
+    if sorted(current) != sorted(desired) {
+        changes.push(FieldDiff {
+            field,
+            current: display(&current),
+            desired: display(&desired),
+        });
+    }
+}
+
+fn diff_value<T: PartialEq + Serialize>(changes: &mut Vec<FieldDiff>, field: &'static str, current: &T, desired: &T) {
+    if current != desired {
+        changes.push(FieldDiff {
+            field,
+            current: display(current),
+            desired: display(desired),
+        });
+    }
+}
+
+fn diff_option<T: PartialEq + Serialize>(
+    changes: &mut Vec<FieldDiff>,
+    field: &'static str,
+    current: &Option<T>,
+    desired: &Option<T>,
+) {
+    if desired.is_some() {
+        diff_value(changes, field, current, desired);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(names: &[&str], upstream: &str) -> WebsiteRequest {
+        WebsiteRequest {
+            id: None,
+            server_names: names.iter().map(|s| s.to_string()).collect(),
+            ports: vec!["80".to_string()],
+            upstreams: vec![upstream.to_string()],
+            group_id: 1,
+            comment: None,
+            email: None,
+            cert_id: None,
+            load_balance: None,
+            redirect_status_code: None,
+            health_check: None,
+            stat_enabled: None,
+            static_default: None,
+            index: None,
+            r#type: None,
+        }
+    }
+
+    #[test]
+    fn test_build_plan() {
+        let current = vec![
+            CurrentSite { id: 1, request: site(&["a.example.com", "www.a.example.com"], "http://10.0.0.1") },
+            CurrentSite { id: 2, request: site(&["b.example.com"], "http://10.0.0.2") },
+            CurrentSite { id: 3, request: site(&["legacy.example.com"], "http://10.0.0.3") },
+        ];
+        let desired = vec![
+            site(&["a.example.com", "www.a.example.com"], "http://10.0.0.1"),
+            site(&["b.example.com"], "http://10.0.0.20"),
+            site(&["c.example.com"], "http://10.0.0.4"),
+        ];
+
+        let plan = build_plan(&current, &desired, &ReconcileOptions::default()).unwrap();
+        assert_eq!(plan.unchanged, vec!["a.example.com".to_string()]);
+        assert_eq!(plan.actions.len(), 2);
+        match &plan.actions[0] {
+            PlanAction::Update { id, changes, .. } => {
+                assert_eq!(*id, 2);
+                assert_eq!(changes.len(), 1);
+                assert_eq!(changes[0].field, "upstreams");
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+        assert!(matches!(&plan.actions[1], PlanAction::Create { key, .. } if key == "c.example.com"));
+
+        let plan = build_plan(&current, &desired, &ReconcileOptions { delete_unmanaged: true }).unwrap();
+        assert!(matches!(plan.actions.last(), Some(PlanAction::Delete { id: 3, .. })));
+        assert!(plan.to_string().contains("1 to create, 1 to update, 1 to delete, 1 unchanged"));
+
+
+        let mut duplicate = desired.clone();
+        duplicate.push(site(&["b.example.com", "old.b.example.com"], "http://10.0.0.2"));
+        assert!(build_plan(&current, &duplicate, &ReconcileOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_diff_site_ignores_unset_fields() {
+        let mut current = site(&["a.example.com"], "http://10.0.0.1");
+        current.comment = Some("managed elsewhere".to_string());
+        let mut desired = site(&["a.example.com"], "http://10.0.0.1");
+        assert!(diff_site(&current, &desired).is_empty());
+
+        desired.comment = Some("from git".to_string());
+        let changes = diff_site(&current, &desired);
+        assert_eq!(changes[0].field, "comment");
+        assert_eq!(merge_site(&current, &desired).comment.as_deref(), Some("from git"));
+
+        let mut desired = site(&["a.example.com"], "http://10.0.0.1");
+        desired.index = Some(2);
+        desired.r#type = Some(1);
+        let fields: Vec<&str> = diff_site(&current, &desired).iter().map(|c| c.field).collect();
+        assert_eq!(fields, vec!["index", "type"]);
+    }
+}
@@ -1,6 +1,9 @@
 use crate::client::Client;
 use crate::error::Result;
 use crate::models::*;
+use crate::paging::paged;
+use futures::stream::Stream;
+use std::collections::HashMap;
 
 impl Client {
     /// List records (GET /open/records)
@@ -120,4 +123,169 @@ impl Client {
     pub async fn get_rule_detect_log_list(&self) -> Result<DetectLogListResponse> {
         self.get("/open/events/rule").await
     }
-}
\ No newline at end of file
+
+    /// Search detect logs (GET /open/records)
+    ///
+    /// Retrieves one page of detect logs matching the given filters.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Page and filters such as time range, host and IP
+    ///
+    /// # Returns
+    ///
+    /// Returns a page of detect logs
+    pub async fn search_detect_logs(&self, request: &DetectLogSearchRequest) -> Result<DetectLogListResponse> {
+        let mut params = HashMap::new();
+        if let Some(page) = request.page {
+            params.insert("page".to_string(), page.to_string());
+        }
+        if let Some(page_size) = request.page_size {
+            params.insert("page_size".to_string(), page_size.to_string());
+        }
+        if let Some(start) = request.start {
+            params.insert("start".to_string(), start.to_string());
+        }
+        if let Some(end) = request.end {
+            params.insert("end".to_string(), end.to_string());
+        }
+        if let Some(ref host) = request.host {
+            params.insert("host".to_string(), host.clone());
+        }
+        if let Some(ref ip) = request.ip {
+            params.insert("ip".to_string(), ip.clone());
+        }
+        if let Some(ref attack_type) = request.attack_type {
+            params.insert("attack_type".to_string(), attack_type.clone());
+        }
+        if let Some(ref action) = request.action {
+            params.insert("action".to_string(), action.clone());
+        }
+        self.get_with_query("/open/records", &params).await
+    }
+
+    /// Stream detect logs matching the given filters
+    ///
+    /// Fetches pages lazily starting at `request.page` (default 1) until a
+    /// short page or `total` is reached. A failed page is yielded as an
+    /// `Err` item and ends the stream.
+    pub fn detect_logs(&self, request: DetectLogSearchRequest) -> impl Stream<Item = Result<DetectLog>> + '_ {
+        let page_size = request.page_size.unwrap_or(100);
+        paged(request.page.unwrap_or(1), page_size, move |page| {
+            let request = DetectLogSearchRequest {
+                page: Some(page),
+                page_size: Some(page_size),
+                ..request.clone()
+            };
+            async move {
+                let response = self.search_detect_logs(&request).await?;
+                Ok((response.data, response.total))
+            }
+        })
+    }
+
+    /// Search challenge records (GET /open/records/challenge)
+    ///
+    /// Retrieves one page of challenge records matching the given filters.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Page and filters such as start time range, IP and site
+    ///
+    /// # Returns
+    ///
+    /// Returns a page of challenge records
+    pub async fn search_challenge_records(&self, request: &ChallengeRecordSearchRequest) -> Result<ChallengeLogsResponse> {
+        let mut params = HashMap::new();
+        if let Some(page) = request.page {
+            params.insert("page".to_string(), page.to_string());
+        }
+        if let Some(page_size) = request.page_size {
+            params.insert("page_size".to_string(), page_size.to_string());
+        }
+        if let Some(begin) = request.started_at_begin {
+            params.insert("started_at_begin".to_string(), begin.to_string());
+        }
+        if let Some(end) = request.started_at_end {
+            params.insert("started_at_end".to_string(), end.to_string());
+        }
+        if let Some(ref ip) = request.ip {
+            params.insert("ip".to_string(), ip.clone());
+        }
+        if let Some(ref site) = request.site {
+            params.insert("site".to_string(), site.clone());
+        }
+        self.get_with_query("/open/records/challenge", &params).await
+    }
+
+    /// Stream challenge records matching the given filters
+    ///
+    /// Fetches pages lazily starting at `request.page` (default 1) until a
+    /// short page or `total` is reached. A failed page is yielded as an
+    /// `Err` item and ends the stream.
+    pub fn challenge_records(&self, request: ChallengeRecordSearchRequest) -> impl Stream<Item = Result<ChallengeLog>> + '_ {
+        let page_size = request.page_size.unwrap_or(100);
+        paged(request.page.unwrap_or(1), page_size, move |page| {
+            let request = ChallengeRecordSearchRequest {
+                page: Some(page),
+                page_size: Some(page_size),
+                ..request.clone()
+            };
+            async move {
+                let response = self.search_challenge_records(&request).await?;
+                Ok((response.data, response.total))
+            }
+        })
+    }
+
+    /// Search waiting room records (GET /open/records/waiting)
+    ///
+    /// Retrieves one page of waiting room records matching the given filters.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Page and filters such as start time range and site
+    ///
+    /// # Returns
+    ///
+    /// Returns a page of waiting room records
+    pub async fn search_waiting_room_logs(&self, request: &WaitingRoomSearchRequest) -> Result<WaitingRoomLogsResponse> {
+        let mut params = HashMap::new();
+        if let Some(page) = request.page {
+            params.insert("page".to_string(), page.to_string());
+        }
+        if let Some(page_size) = request.page_size {
+            params.insert("page_size".to_string(), page_size.to_string());
+        }
+        if let Some(begin) = request.started_at_begin {
+            params.insert("started_at_begin".to_string(), begin.to_string());
+        }
+        if let Some(end) = request.started_at_end {
+            params.insert("started_at_end".to_string(), end.to_string());
+        }
+        if let Some(ref site) = request.site {
+            params.insert("site".to_string(), site.clone());
+        }
+        self.get_with_query("/open/records/waiting", &params).await
+    }
+
+    /// Stream waiting room records matching the given filters
+    ///
+    /// Fetches pages lazily starting at `request.page` (default 1) until a
+    /// short page or `total` is reached. A failed page is yielded as an
+    /// `Err` item and ends the stream.
+    pub fn waiting_room_logs(&self, request: WaitingRoomSearchRequest) -> impl Stream<Item = Result<WaitingRoomLog>> + '_ {
+        let page_size = request.page_size.unwrap_or(100);
+        paged(request.page.unwrap_or(1), page_size, move |page| {
+            let request = WaitingRoomSearchRequest {
+                page: Some(page),
+                page_size: Some(page_size),
+                ..request.clone()
+            };
+            async move {
+                let response = self.search_waiting_room_logs(&request).await?;
+                Ok((response.data, response.total))
+            }
+        })
+    }
+}
@@ -0,0 +1,529 @@
+//! Automatic blocking of repeat offenders
+//!
+//! Works like fail2ban: detect logs and ACL bans are counted per source IP,
+//! and once an IP exceeds a threshold within its time window it is appended
+//! to a designated IP group, which a deny policy can reference. Exempt
+//! networks are never blocked, a dry run only reports, and every block is
+//! written to an audit log.
+
+use crate::acl_bans::Allowlist;
+use crate::client::Client;
+use crate::correlate::log_time;
+use crate::error::{Error, Result};
+use crate::models::*;
+use crate::time::{parse_timestamp, unix_now};
+use futures::future;
+use futures::stream::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// What an IP did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OffenseKind {
+    Detect { attack_type: i32, risk_level: i32 },
+    AclBan { reason: String },
+}
+
+/// One offense of an IP
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Offense {
+    pub ip: IpAddr,
+    pub time: i64,
+    pub kind: OffenseKind,
+}
+
+impl Offense {
+    /// `None` if the log lacks a usable IP or time
+    pub fn from_detect_log(log: &DetectLog) -> Option<Self> {
+        Some(Self {
+            ip: log.src_ip.parse().ok()?,
+            time: log_time(log)?,
+            kind: OffenseKind::Detect {
+                attack_type: log.attack_type,
+                risk_level: log.risk_level,
+            },
+        })
+    }
+
+    /// `None` if the log lacks a usable IP or time
+    pub fn from_acl_log(log: &ACLLog) -> Option<Self> {
+        Some(Self {
+            ip: log.ip.parse().ok()?,
+            time: log.created_at.as_deref().and_then(parse_timestamp)?,
+            kind: OffenseKind::AclBan {
+                reason: log.reason.clone(),
+            },
+        })
+    }
+}
+
+/// Block an IP after `count` matching offenses within `window`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Threshold {
+    /// Count ACL bans instead of detect logs
+    pub acl: bool,
+    pub attack_type: Option<i32>,
+    pub min_risk: Option<i32>,
+    pub count: usize,
+    pub window: Duration,
+}
+
+impl Threshold {
+    /// Any detect log counts
+    pub fn detect(count: usize, window: Duration) -> Self {
+        Self {
+            acl: false,
+            attack_type: None,
+            min_risk: None,
+            count,
+            window,
+        }
+    }
+
+    /// ACL bans count
+    pub fn acl_bans(count: usize, window: Duration) -> Self {
+        Self {
+            acl: true,
+            ..Self::detect(count, window)
+        }
+    }
+
+    /// Only count detect logs of this attack type
+    pub fn attack_type(mut self, attack_type: i32) -> Self {
+        self.attack_type = Some(attack_type);
+        self
+    }
+
+    /// Only count detect logs of at least this risk level
+    pub fn min_risk(mut self, risk_level: i32) -> Self {
+        self.min_risk = Some(risk_level);
+        self
+    }
+
+    pub fn matches(&self, offense: &Offense) -> bool {
+        match &offense.kind {
+            OffenseKind::Detect { attack_type, risk_level } => {
+                !self.acl
+                    && self.attack_type.is_none_or(|t| t == *attack_type)
+                    && self.min_risk.is_none_or(|r| *risk_level >= r)
+            }
+            OffenseKind::AclBan { .. } => self.acl,
+        }
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.acl {
+            write!(f, "{} ACL bans", self.count)?;
+        } else {
+            write!(f, "{} attacks", self.count)?;
+            if let Some(attack_type) = self.attack_type {
+                write!(f, " of type {}", attack_type)?;
+            }
+            if let Some(risk) = self.min_risk {
+                write!(f, " with risk >= {}", risk)?;
+            }
+        }
+        write!(f, " in {}s", self.window.as_secs())
+    }
+}
+
+/// A block decided by the responder
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix time of the offense that crossed the threshold
+    pub at: i64,
+    pub ip: String,
+    /// Threshold that was exceeded
+    pub reason: String,
+    pub offenses: usize,
+    pub group: String,
+    /// Set once the IP was appended to the group
+    pub group_id: Option<i32>,
+    pub dry_run: bool,
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block {} in IP group {:?}: {} offenses, exceeded {}",
+            self.ip, self.group, self.offenses, self.reason
+        )?;
+        if self.dry_run {
+            write!(f, " (dry run)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Offense counters and blocking settings
+#[derive(Debug, Clone)]
+pub struct Responder {
+    /// Comment of the IP group blocked IPs are appended to
+    pub group: String,
+    pub thresholds: Vec<Threshold>,
+    pub exempt: Allowlist,
+    pub dry_run: bool,
+    /// File the audit entries are appended to as JSON lines
+    pub audit_log: Option<PathBuf>,
+    offenses: HashMap<IpAddr, VecDeque<Offense>>,
+    blocked: BTreeSet<IpAddr>,
+    /// Time of every counted log by ID, so logs fetched again are skipped
+    seen_detect: HashMap<i32, i64>,
+    seen_acl: HashMap<i32, i64>,
+}
+
+impl Responder {
+    pub fn new(group: &str) -> Self {
+        Self {
+            group: group.to_string(),
+            thresholds: Vec::new(),
+            exempt: Allowlist::new(),
+            dry_run: false,
+            audit_log: None,
+            offenses: HashMap::new(),
+            blocked: BTreeSet::new(),
+            seen_detect: HashMap::new(),
+            seen_acl: HashMap::new(),
+        }
+    }
+
+    pub fn threshold(mut self, threshold: Threshold) -> Self {
+        self.thresholds.push(threshold);
+        self
+    }
+
+    /// Never block these networks
+    pub fn exempt(mut self, exempt: Allowlist) -> Self {
+        self.exempt = exempt;
+        self
+    }
+
+    pub fn dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    pub fn audit_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit_log = Some(path.into());
+        self
+    }
+
+    /// IPs blocked so far
+    pub fn blocked(&self) -> &BTreeSet<IpAddr> {
+        &self.blocked
+    }
+
+    /// Mark IPs as already blocked, e.g. the current members of the group
+    pub fn mark_blocked(&mut self, ips: impl IntoIterator<Item = IpAddr>) {
+        self.blocked.extend(ips);
+    }
+
+    fn longest_window(&self) -> i64 {
+        self.thresholds.iter().map(|t| t.window.as_secs() as i64).max().unwrap_or(0)
+    }
+
+    /// Count an offense; returns the block if it crosses a threshold.
+    /// Offenses must be observed oldest first.
+    pub fn observe(&mut self, offense: Offense) -> Option<AuditEntry> {
+        if self.blocked.contains(&offense.ip) || self.exempt.matches(&offense.ip).is_some() {
+            return None;
+        }
+
+        let horizon = offense.time - self.longest_window();
+        let (ip, time) = (offense.ip, offense.time);
+        let history = self.offenses.entry(ip).or_default();
+        history.retain(|o| o.time >= horizon);
+        history.push_back(offense);
+
+        let exceeded = self.thresholds.iter().find_map(|threshold| {
+            let since = time - threshold.window.as_secs() as i64;
+            let count = history
+                .iter()
+                .filter(|o| o.time >= since && o.time <= time && threshold.matches(o))
+                .count();
+            (count >= threshold.count).then(|| (threshold.to_string(), count))
+        })?;
+
+        self.offenses.remove(&ip);
+        self.blocked.insert(ip);
+        Some(AuditEntry {
+            at: time,
+            ip: ip.to_string(),
+            reason: exceeded.0,
+            offenses: exceeded.1,
+            group: self.group.clone(),
+            group_id: None,
+            dry_run: self.dry_run,
+        })
+    }
+
+    /// Whether any threshold counts ACL bans
+    pub fn counts_acl_bans(&self) -> bool {
+        self.thresholds.iter().any(|t| t.acl)
+    }
+
+    /// Offense of a detect log not seen before
+    pub fn detect_offense(&mut self, log: &DetectLog) -> Option<Offense> {
+        let offense = Offense::from_detect_log(log)?;
+        self.seen_detect.insert(log.id, offense.time).is_none().then_some(offense)
+    }
+
+    /// Offense of an ACL ban not seen before
+    pub fn acl_offense(&mut self, log: &ACLLog) -> Option<Offense> {
+        let offense = Offense::from_acl_log(log)?;
+        self.seen_acl.insert(log.id, offense.time).is_none().then_some(offense)
+    }
+
+    /// Forget logs and offenses older than `horizon`, which are no longer
+    /// fetched or counted
+    pub fn prune(&mut self, horizon: i64) {
+        self.seen_detect.retain(|_, time| *time >= horizon);
+        self.seen_acl.retain(|_, time| *time >= horizon);
+        self.offenses.retain(|_, history| {
+            history.retain(|o| o.time >= horizon);
+            !history.is_empty()
+        });
+    }
+
+    /// Count offenses in time order; logs fetched again in a later round
+    /// are only counted once
+    pub fn observe_all(&mut self, mut offenses: Vec<Offense>) -> Vec<AuditEntry> {
+        offenses.sort_by_key(|o| o.time);
+        offenses.into_iter().filter_map(|o| self.observe(o)).collect()
+    }
+
+    fn audit(&self, entry: &AuditEntry) -> Result<()> {
+        info!("{}", entry);
+        if let Some(path) = &self.audit_log {
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        }
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Run one round of the responder
+    ///
+    /// Fetches detect logs, and ACL logs if a threshold counts ACL bans,
+    /// since `since`, counts offenses and appends IPs that exceed a
+    /// threshold to the responder's IP group, creating the group if it does
+    /// not exist. Every block is audited.
+    ///
+    /// # Arguments
+    ///
+    /// * `responder` - Thresholds and offense counters
+    /// * `since` - Unix time to fetch logs from
+    ///
+    /// # Returns
+    ///
+    /// Returns the blocks made in this round. On error the responder is
+    /// left as it was before the round, so the same logs are counted again
+    /// on the next one.
+    pub async fn respond_to_offenses(&self, responder: &mut Responder, since: i64) -> Result<Vec<AuditEntry>> {
+        let saved = responder.clone();
+        let blocks = match self.block_offenders(responder, since).await {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                *responder = saved;
+                return Err(e);
+            }
+        };
+        responder.prune(since);
+
+        for block in &blocks {
+            responder.audit(block)?;
+        }
+        Ok(blocks)
+    }
+
+    /// Count the offenses since `since` and append the IPs crossing a
+    /// threshold to the responder's IP group
+    async fn block_offenders(&self, responder: &mut Responder, since: i64) -> Result<Vec<AuditEntry>> {
+        let mut offenses = Vec::new();
+        let mut logs = std::pin::pin!(self.detect_logs(DetectLogSearchRequest {
+            start: Some(since),
+            ..Default::default()
+        }));
+        while let Some(log) = logs.next().await {
+            offenses.extend(responder.detect_offense(&log?));
+        }
+
+        if responder.counts_acl_bans() {
+            // The ACL log endpoint has no time filter and lists the newest
+            // logs first, so paging stops at the first log before `since`
+            let mut bans = std::pin::pin!(self.acl_logs().try_take_while(|log| {
+                let time = log.created_at.as_deref().and_then(parse_timestamp);
+                future::ready(Ok(time.is_none_or(|t| t >= since)))
+            }));
+            while let Some(log) = bans.next().await {
+                offenses.extend(responder.acl_offense(&log?).filter(|o| o.time >= since));
+            }
+        }
+
+        let mut blocks = responder.observe_all(offenses);
+        if blocks.is_empty() {
+            return Ok(blocks);
+        }
+
+        if !responder.dry_run {
+            let ips: Vec<String> = blocks.iter().map(|b| b.ip.clone()).collect();
+            let (id, created) = self
+                .ip_group_find_or_create(&IPGroupCreateRequest {
+                    comment: Some(responder.group.clone()),
+                    ips: ips.clone(),
+                    reference: None,
+                })
+                .await?;
+            if !created {
+                self.ip_group_append(&IPGroupAppendRequest {
+                    ip_group_ids: vec![id],
+                    ips,
+                })
+                .await?;
+            }
+            for block in &mut blocks {
+                block.group_id = Some(id);
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Run the responder every `interval`
+    ///
+    /// Members of the responder's IP group are loaded first so they are not
+    /// blocked again. Each round looks back over the longest threshold
+    /// window; failed rounds are logged and retried on the next tick.
+    pub async fn run_responder(&self, responder: &mut Responder, interval: Duration) -> Result<()> {
+        if let Some(group) = self.ip_group_find_by_comment(&responder.group).await? {
+            let id = group.id.ok_or_else(|| Error::InvalidResponse("IP group without id".to_string()))?;
+            let detail = self.ip_group_detail(&IPGroupDetailRequest { id }).await?.data;
+            responder.mark_blocked(detail.ips.unwrap_or_default().iter().filter_map(|ip| ip.parse().ok()));
+        }
+
+        loop {
+            let since = unix_now() - responder.longest_window() - interval.as_secs() as i64;
+            if let Err(e) = self.respond_to_offenses(responder, since).await {
+                warn!("responder round failed: {}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(ip: &str, time: i64, attack_type: i32, risk_level: i32) -> Offense {
+        Offense {
+            ip: ip.parse().unwrap(),
+            time,
+            kind: OffenseKind::Detect { attack_type, risk_level },
+        }
+    }
+
+    fn acl(ip: &str, time: i64) -> Offense {
+        Offense {
+            ip: ip.parse().unwrap(),
+            time,
+            kind: OffenseKind::AclBan {
+                reason: "req".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let mut responder = Responder::new("auto-blocked")
+            .threshold(Threshold::detect(3, Duration::from_secs(60)).min_risk(2))
+            .threshold(Threshold::detect(2, Duration::from_secs(600)).attack_type(9))
+            .threshold(Threshold::acl_bans(2, Duration::from_secs(3600)))
+            .exempt(Allowlist::new().allow("10.0.0.0/8", "office").unwrap())
+            .dry_run();
+
+        // Low risk does not count; old offenses fall out of the window
+        assert!(responder.observe(detect("1.1.1.1", 0, 0, 2)).is_none());
+        assert!(responder.observe(detect("1.1.1.1", 10, 0, 1)).is_none());
+        assert!(responder.observe(detect("1.1.1.1", 61, 0, 3)).is_none());
+        assert!(responder.observe(detect("1.1.1.1", 62, 0, 2)).is_none());
+        let block = responder.observe(detect("1.1.1.1", 70, 1, 2)).unwrap();
+        assert_eq!((block.ip.as_str(), block.offenses, block.at), ("1.1.1.1", 3, 70));
+        assert_eq!(block.reason, "3 attacks with risk >= 2 in 60s");
+        assert!(block.dry_run);
+
+        // Already blocked IPs are not reported again
+        assert!(responder.observe(detect("1.1.1.1", 71, 1, 3)).is_none());
+
+        assert!(responder.observe(detect("2.2.2.2", 0, 9, 0)).is_none());
+        assert_eq!(responder.observe(detect("2.2.2.2", 500, 9, 0)).unwrap().reason, "2 attacks of type 9 in 600s");
+
+        assert!(responder.observe(acl("3.3.3.3", 0)).is_none());
+        assert_eq!(responder.observe(acl("3.3.3.3", 3000)).unwrap().reason, "2 ACL bans in 3600s");
+
+        for time in 0..10 {
+            assert!(responder.observe(detect("10.1.1.1", time, 9, 3)).is_none());
+        }
+        assert_eq!(responder.blocked().len(), 3);
+
+        // Newest-first input is sorted before counting
+        let blocks = responder.observe_all(vec![
+            detect("5.5.5.5", 30, 0, 3),
+            detect("5.5.5.5", 20, 0, 3),
+            detect("5.5.5.5", 10, 0, 3),
+        ]);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].at, 30);
+    }
+
+    #[test]
+    fn test_seen_logs_and_prune() {
+        let mut responder = Responder::new("auto-blocked").threshold(Threshold::detect(5, Duration::from_secs(60)));
+        assert!(!responder.counts_acl_bans());
+
+        let log: DetectLog = serde_json::from_value(serde_json::json!({
+            "id": 1, "event_id": "e1", "site_uuid": "1", "website": "a", "src_ip": "1.1.1.1",
+            "src_port": 1, "dst_ip": "10.0.0.1", "dst_port": 80, "host": "a.com", "method": "GET",
+            "url_path": "/", "protocol": 1, "module": "m", "attack_type": 0, "risk_level": 1,
+            "action": 1, "reason": "", "rule_id": "r1", "timestamp": 100,
+        }))
+        .unwrap();
+        let offense = responder.detect_offense(&log).unwrap();
+        assert!(responder.detect_offense(&log).is_none());
+        assert!(responder.observe(offense).is_none());
+
+        responder.prune(100);
+        assert!(responder.detect_offense(&log).is_none());
+        responder.prune(101);
+        assert!(responder.offenses.is_empty());
+        assert!(responder.detect_offense(&log).is_some());
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let path = std::env::temp_dir().join(format!("safeline-responder-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut responder = Responder::new("auto-blocked")
+            .threshold(Threshold::acl_bans(1, Duration::from_secs(60)))
+            .audit_log(&path);
+
+        let block = responder.observe(acl("4.4.4.4", 100)).unwrap();
+        assert_eq!(block.to_string(), r#"block 4.4.4.4 in IP group "auto-blocked": 1 offenses, exceeded 1 ACL bans in 60s"#);
+        responder.audit(&block).unwrap();
+        responder.audit(&block).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let entries: Vec<AuditEntry> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(entries, vec![block.clone(), block]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
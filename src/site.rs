@@ -1,6 +1,7 @@
 use crate::client::Client;
 use crate::error::Result;
 use crate::models::*;
+use std::collections::HashMap;
 
 impl Client {
     // ============================================================================
@@ -148,7 +149,11 @@ impl Client {
     /// # Arguments
     ///
     /// * `request` - Health check request
-    pub async fn website_health_check(&self, request: &WebsiteHealthCheckRequest) -> Result<()> {
+    ///
+    /// # Returns
+    ///
+    /// Returns the check result keyed by upstream; empty if the upstream is healthy
+    pub async fn website_health_check(&self, request: &WebsiteHealthCheckRequest) -> Result<HashMap<String, String>> {
         self.post("/open/site/healthcheck", request).await
     }
 
@@ -298,7 +303,22 @@ impl Client {
         self.get(&format!("/open/site/{}/resources", id)).await
     }
 
-    /// Update excludes (PUT /open/site/{id}/excludes)
+    /// Get website excludes (GET /open/site/{id}/excludes)
+    ///
+    /// Retrieves the excludes configuration of a website.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    ///
+    /// # Returns
+    ///
+    /// Returns excluded path prefixes and content types
+    pub async fn get_website_excludes(&self, id: i32) -> Result<SiteExcludes> {
+        self.get(&format!("/open/site/{}/excludes", id)).await
+    }
+
+    /// Update excludes (POST /open/site/{id}/excludes)
     ///
     /// Updates the excludes configuration of a website.
     ///
@@ -307,7 +327,7 @@ impl Client {
     /// * `id` - Website ID
     /// * `request` - Excludes update request
     pub async fn update_excludes(&self, id: i32, request: &UpdateExcludesRequest) -> Result<()> {
-        self.put(&format!("/open/site/{}/excludes", id), request).await
+        self.post(&format!("/open/site/{}/excludes", id), request).await
     }
 
     /// Remove resources (DELETE /open/site/{id}/resources)
@@ -354,17 +374,23 @@ impl Client {
 
     /// Download website log (GET /open/site/{id}/log/download)
     ///
-    /// Downloads log files of a website.
+    /// Downloads a log file of a website.
     ///
     /// # Arguments
     ///
     /// * `id` - Website ID
+    /// * `filename` - Log file name as listed by `get_website_log`
     ///
     /// # Returns
     ///
-    /// Returns log file content
-    pub async fn download_website_log(&self, id: i32) -> Result<String> {
-        self.get(&format!("/open/site/{}/log/download", id)).await
+    /// Returns the raw file content, still compressed for `.gz` files
+    pub async fn download_website_log(&self, id: i32, filename: &str) -> Result<Vec<u8>> {
+        let mut params = HashMap::new();
+        params.insert("filename".to_string(), filename.to_string());
+        let response = self
+            .get_raw(&format!("/open/site/{}/log/download", id), &params)
+            .await?;
+        Ok(response.bytes().await?.to_vec())
     }
 
     /// Get website log limit (GET /open/site/{id}/log/limit)
@@ -397,6 +423,22 @@ impl Client {
         self.get(&format!("/open/site/{}/proxy", id)).await
     }
 
+    /// Put website proxy (PUT /open/site/{id}/proxy)
+    ///
+    /// Updates proxy configuration of a website.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `request` - Proxy configuration
+    ///
+    /// # Returns
+    ///
+    /// Returns the updated proxy configuration
+    pub async fn put_website_proxy(&self, id: i32, request: &ProxyConfig) -> Result<ProxyConfig> {
+        self.put(&format!("/open/site/{}/proxy", id), request).await
+    }
+
     /// Get website semantics (GET /open/site/{id}/semantics)
     ///
     /// Retrieves semantics configuration of a website.
@@ -419,12 +461,15 @@ impl Client {
     /// # Arguments
     ///
     /// * `id` - Website ID
+    /// * `path` - Directory or file path to list
     ///
     /// # Returns
     ///
     /// Returns static files
-    pub async fn get_website_static(&self, id: i32) -> Result<Vec<FileInfo>> {
-        self.get(&format!("/open/site/{}/static", id)).await
+    pub async fn get_website_static(&self, id: i32, path: &str) -> Result<Vec<FileInfo>> {
+        let mut params = HashMap::new();
+        params.insert("path".to_string(), path.to_string());
+        self.get_with_query(&format!("/open/site/{}/static", id), &params).await
     }
 
     /// Add static (POST /open/site/{id}/static)
@@ -490,6 +535,39 @@ impl Client {
         self.put(&format!("/open/site/{}/acl", id), request).await
     }
 
+    /// Create website ACL rule (POST /open/site/{id}/acl)
+    ///
+    /// Adds an ACL rule to a website.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `rule` - ACL rule
+    ///
+    /// # Returns
+    ///
+    /// Returns the ID of the new rule
+    pub async fn create_website_acl_rule(&self, id: i32, rule: &ACLConfig) -> Result<i32> {
+        self.post(&format!("/open/site/{}/acl", id), rule).await
+    }
+
+    /// Update website ACL rule (PUT /open/site/{id}/acl/{rule_id})
+    ///
+    /// Updates an ACL rule of a website.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `rule_id` - ACL rule ID
+    /// * `rule` - ACL rule
+    ///
+    /// # Returns
+    ///
+    /// Returns the rule ID
+    pub async fn update_website_acl_rule(&self, id: i32, rule_id: i32, rule: &ACLConfig) -> Result<i32> {
+        self.put(&format!("/open/site/{}/acl/{}", id, rule_id), rule).await
+    }
+
     /// Delete website ACL rule (DELETE /open/site/{id}/acl/{rule_id})
     ///
     /// Deletes an ACL rule from a website.
@@ -542,4 +620,48 @@ impl Client {
     pub async fn get_site_mode(&self) -> Result<SiteMode> {
         self.get("/open/site/mode").await
     }
-}
\ No newline at end of file
+}
+
+impl From<&WebsiteDetailData> for WebsiteRequest {
+    fn from(detail: &WebsiteDetailData) -> Self {
+        Self {
+            id: Some(detail.id),
+            server_names: detail.server_names.clone(),
+            ports: detail.ports.clone(),
+            upstreams: detail.upstreams.clone(),
+            group_id: detail.group_id,
+            comment: detail.comment.clone(),
+            email: detail.email.clone(),
+            cert_id: detail.cert_id,
+            load_balance: detail.load_balance.clone(),
+            redirect_status_code: detail.redirect_status_code,
+            health_check: detail.health_check.clone(),
+            stat_enabled: detail.stat_enabled,
+            static_default: detail.static_default,
+            index: detail.index,
+            r#type: detail.r#type,
+        }
+    }
+}
+
+impl From<&WebsiteItem> for WebsiteRequest {
+    fn from(item: &WebsiteItem) -> Self {
+        Self {
+            id: Some(item.id),
+            server_names: item.server_names.clone(),
+            ports: item.ports.clone(),
+            upstreams: item.upstreams.clone(),
+            group_id: item.group_id,
+            comment: item.comment.clone(),
+            email: item.email.clone(),
+            cert_id: item.cert_id,
+            load_balance: item.load_balance.clone(),
+            redirect_status_code: item.redirect_status_code,
+            health_check: item.health_check.clone(),
+            stat_enabled: item.stat_enabled,
+            static_default: item.static_default,
+            index: item.index,
+            r#type: item.r#type,
+        }
+    }
+}
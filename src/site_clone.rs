@@ -0,0 +1,287 @@
+//! Website cloning
+//!
+//! Creates a new website from an existing one and copies its
+//! sub-configuration step by step. If any step fails the new website is
+//! deleted again so no half-configured site is left behind.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use futures::future::{BoxFuture, FutureExt};
+use std::fmt;
+use tracing::{info, warn};
+
+/// A step of the clone workflow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloneStep {
+    Create,
+    Defense,
+    Mode,
+    Proxy,
+    Acl,
+    Excludes,
+    StaticFiles,
+    Chaos,
+    Challenge,
+}
+
+impl fmt::Display for CloneStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CloneStep::Create => "create",
+            CloneStep::Defense => "defense",
+            CloneStep::Mode => "mode",
+            CloneStep::Proxy => "proxy",
+            CloneStep::Acl => "acl",
+            CloneStep::Excludes => "excludes",
+            CloneStep::StaticFiles => "static_files",
+            CloneStep::Chaos => "chaos",
+            CloneStep::Challenge => "challenge",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Outcome of a single clone step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepStatus {
+    Applied,
+    /// The source website has nothing to copy for this step
+    Skipped,
+}
+
+/// Result of cloning a website
+#[derive(Debug, Clone)]
+pub struct CloneReport {
+    /// ID of the new website
+    pub id: i32,
+    pub steps: Vec<(CloneStep, StepStatus)>,
+}
+
+impl Client {
+    /// Clone website
+    ///
+    /// Creates a new website with the given server names and upstreams,
+    /// then copies defense, mode, proxy, ACL rules, excludes, static files,
+    /// chaos and challenge settings from the source website. The new
+    /// website is deleted if any step fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_id` - ID of the website to copy
+    /// * `new_server_names` - Server names of the new website
+    /// * `new_upstreams` - Upstreams of the new website
+    ///
+    /// # Returns
+    ///
+    /// Returns the new website ID and the status of every step
+    pub async fn clone_website(
+        &self,
+        source_id: i32,
+        new_server_names: Vec<String>,
+        new_upstreams: Vec<String>,
+    ) -> Result<CloneReport> {
+        let source = self.get_website_detail(source_id).await?.data;
+
+        let request = WebsiteRequest {
+            id: None,
+            server_names: new_server_names,
+            upstreams: new_upstreams,
+            ..WebsiteRequest::from(&source)
+        };
+        let id = self
+            .create_website(&request)
+            .await
+            .map_err(|e| step_failed(CloneStep::Create, e))?
+            .data;
+        info!("clone website {}: created website {}", source_id, id);
+
+        let mut report = CloneReport {
+            id,
+            steps: vec![(CloneStep::Create, StepStatus::Applied)],
+        };
+
+        if let Err((step, e)) = self.clone_settings(&source, id, &mut report).await {
+            warn!("clone website {}: step {} failed: {}, rolling back website {}", source_id, step, e, id);
+            if let Err(rollback) = self.delete_websites(&DeleteWebsiteRequest { ids: vec![id] }).await {
+                warn!("clone website {}: rollback of website {} failed: {}", source_id, id, rollback);
+            }
+            return Err(step_failed(step, e));
+        }
+
+        Ok(report)
+    }
+
+    async fn clone_settings(
+        &self,
+        source: &WebsiteDetailData,
+        id: i32,
+        report: &mut CloneReport,
+    ) -> std::result::Result<(), (CloneStep, Error)> {
+        let steps: [(CloneStep, BoxFuture<'_, Result<StepStatus>>); 8] = [
+            (CloneStep::Defense, self.clone_defense(source, id).boxed()),
+            (CloneStep::Mode, self.clone_mode(source, id).boxed()),
+            (CloneStep::Proxy, self.clone_proxy(source, id).boxed()),
+            (CloneStep::Acl, self.clone_acl(source, id).boxed()),
+            (CloneStep::Excludes, self.clone_excludes(source, id).boxed()),
+            (CloneStep::StaticFiles, self.clone_static(source, id).boxed()),
+            (CloneStep::Chaos, self.clone_chaos(source, id).boxed()),
+            (CloneStep::Challenge, self.clone_challenge(source, id).boxed()),
+        ];
+
+        // Futures are lazy, so the steps still run one after the other
+        for (step, clone) in steps {
+            let status = clone.await.map_err(|e| (step, e))?;
+
+            info!("clone website {}: {} {:?}", source.id, step, status);
+            report.steps.push((step, status));
+        }
+
+        Ok(())
+    }
+
+    async fn clone_defense(&self, source: &WebsiteDetailData, id: i32) -> Result<StepStatus> {
+        let Some(policy_id) = source.auth_defense_id else {
+            return Ok(StepStatus::Skipped);
+        };
+        let policy = self.get_policy_detail(policy_id).await?.data;
+
+        self.put_website_defense(
+            id,
+            &PutWebsiteDefenseRequest {
+                id,
+                enable: policy.is_enabled,
+                pattern: policy.pattern,
+                negate: policy.negate,
+                auth_source_ids: policy.auth_source_ids,
+                auth_callback: policy.auth_callback,
+                review: policy.review,
+                tfa_enabled: policy.tfa_enabled,
+                portal_redirect: source.portal_redirect,
+            },
+        )
+        .await?;
+        Ok(StepStatus::Applied)
+    }
+
+    async fn clone_mode(&self, source: &WebsiteDetailData, id: i32) -> Result<StepStatus> {
+        self.put_website_mode(&PutWebsiteModeRequest {
+            ids: vec![id],
+            mode: source.mode,
+        })
+        .await?;
+        Ok(StepStatus::Applied)
+    }
+
+    async fn clone_proxy(&self, source: &WebsiteDetailData, id: i32) -> Result<StepStatus> {
+        let proxy = self.get_website_proxy(source.id).await?;
+        let target = self.get_website_proxy(id).await?;
+
+        self.put_website_proxy(
+            id,
+            &ProxyConfig {
+                id: target.id,
+                site_id: Some(id),
+                created_at: target.created_at,
+                updated_at: target.updated_at,
+                ..proxy
+            },
+        )
+        .await?;
+        Ok(StepStatus::Applied)
+    }
+
+    async fn clone_acl(&self, source: &WebsiteDetailData, id: i32) -> Result<StepStatus> {
+        let rules = self.get_website_acl(source.id).await?;
+        if rules.is_empty() {
+            return Ok(StepStatus::Skipped);
+        }
+
+        self.apply_acl_rules(id, &rules).await?;
+        Ok(StepStatus::Applied)
+    }
+
+    async fn clone_excludes(&self, source: &WebsiteDetailData, id: i32) -> Result<StepStatus> {
+        let excludes = self.get_website_excludes(source.id).await?;
+        if excludes.prefixes.is_empty() && excludes.content_types.is_empty() {
+            return Ok(StepStatus::Skipped);
+        }
+
+        self.update_excludes(
+            id,
+            &UpdateExcludesRequest {
+                prefixes: excludes.prefixes,
+                content_types: excludes.content_types,
+            },
+        )
+        .await?;
+        Ok(StepStatus::Applied)
+    }
+
+    async fn clone_static(&self, source: &WebsiteDetailData, id: i32) -> Result<StepStatus> {
+        let mut copied = 0;
+        let mut pending = vec!["/".to_string()];
+
+        while let Some(dir) = pending.pop() {
+            for file in self.get_website_static(source.id, &dir).await? {
+                let path = format!("{}/{}", dir.trim_end_matches('/'), file.name);
+                self.add_static(
+                    id,
+                    &AddStaticRequest {
+                        path: path.clone(),
+                        dir: file.dir,
+                        page: file.content,
+                        zip: false,
+                    },
+                )
+                .await?;
+                if file.dir {
+                    pending.push(path);
+                }
+                copied += 1;
+            }
+        }
+
+        Ok(if copied == 0 {
+            StepStatus::Skipped
+        } else {
+            StepStatus::Applied
+        })
+    }
+
+    async fn clone_chaos(&self, source: &WebsiteDetailData, id: i32) -> Result<StepStatus> {
+        if source.chaos_id.is_none() && source.chaos_is_enabled != Some(true) {
+            return Ok(StepStatus::Skipped);
+        }
+
+        let chaos = self.get_website_chaos(source.id).await?;
+        self.website_chaos(id, &WebsiteChaosRequest::from(&chaos)).await?;
+        Ok(StepStatus::Applied)
+    }
+
+    async fn clone_challenge(&self, source: &WebsiteDetailData, id: i32) -> Result<StepStatus> {
+        let Some(policy_id) = source.challenge_id else {
+            return Ok(StepStatus::Skipped);
+        };
+        let policy = self.get_policy_detail(policy_id).await?.data;
+
+        self.website_challenge(&WebsiteChallengeRequest {
+            id,
+            enable: policy.is_enabled,
+            level: Some(policy.level),
+            pattern: policy.pattern,
+            negate: policy.negate,
+            replay: policy.replay,
+            expire: policy.expire,
+        })
+        .await?;
+        Ok(StepStatus::Applied)
+    }
+}
+
+fn step_failed(step: CloneStep, source: Error) -> Error {
+    Error::StepFailed {
+        step: step.to_string(),
+        source: Box::new(source),
+    }
+}
@@ -0,0 +1,309 @@
+//! Serializable site configuration documents
+//!
+//! A `SiteConfig` describes a complete website: basic settings, proxy,
+//! excludes, ACL rules, nginx custom config, chaos and waiting room.
+//! Documents can be exported from one site and imported into the same or
+//! another SafeLine instance, and round-trip through YAML and TOML.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use serde::{Deserialize, Serialize};
+
+/// Full configuration of a website
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteConfig {
+    pub site: WebsiteRequest,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<ProxyConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excludes: Option<SiteExcludes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acl: Option<SetSiteACLRequest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nginx_custom_location: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chaos: Option<WebsiteChaosRequest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waiting_room: Option<SetWebsiteWaitingRoomRequest>,
+}
+
+impl SiteConfig {
+    /// Parse a document from YAML
+    pub fn from_yaml(input: &str) -> Result<Self> {
+        serde_yaml::from_str(input).map_err(|e| Error::ConfigFormat(e.to_string()))
+    }
+
+    /// Render the document as YAML
+    pub fn to_yaml(&self) -> Result<String> {
+        serde_yaml::to_string(self).map_err(|e| Error::ConfigFormat(e.to_string()))
+    }
+
+    /// Parse a document from TOML
+    pub fn from_toml(input: &str) -> Result<Self> {
+        toml::from_str(input).map_err(|e| Error::ConfigFormat(e.to_string()))
+    }
+
+    /// Render the document as TOML
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| Error::ConfigFormat(e.to_string()))
+    }
+}
+
+impl Client {
+    /// Export site configuration
+    ///
+    /// Assembles a `SiteConfig` from the website detail, proxy, excludes,
+    /// ACL, nginx, chaos and waiting room endpoints. Instance specific IDs,
+    /// including the certificate and the site group, are cleared so the
+    /// document can be imported elsewhere.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    ///
+    /// # Returns
+    ///
+    /// Returns the site configuration document
+    pub async fn export_site(&self, id: i32) -> Result<SiteConfig> {
+        let detail = self.get_website_detail(id).await?.data;
+        let site = WebsiteRequest {
+            id: None,
+            cert_id: None,
+            group_id: 0,
+            ..WebsiteRequest::from(&detail)
+        };
+
+        let mut proxy = self.get_website_proxy(id).await?;
+        proxy.id = None;
+        proxy.site_id = None;
+        proxy.created_at = None;
+        proxy.updated_at = None;
+
+        let excludes = self.get_website_excludes(id).await?;
+
+        let rules = self
+            .get_website_acl(id)
+            .await?
+            .into_iter()
+            .map(|rule| ACLConfig {
+                id: 0,
+                site_id: 0,
+                created_at: None,
+                updated_at: None,
+                ..rule
+            })
+            .collect();
+
+        let nginx = self.get_nginx_config(id).await?.data;
+        let chaos = self.get_website_chaos(id).await?;
+        let waiting_room = self.get_website_waiting_room(id).await?;
+
+        Ok(SiteConfig {
+            site,
+            proxy: Some(proxy),
+            excludes: Some(excludes),
+            acl: Some(SetSiteACLRequest { rules, use_global: None }),
+            nginx_custom_location: Some(nginx.custom_location),
+            chaos: Some(WebsiteChaosRequest::from(&chaos)),
+            waiting_room: Some(SetWebsiteWaitingRoomRequest {
+                is_enabled: waiting_room.is_enabled,
+                max_concurrent: waiting_room.max_concurrent,
+                session_timeout: waiting_room.session_timeout,
+            }),
+        })
+    }
+
+    /// Import site configuration
+    ///
+    /// Updates the website whose server names contain the document's first
+    /// server name, or creates it if none exists, then applies every
+    /// sub-configuration present in the document. An existing website keeps
+    /// its certificate and site group unless the document sets them. ACL
+    /// rules are written one at a time through `apply_acl_rules`, so rules
+    /// already on the website and not in the document are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `doc` - Site configuration document
+    ///
+    /// # Returns
+    ///
+    /// Returns the website ID
+    pub async fn import_site(&self, doc: &SiteConfig) -> Result<i32> {
+        let key = doc
+            .site
+            .server_names
+            .first()
+            .ok_or_else(|| Error::InvalidInput("site config has no server name".to_string()))?;
+
+        let existing = self
+            .list_websites()
+            .await?
+            .data
+            .into_iter()
+            .find(|site| site.server_names.contains(key));
+
+        let id = match existing {
+            Some(site) => {
+                let request = WebsiteRequest {
+                    id: Some(site.id),
+                    cert_id: doc.site.cert_id.or(site.cert_id),
+                    group_id: if doc.site.group_id == 0 { site.group_id } else { doc.site.group_id },
+                    ..doc.site.clone()
+                };
+                self.update_website(site.id, &request).await?;
+                site.id
+            }
+            None => {
+                let request = WebsiteRequest {
+                    id: None,
+                    ..doc.site.clone()
+                };
+                self.create_website(&request).await?.data
+            }
+        };
+
+        if let Some(ref proxy) = doc.proxy {
+            let proxy = ProxyConfig {
+                site_id: Some(id),
+                ..proxy.clone()
+            };
+            self.put_website_proxy(id, &proxy).await?;
+        }
+
+        if let Some(ref excludes) = doc.excludes {
+            self.update_excludes(
+                id,
+                &UpdateExcludesRequest {
+                    prefixes: excludes.prefixes.clone(),
+                    content_types: excludes.content_types.clone(),
+                },
+            )
+            .await?;
+        }
+
+        if let Some(ref acl) = doc.acl {
+            let rules: Vec<_> = acl
+                .rules
+                .iter()
+                .map(|rule| ACLConfig {
+                    use_global: rule.use_global.or(acl.use_global),
+                    ..rule.clone()
+                })
+                .collect();
+            self.apply_acl_rules(id, &rules).await?;
+        }
+
+        if let Some(ref custom_location) = doc.nginx_custom_location {
+            self.update_nginx_config(
+                id,
+                &NginxConfigUpdateRequest {
+                    custom_location: custom_location.clone(),
+                },
+            )
+            .await?;
+        }
+
+        if let Some(ref chaos) = doc.chaos {
+            self.website_chaos(id, chaos).await?;
+        }
+
+        if let Some(ref waiting_room) = doc.waiting_room {
+            self.set_website_waiting_room(id, waiting_room).await?;
+        }
+
+        Ok(id)
+    }
+}
+
+impl From<&Chaos> for WebsiteChaosRequest {
+    fn from(chaos: &Chaos) -> Self {
+        Self {
+            is_enabled: chaos.is_enabled,
+            html_encryption: Some(chaos.html_encryption),
+            html_fast_decryption: Some(chaos.html_fast_decryption),
+            js_encryption: Some(chaos.js_encryption),
+            js_path: Some(chaos.js_path.clone()),
+            img_encryption: Some(chaos.img_encryption),
+            img_text: Some(chaos.img_text),
+            img_watermark: Some(chaos.img_watermark),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> SiteConfig {
+        SiteConfig {
+            site: WebsiteRequest {
+                id: None,
+                server_names: vec!["app.example.com".to_string()],
+                ports: vec!["443_ssl".to_string()],
+                upstreams: vec!["http://10.0.0.1:8080".to_string()],
+                group_id: 0,
+                comment: Some("app".to_string()),
+                email: None,
+                cert_id: None,
+                load_balance: None,
+                redirect_status_code: None,
+                health_check: None,
+                stat_enabled: Some(true),
+                static_default: None,
+                index: None,
+                r#type: None,
+            },
+            proxy: None,
+            excludes: Some(SiteExcludes {
+                prefixes: vec!["/static/".to_string()],
+                content_types: vec!["image/png".to_string()],
+            }),
+            acl: Some(SetSiteACLRequest {
+                rules: vec![ACLConfig {
+                    id: 0,
+                    site_id: 0,
+                    name: "login".to_string(),
+                    r#type: Some("req".to_string()),
+                    action: Some("ban".to_string()),
+                    enabled: Some(true),
+                    use_global: None,
+                    built_in: None,
+                    priority: None,
+                    period: Some(60),
+                    block_min: Some(10),
+                    count: Some(100),
+                    conditions: None,
+                    created_at: None,
+                    updated_at: None,
+                }],
+                use_global: None,
+            }),
+            nginx_custom_location: Some("location /health { return 200; }".to_string()),
+            chaos: None,
+            waiting_room: Some(SetWebsiteWaitingRoomRequest {
+                is_enabled: false,
+                max_concurrent: 100,
+                session_timeout: 60,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_yaml_round_trip() {
+        let yaml = sample().to_yaml().unwrap();
+        let parsed = SiteConfig::from_yaml(&yaml).unwrap();
+        assert_eq!(parsed.to_yaml().unwrap(), yaml);
+        assert_eq!(parsed.site.server_names, vec!["app.example.com"]);
+        assert_eq!(parsed.site.cert_id, None);
+    }
+
+    #[test]
+    fn test_toml_round_trip() {
+        let toml = sample().to_toml().unwrap();
+        let parsed = SiteConfig::from_toml(&toml).unwrap();
+        assert_eq!(parsed.to_toml().unwrap(), toml);
+        assert_eq!(parsed.acl.unwrap().rules[0].count, Some(100));
+    }
+}
@@ -0,0 +1,437 @@
+//! Website access and error logs
+//!
+//! Downloads log files listed by `get_website_log` as a byte stream,
+//! transparently decompressing rotated `.gz` files, and parses SafeLine's
+//! nginx access and error log lines into typed entries.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::time::unix_time;
+use async_compression::tokio::bufread::GzipDecoder;
+use bytes::Bytes;
+use futures::stream::{self, Stream, TryStreamExt};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::str::FromStr;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use tokio_util::io::StreamReader;
+
+/// Buffered reader over a decompressed log file
+pub type LogReader = Pin<Box<dyn AsyncBufRead + Send>>;
+
+/// A parsed access log line
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessLogEntry {
+    /// Unix timestamp in seconds
+    pub timestamp: i64,
+    pub client_ip: String,
+    pub method: String,
+    pub path: String,
+    pub protocol: String,
+    pub status: u16,
+    /// Response body size in bytes
+    pub bytes: u64,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    /// Upstream response time in seconds, summed over all tried upstreams
+    pub upstream_time: Option<f64>,
+}
+
+/// A parsed error log line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorLogEntry {
+    /// Unix timestamp in seconds; error logs carry no time zone, so the
+    /// server's local time is read as UTC
+    pub timestamp: i64,
+    pub level: String,
+    pub pid: u32,
+    pub connection: Option<u64>,
+    pub message: String,
+    pub client: Option<String>,
+    pub server: Option<String>,
+    pub request: Option<String>,
+    pub upstream: Option<String>,
+    pub host: Option<String>,
+}
+
+fn parse_error(kind: &str, line: &str) -> Error {
+    Error::InvalidInput(format!("invalid {} log line: {}", kind, line))
+}
+
+/// Parse `10/Oct/2023:13:55:36 +0800`
+fn parse_time_local(value: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let (datetime, zone) = value.split_once(' ')?;
+    let (date, time) = datetime.split_once(':')?;
+    let mut date = date.split('/');
+    let day = date.next()?.parse().ok()?;
+    let month = date.next()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let year = date.next()?.parse().ok()?;
+
+    let sign = match zone.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let offset = zone.get(1..3)?.parse::<i64>().ok()? * 3600 + zone.get(3..5)?.parse::<i64>().ok()? * 60;
+
+    Some(unix_time(year, month, day, time)? - sign * offset)
+}
+
+/// Parse `2023/10/10 13:55:36`
+fn parse_error_time(date: &str, time: &str) -> Option<i64> {
+    let mut date = date.split('/').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    unix_time(year, month, day, time)
+}
+
+/// Split a log line into bare, `"quoted"` and `[bracketed]` fields
+fn split_fields(line: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut field = String::new();
+        match c {
+            '"' => {
+                chars.next();
+                loop {
+                    match chars.next()? {
+                        '\\' => field.push(chars.next()?),
+                        '"' => break,
+                        other => field.push(other),
+                    }
+                }
+            }
+            '[' => {
+                chars.next();
+                loop {
+                    match chars.next()? {
+                        ']' => break,
+                        other => field.push(other),
+                    }
+                }
+            }
+            _ => {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    field.push(c);
+                    chars.next();
+                    // `key="quoted value"`
+                    if c == '=' && chars.peek() == Some(&'"') {
+                        chars.next();
+                        loop {
+                            match chars.next()? {
+                                '\\' => field.push(chars.next()?),
+                                '"' => break,
+                                other => field.push(other),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        fields.push(field);
+    }
+
+    Some(fields)
+}
+
+fn optional(value: &str) -> Option<String> {
+    (!value.is_empty() && value != "-").then(|| value.to_string())
+}
+
+/// Sum `0.004, 0.010` style upstream times; `-` means no upstream
+fn parse_upstream_time(value: &str) -> Option<f64> {
+    let times: Vec<f64> = value
+        .split([',', ':'])
+        .map(str::trim)
+        .filter(|t| !t.is_empty() && *t != "-")
+        .map(|t| t.parse().ok())
+        .collect::<Option<_>>()?;
+    (!times.is_empty()).then(|| times.iter().sum())
+}
+
+impl FromStr for AccessLogEntry {
+    type Err = Error;
+
+    /// Parse the nginx combined format, optionally followed by extra fields.
+    /// The upstream time is read from a `urt=`, `upstream_time=` or
+    /// `upstream_response_time=` field, or else from the first numeric
+    /// extra field.
+    fn from_str(line: &str) -> Result<Self> {
+        let invalid = || parse_error("access", line);
+        let fields = split_fields(line).ok_or_else(invalid)?;
+        if fields.len() < 9 {
+            return Err(invalid());
+        }
+
+        let timestamp = parse_time_local(&fields[3]).ok_or_else(invalid)?;
+        let mut request = fields[4].splitn(3, ' ');
+        let method = request.next().filter(|m| *m != "-").unwrap_or_default().to_string();
+        let path = request.next().unwrap_or_default().to_string();
+        let protocol = request.next().unwrap_or_default().to_string();
+        let status = fields[5].parse().map_err(|_| invalid())?;
+        let bytes = if fields[6] == "-" {
+            0
+        } else {
+            fields[6].parse().map_err(|_| invalid())?
+        };
+
+        let extras = &fields[9..];
+        let upstream_time = extras
+            .iter()
+            .find_map(|f| {
+                let (key, value) = f.split_once('=')?;
+                matches!(key, "urt" | "upstream_time" | "upstream_response_time").then(|| parse_upstream_time(value))
+            })
+            .unwrap_or_else(|| extras.iter().find_map(|f| parse_upstream_time(f)));
+
+        Ok(Self {
+            timestamp,
+            client_ip: fields[0].clone(),
+            method,
+            path,
+            protocol,
+            status,
+            bytes,
+            referer: optional(&fields[7]),
+            user_agent: optional(&fields[8]),
+            upstream_time,
+        })
+    }
+}
+
+/// Split `client: 1.2.3.4, request: "GET / HTTP/1.1"` into key/value pairs
+fn parse_context(mut rest: &str, pairs: &mut HashMap<String, String>) {
+    while let Some((key, value)) = rest.split_once(": ") {
+        let key = key.trim_start_matches(", ").to_string();
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => match value.find(", ") {
+                Some(end) => (&value[..end], &value[end..]),
+                None => (value, ""),
+            },
+        };
+        pairs.insert(key, value.to_string());
+        rest = remaining;
+    }
+}
+
+impl FromStr for ErrorLogEntry {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let invalid = || parse_error("error", line);
+
+        let mut head = line.splitn(4, ' ');
+        let (date, time, level, rest) = (
+            head.next().ok_or_else(invalid)?,
+            head.next().ok_or_else(invalid)?,
+            head.next().ok_or_else(invalid)?,
+            head.next().ok_or_else(invalid)?,
+        );
+        let timestamp = parse_error_time(date, time).ok_or_else(invalid)?;
+        let level = level
+            .strip_prefix('[')
+            .and_then(|l| l.strip_suffix(']'))
+            .ok_or_else(invalid)?
+            .to_string();
+
+        let (process, mut rest) = rest.split_once(": ").ok_or_else(invalid)?;
+        let pid = process
+            .split('#')
+            .next()
+            .and_then(|p| p.parse().ok())
+            .ok_or_else(invalid)?;
+
+        let mut connection = None;
+        if let Some(conn) = rest.strip_prefix('*') {
+            let (id, remaining) = conn.split_once(' ').unwrap_or((conn, ""));
+            connection = id.parse().ok();
+            rest = remaining;
+        }
+
+        let mut context = HashMap::new();
+        let message = match rest.find(", client: ") {
+            Some(i) => {
+                parse_context(&rest[i..], &mut context);
+                &rest[..i]
+            }
+            None => rest,
+        };
+
+        Ok(Self {
+            timestamp,
+            level,
+            pid,
+            connection,
+            message: message.to_string(),
+            client: context.remove("client"),
+            server: context.remove("server"),
+            request: context.remove("request"),
+            upstream: context.remove("upstream"),
+            host: context.remove("host"),
+        })
+    }
+}
+
+impl Client {
+    /// Download website log as a byte stream (GET /open/site/{id}/log/download)
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `filename` - Log file name as listed by `get_website_log`
+    ///
+    /// # Returns
+    ///
+    /// Returns the raw file content in chunks, still compressed for `.gz`
+    /// files
+    pub async fn download_website_log_stream(
+        &self,
+        id: i32,
+        filename: &str,
+    ) -> Result<impl Stream<Item = Result<Bytes>>> {
+        let mut params = HashMap::new();
+        params.insert("filename".to_string(), filename.to_string());
+        let response = self
+            .get_raw(&format!("/open/site/{}/log/download", id), &params)
+            .await?;
+        Ok(response.bytes_stream().map_err(Error::from))
+    }
+
+    /// Open website log for reading
+    ///
+    /// Gzip compressed files are detected by their magic bytes and
+    /// decompressed while reading.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `filename` - Log file name as listed by `get_website_log`
+    pub async fn website_log_reader(&self, id: i32, filename: &str) -> Result<LogReader> {
+        let chunks = self
+            .download_website_log_stream(id, filename)
+            .await?
+            .map_err(std::io::Error::other);
+        let mut reader = StreamReader::new(Box::pin(chunks));
+
+        if reader.fill_buf().await?.starts_with(&[0x1f, 0x8b]) {
+            let mut decoder = GzipDecoder::new(reader);
+            decoder.multiple_members(true);
+            Ok(Box::pin(BufReader::new(decoder)))
+        } else {
+            Ok(Box::pin(reader))
+        }
+    }
+
+    /// Stream parsed access log entries of a website log file
+    ///
+    /// Lines that cannot be parsed are yielded as `Err` items; a read error
+    /// is yielded as an `Err` item and ends the stream.
+    pub async fn website_access_log(
+        &self,
+        id: i32,
+        filename: &str,
+    ) -> Result<impl Stream<Item = Result<AccessLogEntry>>> {
+        Ok(parse_lines(self.website_log_reader(id, filename).await?))
+    }
+
+    /// Stream parsed error log entries of a website log file
+    ///
+    /// Lines that cannot be parsed are yielded as `Err` items; a read error
+    /// is yielded as an `Err` item and ends the stream.
+    pub async fn website_error_log(
+        &self,
+        id: i32,
+        filename: &str,
+    ) -> Result<impl Stream<Item = Result<ErrorLogEntry>>> {
+        Ok(parse_lines(self.website_log_reader(id, filename).await?))
+    }
+}
+
+/// Parse a log line by line; a read or decompression error is yielded as
+/// an `Err` item and ends the stream
+fn parse_lines<T: FromStr<Err = Error>>(reader: LogReader) -> impl Stream<Item = Result<T>> {
+    stream::unfold(Some(reader.lines()), |lines| async move {
+        let mut lines = lines?;
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) if line.trim().is_empty() => continue,
+                Ok(Some(line)) => return Some((line.parse(), Some(lines))),
+                Ok(None) => return None,
+                Err(e) => return Some((Err(Error::from(e)), None)),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[test]
+    fn test_parse_access_log() {
+        let line = r#"203.0.113.9 - - [10/Oct/2023:13:55:36 +0800] "GET /api/items?page=2 HTTP/1.1" 200 5123 "https://example.com/" "Mozilla/5.0 (X11; Linux x86_64)" urt="0.004, 0.010""#;
+        let entry: AccessLogEntry = line.parse().unwrap();
+        assert_eq!(entry.timestamp, 1696917336);
+        assert_eq!(entry.client_ip, "203.0.113.9");
+        assert_eq!((entry.method.as_str(), entry.path.as_str()), ("GET", "/api/items?page=2"));
+        assert_eq!((entry.status, entry.bytes), (200, 5123));
+        assert_eq!(entry.user_agent.as_deref(), Some("Mozilla/5.0 (X11; Linux x86_64)"));
+        assert!((entry.upstream_time.unwrap() - 0.014).abs() < 1e-9);
+
+        let line = r#"10.0.0.1 - - [01/Jan/2024:00:00:00 +0000] "-" 400 0 "-" "-" 0.001"#;
+        let entry: AccessLogEntry = line.parse().unwrap();
+        assert_eq!(entry.timestamp, 1704067200);
+        assert_eq!(entry.method, "");
+        assert_eq!(entry.referer, None);
+        assert_eq!(entry.upstream_time, Some(0.001));
+
+        assert!("not a log line".parse::<AccessLogEntry>().is_err());
+    }
+
+    #[test]
+    fn test_parse_error_log() {
+        let line = r#"2023/10/10 13:55:36 [error] 1234#0: *5678 connect() failed (111: Connection refused) while connecting to upstream, client: 203.0.113.9, server: example.com, request: "GET /a, b HTTP/1.1", upstream: "http://10.0.0.1:8080/", host: "example.com""#;
+        let entry: ErrorLogEntry = line.parse().unwrap();
+        assert_eq!(entry.timestamp, 1696946136);
+        assert_eq!(entry.level, "error");
+        assert_eq!((entry.pid, entry.connection), (1234, Some(5678)));
+        assert_eq!(entry.message, "connect() failed (111: Connection refused) while connecting to upstream");
+        assert_eq!(entry.client.as_deref(), Some("203.0.113.9"));
+        assert_eq!(entry.request.as_deref(), Some("GET /a, b HTTP/1.1"));
+        assert_eq!(entry.upstream.as_deref(), Some("http://10.0.0.1:8080/"));
+        assert_eq!(entry.host.as_deref(), Some("example.com"));
+
+        let entry: ErrorLogEntry = "2024/01/01 00:00:00 [notice] 1#1: signal process started".parse().unwrap();
+        assert_eq!((entry.connection, entry.client), (None, None));
+    }
+    #[tokio::test]
+    async fn test_parse_lines_stops_on_read_error() {
+        let line = r#"10.0.0.1 - - [01/Jan/2024:00:00:00 +0000] "-" 400 0 "-" "-" 0.001"#;
+        let mut input = format!("{}\n\nnot a log line\n", line).into_bytes();
+        input.extend_from_slice(b"\xff\n");
+        input.extend_from_slice(format!("{}\n", line).as_bytes());
+        let reader: LogReader = Box::pin(BufReader::new(std::io::Cursor::new(input)));
+
+        let items: Vec<Result<AccessLogEntry>> = parse_lines(reader).collect().await;
+        assert_eq!(items.len(), 3);
+        assert!(items[0].is_ok());
+        assert!(items[1].is_err());
+        assert!(matches!(items[2], Err(Error::IoError(_))));
+    }
+}
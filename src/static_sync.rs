@@ -0,0 +1,480 @@
+//! Static file directory sync
+//!
+//! Mirrors a local directory to a website's static files. Local and remote
+//! files are compared by content hash when the server returns file content,
+//! otherwise by size and modification time. Files that only moved are
+//! renamed on the server instead of being uploaded again.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::glob::glob_match;
+use crate::models::*;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tracing::{info, warn};
+
+/// Options for `sync_static_dir`
+#[derive(Debug, Clone, Default)]
+pub struct StaticSyncOptions {
+    /// Only sync paths matching one of these globs, all paths if empty
+    pub include: Vec<String>,
+    /// Never touch paths matching one of these globs
+    pub exclude: Vec<String>,
+    /// Delete remote files that do not exist locally
+    pub delete: bool,
+    /// Only compute the plan
+    pub dry_run: bool,
+}
+
+impl StaticSyncOptions {
+    fn selects(&self, path: &str) -> bool {
+        let path = path.trim_start_matches('/');
+        (self.include.is_empty() || self.include.iter().any(|g| glob_match(g, path)))
+            && !self.exclude.iter().any(|g| glob_match(g, path))
+    }
+}
+
+/// Size, modification time and content hash of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileState {
+    pub size: u64,
+    /// Unix timestamp in seconds
+    pub modified: i64,
+    /// Content hash, if the content is known
+    pub hash: Option<u64>,
+}
+
+/// A change needed to bring the server in sync
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StaticSyncAction {
+    CreateDir(String),
+    Upload { path: String, replace: bool },
+    /// Move a remote file whose content matches a local one; with `copy`
+    /// the source is kept
+    Rename { from: String, to: String, copy: bool },
+    Delete(String),
+}
+
+/// Changes computed by `sync_static_dir`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StaticSyncPlan {
+    pub actions: Vec<StaticSyncAction>,
+    /// Number of files already in sync
+    pub unchanged: usize,
+    /// Local files that cannot be uploaded, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+impl StaticSyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+impl fmt::Display for StaticSyncPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in &self.actions {
+            match action {
+                StaticSyncAction::CreateDir(path) => writeln!(f, "+ mkdir {}", path)?,
+                StaticSyncAction::Upload { path, replace: false } => writeln!(f, "+ upload {}", path)?,
+                StaticSyncAction::Upload { path, replace: true } => writeln!(f, "~ upload {}", path)?,
+                StaticSyncAction::Rename { from, to, copy: false } => writeln!(f, "> rename {} -> {}", from, to)?,
+                StaticSyncAction::Rename { from, to, copy: true } => writeln!(f, "> copy {} -> {}", from, to)?,
+                StaticSyncAction::Delete(path) => writeln!(f, "- delete {}", path)?,
+            }
+        }
+        for (path, reason) in &self.skipped {
+            writeln!(f, "! skip {} ({})", path, reason)?;
+        }
+        write!(
+            f,
+            "Sync: {} changes, {} unchanged, {} skipped",
+            self.actions.len(),
+            self.unchanged,
+            self.skipped.len()
+        )
+    }
+}
+
+/// FNV-1a hash, stable across processes
+fn content_hash(content: &[u8]) -> u64 {
+    content.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn parent_dirs(path: &str) -> impl Iterator<Item = String> + '_ {
+    path.match_indices('/')
+        .skip(1)
+        .map(move |(i, _)| path[..i].to_string())
+}
+
+/// Compute the changes needed to make `remote` match `local`
+///
+/// Paths are absolute within the site (`/css/site.css`). `remote_dirs`
+/// lists the directories that already exist on the server.
+pub fn plan_static_sync(
+    local: &BTreeMap<String, FileState>,
+    remote: &BTreeMap<String, FileState>,
+    remote_dirs: &BTreeSet<String>,
+    options: &StaticSyncOptions,
+) -> StaticSyncPlan {
+    let local: BTreeMap<&String, &FileState> = local.iter().filter(|(p, _)| options.selects(p)).collect();
+    let remote: BTreeMap<&String, &FileState> = remote.iter().filter(|(p, _)| options.selects(p)).collect();
+
+    let mut plan = StaticSyncPlan::default();
+    let mut uploads = Vec::new();
+    let mut renames = Vec::new();
+
+    // Remote files that are not wanted locally may be rename sources
+    let mut orphans: Vec<&String> = remote.keys().filter(|p| !local.contains_key(*p)).copied().collect();
+
+    for (path, state) in &local {
+        match remote.get(path) {
+            Some(current) => {
+                let same = match (state.hash, current.hash) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => state.size == current.size && state.modified <= current.modified,
+                };
+                if same {
+                    plan.unchanged += 1;
+                } else {
+                    uploads.push(StaticSyncAction::Upload {
+                        path: path.to_string(),
+                        replace: true,
+                    });
+                }
+            }
+            None => {
+                let moved = orphans.iter().position(|o| {
+                    let o = remote[*o];
+                    o.hash.is_some() && o.hash == state.hash && o.size == state.size
+                });
+                match moved {
+                    // Without `delete` the source stays, so it is copied
+                    Some(i) => renames.push(StaticSyncAction::Rename {
+                        from: orphans.remove(i).to_string(),
+                        to: path.to_string(),
+                        copy: !options.delete,
+                    }),
+                    None => uploads.push(StaticSyncAction::Upload {
+                        path: path.to_string(),
+                        replace: false,
+                    }),
+                }
+            }
+        }
+    }
+
+    let mut dirs = BTreeSet::new();
+    for action in renames.iter().chain(&uploads) {
+        let path = match action {
+            StaticSyncAction::Rename { to, .. } => to,
+            StaticSyncAction::Upload { path, .. } => path,
+            _ => continue,
+        };
+        dirs.extend(parent_dirs(path).filter(|d| !remote_dirs.contains(d)));
+    }
+
+    plan.actions.extend(dirs.into_iter().map(StaticSyncAction::CreateDir));
+    plan.actions.extend(renames);
+    plan.actions.extend(uploads);
+    if options.delete {
+        plan.actions
+            .extend(orphans.into_iter().map(|p| StaticSyncAction::Delete(p.to_string())));
+    }
+
+    plan
+}
+
+/// Walk a local directory, following symlinks; entries that are neither
+/// directories nor regular UTF-8 files are recorded in `skipped`
+fn walk_local(root: &Path, skipped: &mut Vec<(String, String)>) -> Result<BTreeMap<String, FileState>> {
+    let mut files = BTreeMap::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(root.to_path_buf(), String::new())];
+
+    while let Some((dir, prefix)) = pending.pop() {
+        if !visited.insert(std::fs::canonicalize(&dir)?) {
+            skipped.push((prefix, "directory already visited through a symlink".to_string()));
+            continue;
+        }
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = format!("{}/{}", prefix, name);
+            let meta = match std::fs::metadata(entry.path()) {
+                Ok(meta) => meta,
+                Err(e) => {
+                    skipped.push((path, e.to_string()));
+                    continue;
+                }
+            };
+
+            if meta.is_dir() {
+                pending.push((entry.path(), path));
+                continue;
+            }
+            if !meta.is_file() {
+                skipped.push((path, "not a regular file".to_string()));
+                continue;
+            }
+
+            let content = std::fs::read(entry.path())?;
+            if std::str::from_utf8(&content).is_err() {
+                skipped.push((path, "not UTF-8 text".to_string()));
+                continue;
+            }
+
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or_default();
+            files.insert(
+                path,
+                FileState {
+                    size: meta.len(),
+                    modified,
+                    hash: Some(content_hash(&content)),
+                },
+            );
+        }
+    }
+
+    Ok(files)
+}
+
+impl Client {
+    /// Sync static directory
+    ///
+    /// Walks `local_path` and the website's static files, then creates
+    /// directories, renames moved files, uploads new or changed files and,
+    /// with `delete`, removes remote files missing locally. Without
+    /// `delete`, moved files are copied instead of renamed. Empty remote
+    /// directories are left in place. With `dry_run` only the plan is
+    /// returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `site_id` - Website ID
+    /// * `local_path` - Local directory to mirror
+    /// * `options` - Include/exclude globs, deletion and dry-run
+    ///
+    /// # Returns
+    ///
+    /// Returns the plan that was (or would be) applied
+    pub async fn sync_static_dir(
+        &self,
+        site_id: i32,
+        local_path: impl AsRef<Path>,
+        options: &StaticSyncOptions,
+    ) -> Result<StaticSyncPlan> {
+        let root = local_path.as_ref();
+        let mut skipped = Vec::new();
+        let local = walk_local(root, &mut skipped)?;
+
+        let mut remote = BTreeMap::new();
+        let mut remote_dirs = BTreeSet::new();
+        let mut pending = vec!["/".to_string()];
+        while let Some(dir) = pending.pop() {
+            for file in self.get_website_static(site_id, &dir).await? {
+                let path = format!("{}/{}", dir.trim_end_matches('/'), file.name);
+                if file.dir {
+                    remote_dirs.insert(path.clone());
+                    pending.push(path);
+                    continue;
+                }
+                let hash = (!file.content.is_empty() || file.size == 0).then(|| content_hash(file.content.as_bytes()));
+                remote.insert(
+                    path,
+                    FileState {
+                        size: file.size.max(0) as u64,
+                        modified: file.modify_time,
+                        hash,
+                    },
+                );
+            }
+        }
+
+        let mut plan = plan_static_sync(&local, &remote, &remote_dirs, options);
+        plan.skipped = skipped.into_iter().filter(|(p, _)| options.selects(p)).collect();
+
+        if options.dry_run {
+            return Ok(plan);
+        }
+
+        for action in &plan.actions {
+            info!("sync static of website {}: {:?}", site_id, action);
+            match action {
+                StaticSyncAction::CreateDir(path) => {
+                    self.add_static(
+                        site_id,
+                        &AddStaticRequest {
+                            path: path.clone(),
+                            dir: true,
+                            page: String::new(),
+                            zip: false,
+                        },
+                    )
+                    .await?
+                }
+                StaticSyncAction::Upload { path, .. } => {
+                    let file = root.join(path.trim_start_matches('/'));
+                    let page = std::fs::read_to_string(&file).map_err(|e| {
+                        Error::InvalidInput(format!("failed to read {}: {}", file.display(), e))
+                    })?;
+                    self.add_static(
+                        site_id,
+                        &AddStaticRequest {
+                            path: path.clone(),
+                            dir: false,
+                            page,
+                            zip: false,
+                        },
+                    )
+                    .await?
+                }
+                StaticSyncAction::Rename { from, to, copy } => {
+                    self.rename_static(
+                        site_id,
+                        &RenameStaticRequest {
+                            old_path: from.clone(),
+                            new_path: to.clone(),
+                            copy: *copy,
+                        },
+                    )
+                    .await?
+                }
+                StaticSyncAction::Delete(path) => {
+                    self.delete_static(site_id, &DeleteStaticRequest { path: path.clone() })
+                        .await?
+                }
+            }
+        }
+
+        for (path, reason) in &plan.skipped {
+            warn!("sync static of website {}: skipped {} ({})", site_id, path, reason);
+        }
+
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(content: &str, modified: i64) -> FileState {
+        FileState {
+            size: content.len() as u64,
+            modified,
+            hash: Some(content_hash(content.as_bytes())),
+        }
+    }
+
+    #[test]
+    fn test_plan_static_sync() {
+        let local: BTreeMap<String, FileState> = [
+            ("/index.html", file("<h1>new</h1>", 200)),
+            ("/css/site.css", file("body {}", 100)),
+            ("/js/app.js", file("run()", 100)),
+            ("/img/logo.txt", file("logo", 100)),
+            ("/drafts/wip.html", file("wip", 100)),
+        ]
+        .into_iter()
+        .map(|(p, s)| (p.to_string(), s))
+        .collect();
+
+        let mut remote: BTreeMap<String, FileState> = [
+            ("/index.html", file("<h1>old</h1>", 150)),
+            ("/css/site.css", file("body {}", 50)),
+            ("/app.js", file("run()", 100)),
+            ("/old.html", file("gone", 100)),
+        ]
+        .into_iter()
+        .map(|(p, s)| (p.to_string(), s))
+        .collect();
+        // Content unknown: compared by size and mtime
+        remote.insert(
+            "/img/logo.txt".to_string(),
+            FileState {
+                size: 4,
+                modified: 100,
+                hash: None,
+            },
+        );
+        let remote_dirs: BTreeSet<String> = ["/css".to_string(), "/img".to_string()].into();
+
+        let options = StaticSyncOptions {
+            exclude: vec!["drafts/**".to_string()],
+            delete: true,
+            ..Default::default()
+        };
+        let plan = plan_static_sync(&local, &remote, &remote_dirs, &options);
+
+        assert_eq!(
+            plan.actions,
+            vec![
+                StaticSyncAction::CreateDir("/js".to_string()),
+                StaticSyncAction::Rename {
+                    from: "/app.js".to_string(),
+                    to: "/js/app.js".to_string(),
+                    copy: false
+                },
+                StaticSyncAction::Upload {
+                    path: "/index.html".to_string(),
+                    replace: true
+                },
+                StaticSyncAction::Delete("/old.html".to_string()),
+            ]
+        );
+        assert_eq!(plan.unchanged, 2);
+
+        // Without delete a moved file is copied and the original kept
+        let keep = StaticSyncOptions {
+            delete: false,
+            ..options
+        };
+        let plan = plan_static_sync(&local, &remote, &remote_dirs, &keep);
+        assert!(plan.actions.contains(&StaticSyncAction::Rename {
+            from: "/app.js".to_string(),
+            to: "/js/app.js".to_string(),
+            copy: true
+        }));
+        assert!(!plan.actions.iter().any(|a| matches!(a, StaticSyncAction::Delete(_))));
+        assert!(plan.to_string().contains("> copy /app.js -> /js/app.js"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_local_follows_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let root = std::env::temp_dir().join(format!("safeline-static-sync-{}", std::process::id()));
+        let shared = root.with_extension("shared");
+        let _ = std::fs::remove_dir_all(&root);
+        let _ = std::fs::remove_dir_all(&shared);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&shared).unwrap();
+        std::fs::write(root.join("index.html"), "<h1>hi</h1>").unwrap();
+        std::fs::write(shared.join("site.css"), "body {}").unwrap();
+        symlink(&shared, root.join("css")).unwrap();
+        symlink(root.join("missing"), root.join("broken")).unwrap();
+        symlink(&root, root.join("loop")).unwrap();
+
+        let mut skipped = Vec::new();
+        let files = walk_local(&root, &mut skipped);
+        std::fs::remove_dir_all(&root).unwrap();
+        std::fs::remove_dir_all(&shared).unwrap();
+
+        let files = files.unwrap();
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["/css/site.css", "/index.html"]);
+        let mut skipped: Vec<&str> = skipped.iter().map(|(p, _)| p.as_str()).collect();
+        skipped.sort();
+        assert_eq!(skipped, vec!["/broken", "/loop"]);
+    }
+}
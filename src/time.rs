@@ -0,0 +1,103 @@
+//! Unix time and proleptic Gregorian calendar helpers
+//!
+//! Timestamps are plain `i64` seconds since 1970-01-01T00:00:00Z, as used by
+//! the API.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current Unix time in seconds
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Days since 1970-01-01 of a date
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Year, month and day of a day count since 1970-01-01
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+/// Unix time of a date and an `HH:MM:SS` time of day
+pub(crate) fn unix_time(year: i64, month: i64, day: i64, time: &str) -> Option<i64> {
+    let mut parts = time.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (h, m, s) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(days_from_civil(year, month, day) * 86400 + h * 3600 + m * 60 + s)
+}
+
+/// Parse an RFC 3339 timestamp such as `2024-01-01T08:00:00.5+08:00` into
+/// Unix time; a missing offset is taken as UTC
+pub(crate) fn parse_timestamp(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (date, rest) = value.split_at(value.find(['T', ' '])?);
+    let rest = &rest[1..];
+
+    let mut date = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+    let zone_at = rest.find(['Z', 'z', '+', '-']).unwrap_or(rest.len());
+    let (time, zone) = rest.split_at(zone_at);
+    let time = time.split('.').next()?;
+
+    let offset = match zone.chars().next() {
+        None | Some('Z') | Some('z') => 0,
+        Some(sign) => {
+            let (oh, om) = zone[1..].split_once(':')?;
+            let offset = oh.parse::<i64>().ok()? * 3600 + om.parse::<i64>().ok()? * 60;
+            if sign == '-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+    };
+
+    Some(unix_time(year, month, day, time)? - offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_civil_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2024, 1, 1), 19723);
+        assert_eq!(civil_from_days(19723), (2024, 1, 1));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        for days in [-800_000, -1, 0, 59, 60, 11_016, 19_782, 2_932_896] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m as i64, d as i64), days);
+        }
+        assert_eq!(unix_time(2024, 1, 1, "01:02:03"), Some(1704070923));
+        assert_eq!(unix_time(2024, 1, 1, "01:02"), None);
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        const NOW: i64 = 1704067200; // 2024-01-01T00:00:00Z
+        assert_eq!(parse_timestamp("2024-01-01T00:00:00Z"), Some(NOW));
+        assert_eq!(parse_timestamp("2024-01-01T08:00:00.123+08:00"), Some(NOW));
+        assert_eq!(parse_timestamp("2023-12-31 19:00:00-05:00"), Some(NOW));
+        assert_eq!(parse_timestamp("2024-01-01"), None);
+    }
+}
@@ -0,0 +1,475 @@
+//! TLS hardening profiles
+//!
+//! Named profiles following the Mozilla server side TLS recommendations
+//! translate into the TLS related fields of `ProxyConfig`. A site's
+//! current proxy config can be graded against a profile, and a profile
+//! can be applied to one or all HTTPS websites.
+
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::models::*;
+use std::fmt;
+use tracing::{info, warn};
+
+/// Mozilla server side TLS configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsProfile {
+    /// TLS 1.3 only, for clients from 2019 on
+    Modern,
+    /// TLS 1.2 and 1.3 with AEAD ciphers, the general purpose default
+    Intermediate,
+    /// Everything back to TLS 1.0, for very old clients
+    Old,
+}
+
+const INTERMEDIATE_CIPHERS: &[&str] = &[
+    "ECDHE-ECDSA-AES128-GCM-SHA256",
+    "ECDHE-RSA-AES128-GCM-SHA256",
+    "ECDHE-ECDSA-AES256-GCM-SHA384",
+    "ECDHE-RSA-AES256-GCM-SHA384",
+    "ECDHE-ECDSA-CHACHA20-POLY1305",
+    "ECDHE-RSA-CHACHA20-POLY1305",
+    "DHE-RSA-AES128-GCM-SHA256",
+    "DHE-RSA-AES256-GCM-SHA384",
+    "DHE-RSA-CHACHA20-POLY1305",
+];
+
+const OLD_EXTRA_CIPHERS: &[&str] = &[
+    "ECDHE-ECDSA-AES128-SHA256",
+    "ECDHE-RSA-AES128-SHA256",
+    "ECDHE-ECDSA-AES128-SHA",
+    "ECDHE-RSA-AES128-SHA",
+    "ECDHE-ECDSA-AES256-SHA384",
+    "ECDHE-RSA-AES256-SHA384",
+    "ECDHE-ECDSA-AES256-SHA",
+    "ECDHE-RSA-AES256-SHA",
+    "DHE-RSA-AES128-SHA256",
+    "DHE-RSA-AES256-SHA256",
+    "AES128-GCM-SHA256",
+    "AES256-GCM-SHA384",
+    "AES128-SHA256",
+    "AES256-SHA256",
+    "AES128-SHA",
+    "AES256-SHA",
+    "DES-CBC3-SHA",
+];
+
+/// Protocols considered broken regardless of the profile
+const INSECURE_PROTOCOLS: &[&str] = &["SSLv2", "SSLv3", "TLSv1", "TLSv1.1"];
+
+/// Proxy config values of a TLS profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsSettings {
+    pub ssl_protocols: Vec<String>,
+    /// TLS 1.2 and older ciphers; empty keeps the server defaults. TLS 1.3
+    /// suites are not configured through `ssl_ciphers`.
+    pub ssl_ciphers: Vec<String>,
+    pub force_https: bool,
+    pub hsts: bool,
+    pub hsts_max_age: i32,
+    pub http2: bool,
+    pub http3: bool,
+}
+
+impl TlsProfile {
+    /// Proxy config values of the profile
+    pub fn settings(&self) -> TlsSettings {
+        let (protocols, ciphers): (&[&str], Vec<&str>) = match self {
+            TlsProfile::Modern => (&["TLSv1.3"], Vec::new()),
+            TlsProfile::Intermediate => (&["TLSv1.2", "TLSv1.3"], INTERMEDIATE_CIPHERS.to_vec()),
+            TlsProfile::Old => (
+                &["TLSv1", "TLSv1.1", "TLSv1.2", "TLSv1.3"],
+                INTERMEDIATE_CIPHERS.iter().chain(OLD_EXTRA_CIPHERS).copied().collect(),
+            ),
+        };
+
+        TlsSettings {
+            ssl_protocols: protocols.iter().map(|p| p.to_string()).collect(),
+            ssl_ciphers: ciphers.iter().map(|c| c.to_string()).collect(),
+            force_https: true,
+            hsts: true,
+            hsts_max_age: 63072000,
+            http2: true,
+            http3: *self != TlsProfile::Old,
+        }
+    }
+
+    /// Apply the profile to a proxy config, keeping all other fields
+    ///
+    /// The profile's settings are set on the website itself, overriding
+    /// the global ones.
+    pub fn apply(&self, proxy: &ProxyConfig) -> ProxyConfig {
+        let settings = self.settings();
+        let ssl_ciphers = if settings.ssl_ciphers.is_empty() {
+            proxy.ssl_ciphers.clone()
+        } else {
+            Some(string_item(settings.ssl_ciphers.join(":")))
+        };
+        ProxyConfig {
+            ssl_protocols: Some(string_item(settings.ssl_protocols.join(" "))),
+            ssl_ciphers,
+            force_https: Some(bool_item(settings.force_https)),
+            hsts: Some(bool_item(settings.hsts)),
+            hsts_max_age: Some(string_item(settings.hsts_max_age.to_string())),
+            http2: Some(bool_item(settings.http2)),
+            http3: Some(bool_item(settings.http3)),
+            ..proxy.clone()
+        }
+    }
+}
+
+impl fmt::Display for TlsProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TlsProfile::Modern => "modern",
+            TlsProfile::Intermediate => "intermediate",
+            TlsProfile::Old => "old",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How much a deviation weakens the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Minor,
+    Major,
+    Critical,
+}
+
+/// A proxy config field that differs from the profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deviation {
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+    pub severity: Severity,
+}
+
+/// Result of grading a proxy config against a profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsReport {
+    pub site_id: i32,
+    pub profile: TlsProfile,
+    pub deviations: Vec<Deviation>,
+}
+
+impl TlsReport {
+    /// Grade the proxy config of a website against a profile
+    ///
+    /// Items that follow the global settings are graded on the value in
+    /// `proxy.global` where it carries one, and skipped otherwise.
+    pub fn new(profile: TlsProfile, proxy: &ProxyConfig) -> Self {
+        let global = proxy.global.as_ref();
+        let expected = profile.settings();
+        let mut deviations = Vec::new();
+        let mut deviate = |field, expected: String, actual: String, severity| {
+            deviations.push(Deviation {
+                field,
+                expected,
+                actual,
+                severity,
+            })
+        };
+
+        let protocols = effective_string(&proxy.ssl_protocols, global.and_then(|g| g.ssl_protocols.as_ref()));
+        if let Some(protocols) = protocols.map(split_list) {
+            let extra: Vec<&String> = protocols
+                .iter()
+                .filter(|p| !expected.ssl_protocols.iter().any(|e| e.eq_ignore_ascii_case(p)))
+                .collect();
+            let missing = expected
+                .ssl_protocols
+                .iter()
+                .any(|e| !protocols.iter().any(|p| p.eq_ignore_ascii_case(e)));
+            if !extra.is_empty() || missing {
+                let insecure = extra
+                    .iter()
+                    .any(|p| INSECURE_PROTOCOLS.iter().any(|i| i.eq_ignore_ascii_case(p)));
+                let severity = match (insecure, extra.is_empty()) {
+                    (true, _) => Severity::Critical,
+                    (false, false) => Severity::Major,
+                    (false, true) => Severity::Minor,
+                };
+                deviate(
+                    "ssl_protocols",
+                    expected.ssl_protocols.join(" "),
+                    protocols.join(" "),
+                    severity,
+                );
+            }
+        }
+
+        let ciphers = effective_string(&proxy.ssl_ciphers, global.and_then(|g| g.ssl_ciphers.as_ref()));
+        let ciphers = split_list(ciphers.unwrap_or_default());
+        // An empty or unknown cipher list leaves the server's defaults in
+        // place, as does a profile without TLS 1.2 ciphers
+        if !ciphers.is_empty()
+            && !expected.ssl_ciphers.is_empty()
+            && ciphers.iter().any(|c| !expected.ssl_ciphers.contains(c))
+        {
+            deviate(
+                "ssl_ciphers",
+                expected.ssl_ciphers.join(":"),
+                ciphers.join(":"),
+                Severity::Major,
+            );
+        }
+
+        let mut flag = |field, expected: bool, actual: Option<bool>, severity| {
+            if let Some(actual) = actual.filter(|actual| expected && !actual) {
+                deviate(field, expected.to_string(), actual.to_string(), severity);
+            }
+        };
+        flag("force_https", expected.force_https, site_bool(&proxy.force_https), Severity::Major);
+        flag("hsts", expected.hsts, site_bool(&proxy.hsts), Severity::Major);
+        flag("http2", expected.http2, site_bool(&proxy.http2), Severity::Minor);
+        flag("http3", expected.http3, site_bool(&proxy.http3), Severity::Minor);
+
+        let max_age = effective_string(&proxy.hsts_max_age, None);
+        let short = |max_age: &str| max_age.trim().parse::<i64>().unwrap_or(0) < expected.hsts_max_age as i64;
+        if let (Some(true), Some(max_age)) = (site_bool(&proxy.hsts), max_age.filter(|m| short(m))) {
+            deviate(
+                "hsts_max_age",
+                expected.hsts_max_age.to_string(),
+                max_age.to_string(),
+                Severity::Minor,
+            );
+        }
+
+        Self {
+            site_id: proxy.site_id.unwrap_or_default(),
+            profile,
+            deviations,
+        }
+    }
+
+    /// Letter grade: `A` if compliant, `B` with only minor deviations, `C`
+    /// with major ones and `F` if insecure protocols are enabled
+    pub fn grade(&self) -> char {
+        match self.deviations.iter().map(|d| d.severity).max() {
+            None => 'A',
+            Some(Severity::Minor) => 'B',
+            Some(Severity::Major) => 'C',
+            Some(Severity::Critical) => 'F',
+        }
+    }
+}
+
+impl fmt::Display for TlsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "site {}: grade {} ({} profile)", self.site_id, self.grade(), self.profile)?;
+        for d in &self.deviations {
+            writeln!(
+                f,
+                "  [{:?}] {}: expected {}, got {}",
+                d.severity, d.field, d.expected, d.actual
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Split a space or colon separated list
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split([':', ' ', ','])
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn bool_value(item: &Option<ProxyItemBool>) -> bool {
+    item.as_ref().is_some_and(|i| i.value)
+}
+
+/// Value a string item takes effect with: the global value if the item
+/// follows the global settings, `None` if that is unknown
+fn effective_string<'a>(item: &'a Option<ProxyItemString>, global: Option<&'a ProxyItemString>) -> Option<&'a str> {
+    match item {
+        Some(item) if item.global => global.map(|g| g.value.as_str()),
+        Some(item) => Some(item.value.as_str()),
+        None => Some(""),
+    }
+}
+
+/// Site-level value of a flag, `None` if it follows the global settings
+fn site_bool(item: &Option<ProxyItemBool>) -> Option<bool> {
+    match item {
+        Some(item) if item.global => None,
+        item => Some(bool_value(item)),
+    }
+}
+
+fn string_item(value: String) -> ProxyItemString {
+    ProxyItemString { global: false, value }
+}
+
+fn bool_item(value: bool) -> ProxyItemBool {
+    ProxyItemBool { global: false, value }
+}
+
+/// Whether a website listens on an SSL port
+pub fn is_https(site: &WebsiteItem) -> bool {
+    site.ports.iter().any(|p| p.ends_with("_ssl"))
+}
+
+/// Result of applying a profile to every HTTPS website
+#[derive(Debug, Default)]
+pub struct TlsBulkReport {
+    pub applied: Vec<i32>,
+    /// Websites that already matched the profile
+    pub unchanged: Vec<i32>,
+    pub failed: Vec<(i32, Error)>,
+}
+
+impl Client {
+    /// Check website TLS settings
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `profile` - Profile to grade against
+    ///
+    /// # Returns
+    ///
+    /// Returns the grade and the deviations from the profile
+    pub async fn check_tls(&self, id: i32, profile: TlsProfile) -> Result<TlsReport> {
+        let proxy = self.get_website_proxy(id).await?;
+        Ok(TlsReport {
+            site_id: id,
+            ..TlsReport::new(profile, &proxy)
+        })
+    }
+
+    /// Apply TLS profile
+    ///
+    /// Updates the TLS fields of the website's proxy config, keeping all
+    /// other proxy settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Website ID
+    /// * `profile` - Profile to apply
+    ///
+    /// # Returns
+    ///
+    /// Returns the updated proxy config
+    pub async fn apply_tls_profile(&self, id: i32, profile: TlsProfile) -> Result<ProxyConfig> {
+        let proxy = self.get_website_proxy(id).await?;
+        self.put_website_proxy(id, &profile.apply(&proxy)).await
+    }
+
+    /// Apply TLS profile to all HTTPS websites
+    ///
+    /// Websites that already match the profile are left untouched. A
+    /// failure on one website does not stop the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `profile` - Profile to apply
+    ///
+    /// # Returns
+    ///
+    /// Returns which websites were updated, unchanged or failed
+    pub async fn apply_tls_profile_all(&self, profile: TlsProfile) -> Result<TlsBulkReport> {
+        let mut report = TlsBulkReport::default();
+
+        for site in self.list_websites().await?.data.iter().filter(|s| is_https(s)) {
+            let result = async {
+                let proxy = self.get_website_proxy(site.id).await?;
+                if TlsReport::new(profile, &proxy).deviations.is_empty() {
+                    return Ok(false);
+                }
+                self.put_website_proxy(site.id, &profile.apply(&proxy)).await?;
+                Ok(true)
+            }
+            .await;
+
+            match result {
+                Ok(true) => {
+                    info!("applied {} TLS profile to website {}", profile, site.id);
+                    report.applied.push(site.id);
+                }
+                Ok(false) => report.unchanged.push(site.id),
+                Err(e) => {
+                    warn!("failed to apply {} TLS profile to website {}: {}", profile, site.id, e);
+                    report.failed.push((site.id, e));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy() -> ProxyConfig {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "site_id": 7,
+            "host": { "global": false, "value": "$host" },
+            "http_1.0": { "global": false, "value": false },
+            "http2": { "global": false, "value": true },
+            "http3": { "global": true, "value": false },
+            "gzip": { "global": false, "value": true },
+            "hsts": { "global": false, "value": false },
+            "hsts_max_age": { "global": false, "value": "" },
+            "ssl_protocols": { "global": false, "value": "TLSv1 TLSv1.1 TLSv1.2" },
+            "ssl_ciphers": { "global": true, "value": "" },
+            "http_headers": { "global": false, "value": [] },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_grade_and_apply() {
+        let report = TlsReport::new(TlsProfile::Intermediate, &proxy());
+        assert_eq!(report.grade(), 'F');
+        let fields: Vec<&str> = report.deviations.iter().map(|d| d.field).collect();
+        // http3 follows the global settings, which carry no value for it
+        assert_eq!(fields, vec!["ssl_protocols", "force_https", "hsts"]);
+
+        let mut global = proxy();
+        global.ssl_protocols = Some(ProxyItemString {
+            global: true,
+            value: "TLSv1 TLSv1.1 TLSv1.2".to_string(),
+        });
+        let fields = |proxy: &ProxyConfig| -> Vec<&'static str> {
+            TlsReport::new(TlsProfile::Intermediate, proxy).deviations.iter().map(|d| d.field).collect()
+        };
+        assert_eq!(fields(&global), vec!["force_https", "hsts"]);
+        global.global = Some(GlobalProxyConfig {
+            ip_source: None,
+            ipv6: None,
+            ssl_protocols: Some(string_item("TLSv1.2 TLSv1.3".to_string())),
+            ssl_ciphers: Some(string_item("RC4-SHA".to_string())),
+        });
+        assert_eq!(fields(&global), vec!["ssl_ciphers", "force_https", "hsts"]);
+        global.global.as_mut().unwrap().ssl_protocols = Some(string_item("TLSv1.1 TLSv1.2".to_string()));
+        assert_eq!(fields(&global), vec!["ssl_protocols", "ssl_ciphers", "force_https", "hsts"]);
+
+        let applied = TlsProfile::Intermediate.apply(&proxy());
+        assert!(bool_value(&applied.gzip));
+        assert_eq!(effective_string(&applied.ssl_protocols, None), Some("TLSv1.2 TLSv1.3"));
+        assert_eq!(effective_string(&applied.hsts_max_age, None), Some("63072000"));
+        assert!(!applied.http3.as_ref().unwrap().global);
+        assert_eq!(TlsReport::new(TlsProfile::Intermediate, &applied).grade(), 'A');
+
+        let json = serde_json::to_value(&applied).unwrap();
+        assert_eq!(json["http_1.0"]["value"], false);
+        assert!(json["ssl_ciphers"]["value"].as_str().unwrap().starts_with("ECDHE-ECDSA-AES128-GCM-SHA256:"));
+
+        // TLS 1.3 suites are not put into ssl_ciphers
+        let modern_applied = TlsProfile::Modern.apply(&proxy());
+        assert_eq!(modern_applied.ssl_ciphers, proxy().ssl_ciphers);
+        assert_eq!(TlsReport::new(TlsProfile::Modern, &modern_applied).grade(), 'A');
+
+        let modern = TlsReport::new(TlsProfile::Modern, &applied);
+        assert_eq!(modern.grade(), 'C');
+        assert!(TlsReport::new(TlsProfile::Old, &applied).deviations[0].severity == Severity::Minor);
+    }
+}
@@ -128,6 +128,31 @@ async fn test_get_acl_logs_with_ip_filter() {
     }
 }
 
+#[tokio::test]
+async fn test_all_acl_logs() {
+    // Load configuration from local.env
+    let url = std::env::var("SAFELINE_URL")
+        .unwrap_or_else(|_| "https://0.0.0.0:9443/api".to_string());
+    let token = std::env::var("SAFELINE_TOKEN")
+        .unwrap_or_else(|_| "zHtRjyNvVDpI6mrYhZdhEbPOVBWkdFXG".to_string());
+
+    let client = Client::new(&url, &token);
+
+    // Test paging through all ACL logs
+    println!("Testing all_acl_logs...");
+
+    match client.all_acl_logs().await {
+        Ok(logs) => {
+            println!("✓ all_acl_logs succeeded");
+            println!("  Number of logs returned: {}", logs.len());
+        }
+        Err(e) => {
+            eprintln!("✗ all_acl_logs failed: {:?}", e);
+            panic!("All ACL logs test failed");
+        }
+    }
+}
+
 #[tokio::test]
 async fn test_acl_relieve() {
     // Load configuration from local.env
@@ -152,7 +177,7 @@ async fn test_acl_relieve() {
         Ok(logs_response) => {
             if let Some(first_log) = logs_response.data.first() {
                 let relieve_request = safeline_rs::ACLRelieveRequest {
-                    id: first_log.id,
+                    id: vec![first_log.id],
                     search: None,
                 };
 
@@ -232,8 +257,8 @@ async fn test_website_acl() {
                     id: 0, // New rule
                     site_id,
                     name: "Test ACL Rule".to_string(),
-                    r#type: Some(1),
-                    action: Some(1),
+                    r#type: Some("req".to_string()),
+                    action: Some("ban".to_string()),
                     enabled: Some(true),
                     use_global: Some(false),
                     built_in: Some(false),
@@ -1,4 +1,3 @@
-use reqwest::get;
 use safeline_rs::{IPGroupAppendRequest, IPGroupCreateRequest, IPGroupListRequest};
 use serde_json::json;
 use tracing::info;
@@ -33,7 +32,7 @@ async fn test_get_ipgroup() {
 #[tokio::test]
 async fn test_add_ip_to_ipgroup() {
     let cli = get_client();
-    let res = cli
+    let _res = cli
         .ip_group_append(&IPGroupAppendRequest {
             ip_group_ids: vec![1444],
             ips: vec!["0.0.0.0".to_string()],
@@ -771,11 +771,9 @@ async fn test_sort_website_groups() {
                 let first_group_id = groups_response.data[0].id;
                 let second_group_id = groups_response.data[1].id;
 
-                println!("  Sorting groups with IDs: {} and {}", first_group_id, second_group_id);
+                println!("  Moving group {} after group {}", first_group_id, second_group_id);
 
-                let sort_req = safeline_rs::SortGroupRequest {
-                    position: vec![second_group_id, first_group_id],
-                };
+                let sort_req = safeline_rs::SortGroupRequest { position: 2 };
 
                 match client.sort_website_groups(first_group_id, &sort_req).await {
                     Ok(_) => {
@@ -815,11 +813,9 @@ async fn test_sort_websites() {
                 let first_site_id = list_response.data[0].id;
                 let second_site_id = list_response.data[1].id;
 
-                println!("  Sorting websites with IDs: {} and {}", first_site_id, second_site_id);
+                println!("  Moving website {} after website {}", first_site_id, second_site_id);
 
-                let sort_req = safeline_rs::SortWebsiteRequest {
-                    position: vec![second_site_id, first_site_id],
-                };
+                let sort_req = safeline_rs::SortWebsiteRequest { position: 2 };
 
                 match client.sort_websites(first_site_id, &sort_req).await {
                     Ok(_) => {
@@ -1005,4 +1001,58 @@ async fn test_update_site_group() {
             panic!("Update site group test failed - could not get websites");
         }
     }
-}
\ No newline at end of file
+}
+
+/// Test cloning a website with its sub-configuration
+#[tokio::test]
+async fn test_clone_website() {
+    let url = std::env::var("SAFELINE_URL")
+        .unwrap_or_else(|_| "https://0.0.0.0:9443/api".to_string());
+    let token = std::env::var("SAFELINE_TOKEN")
+        .unwrap_or_else(|_| "zHtRjyNvVDpI6mrYhZdhEbPOVBWkdFXG".to_string());
+
+    let client = safeline_rs::Client::new(&url, &token);
+
+    println!("Testing clone_website...");
+
+    match client.list_websites().await {
+        Ok(list_response) => {
+            if let Some(first_site) = list_response.data.first() {
+                let site_id = first_site.id;
+                println!("  Cloning website ID: {}", site_id);
+
+                match client
+                    .clone_website(
+                        site_id,
+                        vec!["clone.example.com".to_string()],
+                        vec!["http://127.0.0.1:8081".to_string()],
+                    )
+                    .await
+                {
+                    Ok(report) => {
+                        println!("✓ clone_website succeeded");
+                        println!("  New website ID: {}", report.id);
+                        for (step, status) in &report.steps {
+                            println!("    {}: {:?}", step, status);
+                        }
+
+                        let delete_req = safeline_rs::DeleteWebsiteRequest { ids: vec![report.id] };
+                        if let Err(e) = client.delete_websites(&delete_req).await {
+                            eprintln!("  Failed to clean up cloned website: {:?}", e);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("✗ clone_website failed: {:?}", e);
+                        panic!("Clone website test failed");
+                    }
+                }
+            } else {
+                println!("  No websites found to test clone_website");
+            }
+        }
+        Err(e) => {
+            eprintln!("✗ Failed to get websites: {:?}", e);
+            panic!("Clone website test failed - could not get websites");
+        }
+    }
+}